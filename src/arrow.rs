@@ -0,0 +1,366 @@
+//! Conversion of responses into Arrow `RecordBatch`es and Parquet files, behind the `arrow`
+//! feature
+//!
+//! Each batch starts with a `captured_at` column, the UTC time that the response was fetched, so
+//! that snapshots taken every few minutes can be appended to the same dataset. Numbers keep their
+//! types, enums are written as the strings the API uses and nested coordinates are flattened, eg
+//! into `start_lat` and `end_long` for speed bands. Highway directions are written as the numbers
+//! the API uses. Coordinates that Datamall did not return and directions it does not document are
+//! null.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::arrow::ParquetWriter;
+//! use lta::models::chrono::Utc;
+//! use lta::{Client, LTAClient, LTAResult, Traffic, TrafficRequests};
+//! use std::fs::File;
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let file = File::create("speed_bands.parquet").expect("Unable to create file");
+//!     let mut parquet = ParquetWriter::try_new(file)?;
+//!
+//!     for _ in 0..3 {
+//!         let bands = Traffic::get_all_traffic_speed_band(&client).await?;
+//!         parquet.write(&bands, Utc::now())?;
+//!         tokio::time::sleep(std::time::Duration::from_secs(300)).await;
+//!     }
+//!     parquet.close()?;
+//!     Ok(())
+//! }
+//! ```
+use crate::export::cell;
+use crate::models::chrono::{DateTime, Utc};
+use crate::models::traffic::prelude::*;
+use crate::models::utils::Coordinates;
+use crate::{LTAError, LTAResult};
+use arrow_array::{
+    ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray, UInt32Array,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// Name of the column holding the time that records were captured
+pub const CAPTURED_AT: &str = "captured_at";
+
+/// Model that can be converted into Arrow columns
+pub trait ArrowRecord: Sized {
+    /// Fields of the model, without [`CAPTURED_AT`]
+    fn fields() -> Vec<Field>;
+
+    /// One array per field, in the order of [`ArrowRecord::fields`]
+    fn columns(records: &[Self]) -> Vec<ArrayRef>;
+}
+
+/// Returns the schema of the batches of `T`, starting with [`CAPTURED_AT`]
+pub fn schema<T: ArrowRecord>() -> SchemaRef {
+    let captured_at = Field::new(
+        CAPTURED_AT,
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        false,
+    );
+    let fields: Vec<Field> = std::iter::once(captured_at).chain(T::fields()).collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// Converts `records` into a batch, with every row captured at `captured_at`
+pub fn to_record_batch<T: ArrowRecord>(
+    records: &[T],
+    captured_at: DateTime<Utc>,
+) -> LTAResult<RecordBatch> {
+    let timestamps = vec![captured_at.timestamp_micros(); records.len()];
+    let captured_at = TimestampMicrosecondArray::from(timestamps).with_timezone("UTC");
+    let columns: Vec<ArrayRef> = std::iter::once(Arc::new(captured_at) as ArrayRef)
+        .chain(T::columns(records))
+        .collect();
+
+    RecordBatch::try_new(schema::<T>(), columns).map_err(arrow_error)
+}
+
+/// Writes batches of `T` to a Parquet file, one row group per call to [`ParquetWriter::write`]
+pub struct ParquetWriter<T, W: Write + Send> {
+    writer: ArrowWriter<W>,
+    model: PhantomData<T>,
+}
+
+impl<T: ArrowRecord, W: Write + Send> ParquetWriter<T, W> {
+    pub fn try_new(writer: W) -> LTAResult<Self> {
+        Self::with_properties(writer, WriterProperties::default())
+    }
+
+    /// Uses `props` for compression, row group sizes and the other Parquet settings
+    pub fn with_properties(writer: W, props: WriterProperties) -> LTAResult<Self> {
+        let writer =
+            ArrowWriter::try_new(writer, schema::<T>(), Some(props)).map_err(parquet_error)?;
+        Ok(ParquetWriter {
+            writer,
+            model: PhantomData,
+        })
+    }
+
+    pub fn write(&mut self, records: &[T], captured_at: DateTime<Utc>) -> LTAResult<()> {
+        let batch = to_record_batch(records, captured_at)?;
+        self.writer.write(&batch).map_err(parquet_error)?;
+        self.writer.flush().map_err(parquet_error)
+    }
+
+    /// Writes the footer and returns the underlying writer. Files are unreadable without it
+    pub fn close(self) -> LTAResult<W> {
+        self.writer.into_inner().map_err(parquet_error)
+    }
+}
+
+/// Writes `records` to `writer` as a Parquet file
+pub fn write_parquet<T, W>(records: &[T], captured_at: DateTime<Utc>, writer: W) -> LTAResult<W>
+where
+    T: ArrowRecord,
+    W: Write + Send,
+{
+    let mut parquet = ParquetWriter::try_new(writer)?;
+    parquet.write(records, captured_at)?;
+    parquet.close()
+}
+
+fn arrow_error(e: arrow_schema::ArrowError) -> LTAError {
+    LTAError::Custom(format!("Unable to build record batch: {}", e))
+}
+
+fn parquet_error(e: parquet::errors::ParquetError) -> LTAError {
+    LTAError::Custom(format!("Unable to write Parquet: {}", e))
+}
+
+fn utf8(name: &str) -> Field {
+    Field::new(name, DataType::Utf8, false)
+}
+
+fn float64(name: &str, nullable: bool) -> Field {
+    Field::new(name, DataType::Float64, nullable)
+}
+
+fn uint32(name: &str) -> Field {
+    Field::new(name, DataType::UInt32, false)
+}
+
+fn strings<T, F: Fn(&T) -> String>(records: &[T], f: F) -> ArrayRef {
+    Arc::new(StringArray::from(records.iter().map(f).collect::<Vec<_>>()))
+}
+
+fn uint32s<T, F: Fn(&T) -> u32>(records: &[T], f: F) -> ArrayRef {
+    Arc::new(UInt32Array::from(records.iter().map(f).collect::<Vec<_>>()))
+}
+
+fn float64s<T, F: Fn(&T) -> Option<f64>>(records: &[T], f: F) -> ArrayRef {
+    Arc::new(Float64Array::from(
+        records.iter().map(f).collect::<Vec<_>>(),
+    ))
+}
+
+impl ArrowRecord for TrafficSpeedBand {
+    fn fields() -> Vec<Field> {
+        vec![
+            Field::new("link_id", DataType::UInt64, false),
+            utf8("road_name"),
+            utf8("road_category"),
+            uint32("speed_band"),
+            uint32("min_speed"),
+            uint32("max_speed"),
+            float64("start_lat", true),
+            float64("start_long", true),
+            float64("end_lat", true),
+            float64("end_long", true),
+        ]
+    }
+
+    fn columns(records: &[Self]) -> Vec<ArrayRef> {
+        let link_ids = records.iter().map(|r| r.link_id).collect::<Vec<_>>();
+        vec![
+            Arc::new(UInt64Array::from(link_ids)),
+            strings(records, |r| r.road_name.clone()),
+            strings(records, |r| cell(&r.road_category)),
+            uint32s(records, |r| r.speed_band),
+            uint32s(records, |r| r.min_speed),
+            uint32s(records, |r| r.max_speed),
+            float64s(records, |r| r.coord_start_end.as_ref().map(|l| l.start.lat)),
+            float64s(records, |r| {
+                r.coord_start_end.as_ref().map(|l| l.start.long)
+            }),
+            float64s(records, |r| r.coord_start_end.as_ref().map(|l| l.end.lat)),
+            float64s(records, |r| r.coord_start_end.as_ref().map(|l| l.end.long)),
+        ]
+    }
+}
+
+impl ArrowRecord for CarPark {
+    fn fields() -> Vec<Field> {
+        vec![
+            utf8("carpark_id"),
+            utf8("area"),
+            utf8("dev"),
+            float64("lat", true),
+            float64("long", true),
+            uint32("avail_lots"),
+            utf8("lot_type"),
+            utf8("agency"),
+        ]
+    }
+
+    fn columns(records: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            strings(records, |r| r.carpark_id.clone()),
+            strings(records, |r| r.area.clone()),
+            strings(records, |r| r.dev.clone()),
+            float64s(records, |r| r.coords.as_ref().map(|c| c.lat)),
+            float64s(records, |r| r.coords.as_ref().map(|c| c.long)),
+            uint32s(records, |r| r.avail_lots),
+            strings(records, |r| cell(&r.lot_type)),
+            strings(records, |r| cell(&r.agency)),
+        ]
+    }
+}
+
+impl ArrowRecord for EstTravelTime {
+    fn fields() -> Vec<Field> {
+        vec![
+            utf8("name"),
+            Field::new("direction", DataType::UInt32, true),
+            utf8("far_end_pt"),
+            utf8("start_pt"),
+            utf8("end_pt"),
+            uint32("est_travel_time"),
+        ]
+    }
+
+    fn columns(records: &[Self]) -> Vec<ArrayRef> {
+        let directions = records
+            .iter()
+            .map(|r| match r.direction {
+                HighwayDirection::EastToWest => Some(1),
+                HighwayDirection::WestToEast => Some(2),
+                HighwayDirection::Unknown => None,
+            })
+            .collect::<Vec<_>>();
+        vec![
+            strings(records, |r| cell(&r.name)),
+            Arc::new(UInt32Array::from(directions)),
+            strings(records, |r| r.far_end_pt.clone()),
+            strings(records, |r| r.start_pt.clone()),
+            strings(records, |r| r.end_pt.clone()),
+            uint32s(records, |r| r.est_travel_time),
+        ]
+    }
+}
+
+/// Taxi availability
+impl ArrowRecord for Coordinates {
+    fn fields() -> Vec<Field> {
+        vec![float64("lat", false), float64("long", false)]
+    }
+
+    fn columns(records: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            float64s(records, |r| Some(r.lat)),
+            float64s(records, |r| Some(r.long)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::chrono::{Duration, TimeZone};
+    use crate::models::utils::Location;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn speed_bands() -> Vec<TrafficSpeedBand> {
+        let band = TrafficSpeedBand {
+            link_id: 103000000,
+            road_name: "KENT ROAD".to_string(),
+            road_category: RoadCategory::SlipRoads,
+            speed_band: 2,
+            min_speed: 10,
+            max_speed: 19,
+            coord_start_end: Some(Location::new(1.31, 103.85, 1.32, 103.86)),
+        };
+        let no_location = TrafficSpeedBand {
+            link_id: 103000010,
+            coord_start_end: None,
+            ..band.clone()
+        };
+        vec![band, no_location]
+    }
+
+    #[test]
+    fn builds_typed_columns() {
+        let captured_at = Utc.with_ymd_and_hms(2020, 12, 1, 8, 0, 0).unwrap();
+        let batch = to_record_batch(&speed_bands(), captured_at).unwrap();
+
+        assert_eq!(batch.num_rows(), 2);
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).name(), CAPTURED_AT);
+        assert_eq!(schema.field(1).data_type(), &DataType::UInt64);
+        assert_eq!(schema.field(4).data_type(), &DataType::UInt32);
+
+        let start_lat = batch.column_by_name("start_lat").unwrap();
+        let start_lat = start_lat.as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(start_lat.value(0), 1.31);
+        assert!(start_lat.is_null(1));
+
+        let captured = batch.column(0);
+        let captured = captured
+            .as_any()
+            .downcast_ref::<TimestampMicrosecondArray>()
+            .unwrap();
+        assert_eq!(captured.value(1), captured_at.timestamp_micros());
+    }
+
+    #[test]
+    fn unknown_directions_are_null() {
+        let known = EstTravelTime {
+            name: Highway::AYE,
+            direction: HighwayDirection::WestToEast,
+            far_end_pt: "TUAS CHECKPOINT".to_string(),
+            start_pt: "AYE/MCE INTERCHANGE".to_string(),
+            end_pt: "TELOK BLANGAH RD".to_string(),
+            est_travel_time: 2,
+        };
+        let unknown = EstTravelTime {
+            direction: HighwayDirection::Unknown,
+            ..known.clone()
+        };
+        let captured_at = Utc.with_ymd_and_hms(2020, 12, 1, 8, 0, 0).unwrap();
+        let batch = to_record_batch(&[known, unknown], captured_at).unwrap();
+
+        let direction = batch.column_by_name("direction").unwrap();
+        let direction = direction.as_any().downcast_ref::<UInt32Array>().unwrap();
+        assert_eq!(direction.value(0), 2);
+        assert!(direction.is_null(1));
+    }
+
+    #[test]
+    fn appends_snapshots_to_parquet() {
+        let first = Utc.with_ymd_and_hms(2020, 12, 1, 8, 0, 0).unwrap();
+        let mut parquet = ParquetWriter::try_new(Vec::new()).unwrap();
+        parquet.write(&speed_bands(), first).unwrap();
+        parquet
+            .write(&speed_bands(), first + Duration::minutes(5))
+            .unwrap();
+        let file = parquet.close().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes::Bytes::from(file))
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(RecordBatch::num_rows).sum();
+        assert_eq!(rows, 4);
+        assert_eq!(batches[0].schema(), schema::<TrafficSpeedBand>());
+    }
+}
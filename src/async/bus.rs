@@ -1,9 +1,78 @@
+use crate::disk_cache::DiskCache;
 use crate::models::bus::prelude::*;
 use crate::r#async::build_req_with_query;
 use crate::r#async::build_req_with_skip;
 use crate::r#async::client::LTAClient;
+use crate::r#async::{fetch_all_pages, fetch_all_pages_disk_cached};
+use crate::timetable::RawBusRouteResp;
 use crate::{Bus, Client, LTAResult};
 use async_trait::async_trait;
+use futures::StreamExt;
+use std::collections::HashMap;
+
+/// Bus stop of a batch of arrival requests, eg `BusRequests::get_arrivals`
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ArrivalQuery {
+    pub bus_stop_code: u32,
+
+    /// Services whose arrivals are returned, every service at the stop if empty
+    pub services: Vec<String>,
+}
+
+impl ArrivalQuery {
+    pub fn new(bus_stop_code: u32) -> Self {
+        ArrivalQuery {
+            bus_stop_code,
+            services: Vec::new(),
+        }
+    }
+
+    pub fn service<S: Into<String>>(mut self, service_no: S) -> Self {
+        self.services.push(service_no.into());
+        self
+    }
+
+    /// Returns the service to pass to `get_arrival`, when only one is requested
+    pub(crate) fn service_no(&self) -> Option<&str> {
+        match self.services.as_slice() {
+            [service_no] => Some(service_no),
+            _ => None,
+        }
+    }
+
+    /// Removes the services that were not requested from `resp`
+    pub(crate) fn filter(&self, mut resp: BusArrivalResp) -> BusArrivalResp {
+        if !self.services.is_empty() {
+            resp.services
+                .retain(|s| self.services.contains(&s.service_no));
+        }
+        resp
+    }
+
+    /// Combines queries of the same stop, so that each stop is requested once. A query without
+    /// services overrides the services of the others
+    pub(crate) fn merge(queries: Vec<ArrivalQuery>) -> Vec<ArrivalQuery> {
+        let mut merged: Vec<ArrivalQuery> = Vec::with_capacity(queries.len());
+        for query in queries {
+            match merged
+                .iter_mut()
+                .find(|q| q.bus_stop_code == query.bus_stop_code)
+            {
+                Some(q) if q.services.is_empty() || query.services.is_empty() => q.services.clear(),
+                Some(q) => q.services.extend(query.services),
+                None => merged.push(query),
+            }
+        }
+
+        merged
+    }
+}
+
+impl From<u32> for ArrivalQuery {
+    fn from(bus_stop_code: u32) -> Self {
+        ArrivalQuery::new(bus_stop_code)
+    }
+}
 
 /// All API pertaining to buses
 #[async_trait]
@@ -43,6 +112,88 @@ pub trait BusRequests<C: Client> {
     ///
     /// **Update freq**: Ad-Hoc
     async fn get_bus_stops(client: &C, skip: Option<u32>) -> LTAResult<Vec<BusStop>>;
+
+    /// Returns the arrivals at every stop of `queries`, keyed by bus stop code. Up to
+    /// `concurrency` stops are requested at a time, each through the client's rate limiter and
+    /// retry policy. A stop that cannot be requested has its error in the map, without failing
+    /// the other stops. Queries of the same stop are combined into one request
+    async fn get_arrivals(
+        client: &C,
+        queries: Vec<ArrivalQuery>,
+        concurrency: usize,
+    ) -> HashMap<u32, LTAResult<BusArrivalResp>>
+    where
+        C: Sync,
+    {
+        let requests = ArrivalQuery::merge(queries)
+            .into_iter()
+            .map(|query| async move {
+                let resp = Self::get_arrival(client, query.bus_stop_code, query.service_no()).await;
+                (query.bus_stop_code, resp.map(|resp| query.filter(resp)))
+            });
+
+        futures::stream::iter(requests)
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// Same as [`get_bus_services`](BusRequests::get_bus_services), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_bus_services(client: &C) -> LTAResult<Vec<BusService>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_bus_services(client, skip)).await
+    }
+
+    /// Same as [`get_bus_routes`](BusRequests::get_bus_routes), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_bus_routes(client: &C) -> LTAResult<Vec<BusRoute>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_bus_routes(client, skip)).await
+    }
+
+    /// Same as [`get_bus_stops`](BusRequests::get_bus_stops), but keeps requesting with increasing
+    /// `skip` until every record is returned
+    async fn get_all_bus_stops(client: &C) -> LTAResult<Vec<BusStop>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_bus_stops(client, skip)).await
+    }
+
+    /// Same as [`get_all_bus_services`](BusRequests::get_all_bus_services), but reads the records
+    /// from `cache` while they are fresh, and stores them in it after fetching them otherwise
+    async fn get_all_bus_services_cached(
+        client: &C,
+        cache: &DiskCache,
+    ) -> LTAResult<Vec<BusService>>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        fetch_all_pages_disk_cached::<BusServiceResp, _, _>(client, "/BusServices", cache).await
+    }
+
+    /// Same as [`get_all_bus_routes`](BusRequests::get_all_bus_routes), but reads the records
+    /// from `cache` while they are fresh, and stores them in it after fetching them otherwise
+    async fn get_all_bus_routes_cached(client: &C, cache: &DiskCache) -> LTAResult<Vec<BusRoute>>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        fetch_all_pages_disk_cached::<RawBusRouteResp, _, _>(client, "/BusRoutes", cache).await
+    }
+
+    /// Same as [`get_all_bus_stops`](BusRequests::get_all_bus_stops), but reads the records
+    /// from `cache` while they are fresh, and stores them in it after fetching them otherwise
+    async fn get_all_bus_stops_cached(client: &C, cache: &DiskCache) -> LTAResult<Vec<BusStop>>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        fetch_all_pages_disk_cached::<BusStopsResp, _, _>(client, "/BusStops", cache).await
+    }
 }
 
 #[async_trait]
@@ -52,10 +203,10 @@ impl BusRequests<LTAClient> for Bus {
         bus_stop_code: u32,
         service_no: Option<&str>,
     ) -> LTAResult<BusArrivalResp> {
-        let url = api_url!("/BusArrivalv2");
+        let path = "/BusArrivalv2";
         match service_no {
             Some(srv_no) => {
-                build_req_with_query::<RawBusArrivalResp, _, _, _>(client, url, |rb| {
+                build_req_with_query::<RawBusArrivalResp, _, _, _>(client, path, |rb| {
                     rb.query(&[
                         ("BusStopCode", bus_stop_code.to_string().as_str()),
                         ("ServiceNo", srv_no),
@@ -64,7 +215,7 @@ impl BusRequests<LTAClient> for Bus {
                 .await
             }
             None => {
-                build_req_with_query::<RawBusArrivalResp, _, _, _>(client, url, |rb| {
+                build_req_with_query::<RawBusArrivalResp, _, _, _>(client, path, |rb| {
                     rb.query(&[("BusStopCode", bus_stop_code.to_string())])
                 })
                 .await
@@ -73,14 +224,14 @@ impl BusRequests<LTAClient> for Bus {
     }
 
     async fn get_bus_services(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusService>> {
-        build_req_with_skip::<BusServiceResp, _, _>(client, api_url!("/BusServices"), skip).await
+        build_req_with_skip::<BusServiceResp, _, _>(client, "/BusServices", skip).await
     }
 
     async fn get_bus_routes(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusRoute>> {
-        build_req_with_skip::<BusRouteResp, _, _>(client, api_url!("/BusRoutes"), skip).await
+        build_req_with_skip::<RawBusRouteResp, _, _>(client, "/BusRoutes", skip).await
     }
 
     async fn get_bus_stops(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusStop>> {
-        build_req_with_skip::<BusStopsResp, _, _>(client, api_url!("/BusStops"), skip).await
+        build_req_with_skip::<BusStopsResp, _, _>(client, "/BusStops", skip).await
     }
 }
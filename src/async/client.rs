@@ -1,5 +1,9 @@
 //! Client for interacting with LTA API
-use crate::{Client, LTAError, LTAResult};
+use crate::cache::ResponseCache;
+use crate::cassette::Cassette;
+use crate::rate_limit::RateLimiter;
+use crate::retry::RetryPolicy;
+use crate::{Client, LTAError, LTAResult, DEFAULT_BASE_URL};
 
 /// A `Client` to make requests with
 /// The `Client` holds a connection pool internally, so it is advised that you create one and reuse it
@@ -30,6 +34,11 @@ use crate::{Client, LTAError, LTAResult};
 #[derive(Debug, Clone)]
 pub struct LTAClient {
     api_key: String,
+    base_url: String,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    cache: Option<ResponseCache>,
+    cassette: Option<Cassette>,
     client: reqwest::Client,
 }
 
@@ -39,7 +48,16 @@ impl Client for LTAClient {
 
     fn new<S: Into<String>>(api_key: S, client: Self::InternalClient) -> LTAClient {
         let api_key = api_key.into();
-        LTAClient { api_key, client }
+        let base_url = DEFAULT_BASE_URL.to_string();
+        LTAClient {
+            api_key,
+            base_url,
+            retry_policy: RetryPolicy::none(),
+            rate_limiter: None,
+            cache: None,
+            cassette: None,
+            client,
+        }
     }
 
     fn with_api_key<S: Into<String>>(api_key: S) -> LTAResult<Self> {
@@ -48,8 +66,17 @@ impl Client for LTAClient {
         if api_key.is_empty() {
             return Err(LTAError::InvalidAPIKey);
         }
+        let base_url = DEFAULT_BASE_URL.to_string();
         let client = reqwest::Client::new();
-        Ok(LTAClient { api_key, client })
+        Ok(LTAClient {
+            api_key,
+            base_url,
+            retry_policy: RetryPolicy::none(),
+            rate_limiter: None,
+            cache: None,
+            cassette: None,
+            client,
+        })
     }
 
     fn req_builder(&self, url: &str) -> Self::RB {
@@ -57,4 +84,65 @@ impl Client for LTAClient {
             .get(url)
             .header("AccountKey", self.api_key.as_str())
     }
+
+    /// Links are pre-signed, so they are requested without the `AccountKey` header
+    fn link_req_builder(&self, url: &str) -> Self::RB {
+        self.client.get(url)
+    }
+
+    fn base_url(&self) -> &str {
+        self.base_url.as_str()
+    }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.rate_limiter.as_ref()
+    }
+
+    fn cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_ref()
+    }
+
+    fn cassette(&self) -> Option<&Cassette> {
+        self.cassette.as_ref()
+    }
+}
+
+impl LTAClient {
+    /// Sends every request to `base_url` instead of [`DEFAULT_BASE_URL`], eg a mock server,
+    /// a caching proxy or the HTTPS endpoint
+    pub fn with_base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Retries failed requests according to `retry_policy`. By default, requests are not retried
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Delays requests to stay under the limits of `rate_limiter`. The limiter is shared by every
+    /// clone of the client
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Serves repeated requests from `cache` while the cached response is fresh. The cache is
+    /// shared by every clone of the client
+    pub fn with_cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Records every request and its response to `cassette`, or replays them from it without
+    /// network, depending on its mode
+    pub fn with_cassette(mut self, cassette: Cassette) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
 }
@@ -1,9 +1,10 @@
 use crate::models::chrono::NaiveDate;
 use crate::models::crowd::passenger_vol;
 use crate::models::crowd::prelude::*;
+use crate::passenger_vol::PassengerVolRecords;
 use crate::r#async::client::LTAClient;
-use crate::r#async::{build_req_with_query, build_req_with_skip};
-use crate::{vol_type_to_url, Client, Crowd, LTAResult};
+use crate::r#async::{build_req_with_query, build_req_with_skip, download_link};
+use crate::{vol_type_to_path, Client, Crowd, LTAError, LTAResult};
 use async_trait::async_trait;
 
 /// All APIs pertaining to transportation crowd\
@@ -20,6 +21,27 @@ pub trait CrowdRequests<C: Client> {
         date: Option<NaiveDate>,
         skip: Option<u32>,
     ) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_passenger_vol_by`](CrowdRequests::get_passenger_vol_by), but follows the
+    /// link before it expires and decodes the CSV file in the archive into
+    /// [`PassengerVolRecord`](crate::passenger_vol::PassengerVolRecord)s.
+    ///
+    /// The archive is downloaded into memory, records are decoded as the iterator is advanced
+    async fn get_passenger_vol_records(
+        client: &C,
+        vol_type: passenger_vol::VolType,
+        date: Option<NaiveDate>,
+    ) -> LTAResult<PassengerVolRecords>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        let links = Self::get_passenger_vol_by(client, vol_type, date, None).await?;
+        let link = links
+            .first()
+            .ok_or_else(|| LTAError::Custom("No passenger volume link returned".to_string()))?;
+        let archive = download_link(client, link).await?;
+        PassengerVolRecords::from_zip(archive)
+    }
 }
 
 #[async_trait]
@@ -32,19 +54,19 @@ impl CrowdRequests<LTAClient> for Crowd {
     ) -> LTAResult<Vec<String>> {
         let fmt_date = date.map(|f| f.format(passenger_vol::FORMAT).to_string());
 
-        let url = vol_type_to_url(vol_type)?;
+        let path = vol_type_to_path(vol_type)?;
 
         match fmt_date {
             Some(nd) => {
                 build_req_with_query::<passenger_vol::PassengerVolRawResp, _, _, _>(
                     client,
-                    url,
+                    path,
                     |rb| rb.query(&[("Date", nd)]),
                 )
                 .await
             }
             None => {
-                build_req_with_skip::<passenger_vol::PassengerVolRawResp, _, _>(client, url, skip)
+                build_req_with_skip::<passenger_vol::PassengerVolRawResp, _, _>(client, path, skip)
                     .await
             }
         }
@@ -1,8 +1,10 @@
+use crate::facilities::{parse_maintenance, station_codes, StationMaintenance, LINE_CONCURRENCY};
 use crate::models::facility::prelude::FacilityMaintenanceRawResp;
 use crate::models::train::prelude::*;
-use crate::r#async::build_req_with_query;
+use crate::r#async::{build_req_with_query, download_link};
 use crate::{Client, Facility, LTAClient, LTAResult};
 use async_trait::async_trait;
+use futures::StreamExt;
 
 #[async_trait]
 pub trait FacilityReqeusts<C: Client> {
@@ -13,6 +15,47 @@ pub trait FacilityReqeusts<C: Client> {
         client: &C,
         station_code: StationCode,
     ) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_facilities_maintenance`](FacilityReqeusts::get_facilities_maintenance), but
+    /// downloads the linked files and returns the maintenance schedules of `station_code`.
+    /// Schedules in the files for other station codes are returned in
+    /// [`unmatched`](StationMaintenance::unmatched)
+    async fn get_facilities_maintenance_records(
+        client: &C,
+        station_code: StationCode,
+    ) -> LTAResult<StationMaintenance>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        let mut records = Vec::new();
+        for link in Self::get_facilities_maintenance(client, station_code.clone()).await? {
+            let json = download_link(client, &link).await?;
+            records.extend(parse_maintenance(&json, &link)?);
+        }
+
+        Ok(StationMaintenance::split(records, &station_code))
+    }
+
+    /// Returns the maintenance schedules of every station of `line`, in station order. Up to
+    /// [`LINE_CONCURRENCY`] stations are requested at a time. A station that cannot be requested
+    /// has its error in its place, without failing the other stations
+    async fn get_line_facilities_maintenance(
+        client: &C,
+        line: MrtLine,
+    ) -> Vec<(StationCode, LTAResult<StationMaintenance>)>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        let requests = station_codes(&line).into_iter().map(|code| async move {
+            let records = Self::get_facilities_maintenance_records(client, code.clone()).await;
+            (code, records)
+        });
+
+        futures::stream::iter(requests)
+            .buffered(LINE_CONCURRENCY)
+            .collect()
+            .await
+    }
 }
 
 #[async_trait]
@@ -23,7 +66,7 @@ impl FacilityReqeusts<LTAClient> for Facility {
     ) -> LTAResult<Vec<String>> {
         build_req_with_query::<FacilityMaintenanceRawResp, _, _, _>(
             client,
-            api_url!("/FacilitiesMaintenance"),
+            "/FacilitiesMaintenance",
             |rb| rb.query(&[("StationCode", station_code)]),
         )
         .await
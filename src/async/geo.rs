@@ -1,5 +1,6 @@
+use crate::geospatial::Shapefile;
 use crate::models::geo::prelude::*;
-use crate::r#async::build_req_with_query;
+use crate::r#async::{build_req_with_query, download_link};
 use crate::{Client, Geo, LTAClient, LTAResult};
 use async_trait::async_trait;
 
@@ -12,6 +13,22 @@ pub trait GeoRequests<C: Client> {
         client: &C,
         id: GeospatialLayerId,
     ) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_geospatial_whole_island`](GeoRequests::get_geospatial_whole_island), but
+    /// downloads the archives before the links expire and decodes the shapefiles in them into
+    /// [`Feature`](crate::geospatial::Feature)s
+    async fn get_geospatial_layer(client: &C, id: GeospatialLayerId) -> LTAResult<Vec<Shapefile>>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        let mut shapefiles = Vec::new();
+        for link in Self::get_geospatial_whole_island(client, id).await? {
+            let archive = download_link(client, &link).await?;
+            shapefiles.extend(Shapefile::from_zip(&archive)?);
+        }
+
+        Ok(shapefiles)
+    }
 }
 
 #[async_trait]
@@ -22,7 +39,7 @@ impl GeoRequests<LTAClient> for Geo {
     ) -> LTAResult<Vec<String>> {
         build_req_with_query::<GeospatialWholeIslandRawResp, _, _, _>(
             client,
-            api_url!("/GeospatialWholeIsland"),
+            "/GeospatialWholeIsland",
             |rb| rb.query(&[("ID", id)]),
         )
         .await
@@ -3,11 +3,22 @@ pub mod client;
 pub mod crowd;
 pub mod facility;
 pub mod geo;
+pub mod paginate;
 pub mod taxi;
 pub mod traffic;
 pub mod train;
-
-use crate::{Client, LTAError, LTAResult};
+pub mod watch;
+
+use crate::cassette::{CassetteMode, RecordedRequest};
+use crate::disk_cache::{unix_now, CacheMetadata, DiskCache, PageMetadata, RawPage};
+use crate::rate_limit::RateLimiter;
+use crate::{join_url, Client, LTAError, LTAResult, PageOptions, RawResponse, PAGE_SIZE};
+use futures::TryStreamExt;
+use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Duration;
 
 pub use crate::r#async::client::LTAClient;
 
@@ -21,73 +32,338 @@ pub mod prelude {
 /// helper function to build request with skip
 pub(crate) async fn build_req_with_skip<T, T2, C>(
     client: &C,
-    url: &str,
+    path: &str,
     skip: Option<u32>,
 ) -> LTAResult<T2>
 where
     C: Client<RB = reqwest::RequestBuilder>,
     for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
 {
     let skip = skip.unwrap_or(0);
-    let rb = client.req_builder(url).query(&[("$skip", skip)]);
-    rb.send()
-        .await
-        .map_err(LTAError::BackendError)
-        .and_then(handle_status_code)?
-        .json::<T>()
-        .await
-        .map(Into::into)
-        .map_err(LTAError::BackendError)
+    let url = join_url(client, path);
+    let rb = client.req_builder(&url).query(&[("$skip", skip)]);
+    send_req::<T, T2, C>(client, rb, path, &url).await
 }
 
 /// helper function to build request with query
 pub(crate) async fn build_req_with_query<T, T2, F, C>(
     client: &C,
-    url: &str,
+    path: &str,
     query: F,
 ) -> LTAResult<T2>
 where
     F: FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
     C: Client<RB = reqwest::RequestBuilder>,
     for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
 {
-    let rb = client.req_builder(url);
-    query(rb)
-        .send()
+    let url = join_url(client, path);
+    let rb = client.req_builder(&url);
+    send_req::<T, T2, C>(client, query(rb), path, &url).await
+}
+
+/// helper function to keep requesting pages with increasing `$skip` until an empty or short page
+/// is returned
+pub(crate) async fn fetch_all_pages<T, F, Fut>(get_page: F) -> LTAResult<Vec<T>>
+where
+    F: FnMut(Option<u32>) -> Fut,
+    Fut: Future<Output = LTAResult<Vec<T>>>,
+{
+    paginate::records(get_page, PageOptions::default())
+        .try_collect()
         .await
-        .map_err(LTAError::BackendError)
-        .and_then(handle_status_code)?
-        .json::<T>()
+}
+
+/// helper function to send a request and decode the response body, reading from and storing
+/// in the client's cache if it has one
+async fn send_req<T, T2, C>(
+    client: &C,
+    rb: reqwest::RequestBuilder,
+    path: &str,
+    url: &str,
+) -> LTAResult<T2>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
+{
+    let send_once = |rb| send_req_once::<T, T2, C>(client, rb, url);
+    let cache = match client.cache() {
+        Some(cache) => cache,
+        None => return send_req_with_retry(client, rb, send_once).await,
+    };
+
+    let key = match rb.try_clone().and_then(|rb| rb.build().ok()) {
+        Some(req) => req.url().to_string(),
+        None => return send_req_with_retry(client, rb, send_once).await,
+    };
+
+    if let Some(data) = cache.get::<T2>(&key) {
+        return Ok(data);
+    }
+
+    let in_flight = cache.in_flight(&key);
+    let _lock = in_flight.lock().await;
+    if let Some(data) = cache.get::<T2>(&key) {
+        return Ok(data);
+    }
+
+    let data = send_req_with_retry(client, rb, send_once).await?;
+    cache.insert(path, key, data.clone());
+    Ok(data)
+}
+
+/// helper function to send a request, waiting for the client's rate limiter and retrying
+/// according to its retry policy
+async fn send_req_with_retry<R, C, F, Fut>(
+    client: &C,
+    mut rb: reqwest::RequestBuilder,
+    send_once: F,
+) -> LTAResult<R>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+    F: Fn(reqwest::RequestBuilder) -> Fut,
+    Fut: Future<Output = LTAResult<R>>,
+{
+    let retry_policy = client.retry_policy();
+    let mut attempt = 1;
+
+    loop {
+        let delay = client.rate_limiter().map(RateLimiter::reserve);
+        if let Some(delay) = delay.filter(|d| *d > Duration::from_secs(0)) {
+            tokio::time::sleep(delay).await;
+        }
+
+        let next_rb = rb.try_clone();
+        let err = match send_once(rb).await {
+            Ok(data) => return Ok(data),
+            Err(err) => err,
+        };
+
+        match (retry_policy.next_delay(attempt, &err), next_rb) {
+            (Some(delay), Some(next_rb)) => {
+                tokio::time::sleep(delay).await;
+                rb = next_rb;
+                attempt += 1;
+            }
+            _ => return Err(err),
+        }
+    }
+}
+
+async fn send_req_once<T, T2, C>(
+    client: &C,
+    rb: reqwest::RequestBuilder,
+    url: &str,
+) -> LTAResult<T2>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<T2>,
+{
+    let res = send_raw(client, rb, url).await?.error_for_status()?;
+    res.json::<T>().map(Into::into)
+}
+
+/// helper function to send a request and read the response body, recording it to or replaying
+/// it from the client's cassette if it has one
+async fn send_raw<C>(client: &C, rb: reqwest::RequestBuilder, url: &str) -> LTAResult<RawResponse>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+{
+    let recorded = client.cassette().map(|cassette| {
+        let req = rb.try_clone().and_then(|rb| rb.build().ok());
+        let req = req.map(|req| RecordedRequest::new(req.method(), req.url(), req.headers()));
+        (cassette, req)
+    });
+
+    if let Some((cassette, req)) = &recorded {
+        if cassette.mode() == CassetteMode::Replay {
+            let req = req
+                .as_ref()
+                .ok_or_else(|| LTAError::Custom(format!("Unable to replay request to {}", url)))?;
+            return cassette.replay_response(req);
+        }
+    }
+
+    let res = rb.send().await.map_err(|e| LTAError::transport(url, e))?;
+    let (status, headers) = (res.status(), res.headers().clone());
+    let url = res.url().to_string();
+    let body = res
+        .bytes()
         .await
-        .map(Into::into)
-        .map_err(LTAError::BackendError)
+        .map_err(|e| LTAError::transport(&url, e))?
+        .to_vec();
+    let res = RawResponse {
+        status,
+        url,
+        headers,
+        body,
+    };
+
+    if let Some((cassette, Some(req))) = recorded {
+        cassette.record_interaction(req, &res);
+    }
+
+    Ok(res)
+}
+
+/// helper function to download a link returned by an API, eg a passenger volume archive
+pub(crate) async fn download_link<C>(client: &C, link: &str) -> LTAResult<Vec<u8>>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+{
+    let rb = client.link_req_builder(link);
+    Ok(send_raw(client, rb, link).await?.error_for_status()?.body)
 }
 
-fn handle_status_code(res: reqwest::Response) -> LTAResult<reqwest::Response> {
-    use reqwest::StatusCode;
+/// helper function to fetch every record of `path`, reading from `cache` while the dataset is
+/// fresh and revalidating each page with a conditional request once it is stale
+pub(crate) async fn fetch_all_pages_disk_cached<T, T2, C>(
+    client: &C,
+    path: &str,
+    cache: &DiskCache,
+) -> LTAResult<Vec<T2>>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<Vec<T2>>,
+{
+    let name = path.trim_start_matches('/').to_string();
+    let cached = {
+        let (cache, name) = (cache.clone(), name.clone());
+        tokio::task::spawn_blocking(move || cache.load(&name))
+            .await
+            .ok()
+            .flatten()
+    };
 
-    let status_code = res.status();
+    if let Some((metadata, page)) = &cached {
+        if cache.is_fresh(metadata) {
+            return decode_raw_page::<T, T2>(&name, page.clone());
+        }
+    }
 
-    if status_code.is_success() {
-        return Ok(res);
+    let validators = cached.as_ref().and_then(|(m, _)| cache.validators(m));
+    let url = join_url(client, path);
+    let mut metadata = CacheMetadata {
+        fetched_at: unix_now(),
+        ..CacheMetadata::default()
+    };
+    let mut page = RawPage::default();
+
+    loop {
+        let cached_page = validators
+            .as_ref()
+            .and_then(|m| m.pages.get(metadata.pages.len()));
+        let mut rb = client
+            .req_builder(&url)
+            .query(&[("$skip", page.value.len())]);
+        if let Some(etag) = cached_page.and_then(|p| p.etag.as_ref()) {
+            rb = rb.header(IF_NONE_MATCH, etag.as_str());
+        }
+        if let Some(last_modified) = cached_page.and_then(|p| p.last_modified.as_ref()) {
+            rb = rb.header(IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+
+        let fetched =
+            send_req_with_retry(client, rb, |rb| send_req_if_modified(client, rb, &url)).await?;
+        let (page_metadata, records) = match (fetched, cached_page, &cached) {
+            (Some(fetched), _, _) => fetched,
+            (None, Some(cached_page), Some((_, cached))) => {
+                let start = page.value.len();
+                let records = cached
+                    .value
+                    .get(start..start + cached_page.records)
+                    .ok_or_else(|| {
+                        LTAError::Custom(format!(
+                            "{} was not modified but page at $skip={} is not cached",
+                            url, start
+                        ))
+                    })?;
+                (cached_page.clone(), records.to_vec())
+            }
+            (None, _, _) => {
+                return Err(LTAError::Custom(format!(
+                    "{} was not modified but is not cached",
+                    url
+                )))
+            }
+        };
+
+        let is_last = records.len() < PAGE_SIZE as usize;
+        metadata.pages.push(page_metadata);
+        page.value.extend(records);
+        if is_last {
+            break;
+        }
     }
+    metadata.records = page.value.len();
+
+    // Failing to write the cache only means the dataset is downloaded again next time
+    let (store_cache, store_name) = (cache.clone(), name.clone());
+    let store_page = page.clone();
+    let _ =
+        tokio::task::spawn_blocking(move || store_cache.store(&store_name, &metadata, &store_page))
+            .await;
 
-    match status_code {
-        StatusCode::UNAUTHORIZED => Err(LTAError::Unauthorized),
-        StatusCode::NOT_FOUND => Err(LTAError::Unauthorized),
-        _ => Err(LTAError::UnhandledStatusCode),
+    decode_raw_page::<T, T2>(&name, page)
+}
+
+/// helper function to request a page of a dataset, returning `None` if the server responds with
+/// `304 Not Modified`
+async fn send_req_if_modified<C>(
+    client: &C,
+    rb: reqwest::RequestBuilder,
+    url: &str,
+) -> LTAResult<Option<(PageMetadata, Vec<Value>)>>
+where
+    C: Client<RB = reqwest::RequestBuilder>,
+{
+    let res = send_raw(client, rb, url).await?;
+    if res.status == StatusCode::NOT_MODIFIED {
+        return Ok(None);
     }
+
+    let res = res.error_for_status()?;
+    let page = res.json::<RawPage>()?;
+    let metadata = PageMetadata::from_headers(&res.headers, page.value.len());
+    Ok(Some((metadata, page.value)))
+}
+
+fn decode_raw_page<T, T2>(name: &str, page: RawPage) -> LTAResult<Vec<T2>>
+where
+    for<'de> T: serde::Deserialize<'de> + Into<Vec<T2>>,
+{
+    serde_json::to_value(page)
+        .and_then(serde_json::from_value::<T>)
+        .map(Into::into)
+        .map_err(|e| LTAError::Custom(format!("Unable to decode cached {}: {}", name, e)))
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::cassette::Cassette;
+    use crate::disk_cache::DiskCache;
+    use crate::facilities::FacilityType;
+    use crate::geospatial::{FieldValue, Geometry, Point};
+    use crate::mock::{Fault, MockRequest, MockServer};
+    use crate::models::chrono::{NaiveDate, NaiveTime};
     use crate::models::geo::prelude::GeospatialLayerId;
-    use crate::models::prelude::{StationCode, VolType};
+    use crate::models::prelude::{MrtLine, StationCode, VolType};
     use crate::models::traffic::road::RoadDetailsType;
+    use crate::passenger_vol::PassengerVolRecord;
     use crate::prelude::*;
     use crate::r#async::prelude::*;
-    use crate::{Client, LTAClient, LTAResult};
-    use std::env;
+    use crate::r#async::watch::{watch, ArrivalEvent, WatchOptions};
+    use crate::r#async::{fetch_all_pages, paginate};
+    use crate::retry::RetryPolicy;
+    use crate::{
+        join_url, ArrivalQuery, Client, HttpErrorDetails, LTAClient, LTAError, LTAResult,
+        PageOptions, MAX_BODY_SNIPPET_LEN, PAGE_SIZE,
+    };
+    use futures::{StreamExt, TryStreamExt};
+    use serde_json::json;
+    use std::time::Duration;
 
     macro_rules! gen_test {
         ($f: expr) => {{
@@ -99,9 +375,20 @@ mod tests {
     }
 
     fn get_client() -> LTAClient {
-        let api_key = env::var("API_KEY").expect("API_KEY does not exist!");
-        let client = LTAClient::with_api_key(api_key).unwrap();
-        client
+        client_for(MockServer::shared())
+    }
+
+    fn client_for(server: &MockServer) -> LTAClient {
+        LTAClient::with_api_key("API_KEY")
+            .unwrap()
+            .with_base_url(server.base_url())
+    }
+
+    fn taxi_avail_fixture(len: usize) -> serde_json::Value {
+        let value: Vec<_> = (0..len)
+            .map(|i| json!({ "Longitude": 103.8, "Latitude": i as f64 / 1000.0 }))
+            .collect();
+        json!({ "value": value })
     }
 
     #[tokio::test]
@@ -112,6 +399,151 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn get_arrivals_keeps_errors_per_stop() {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+        server.fail_next("/BusArrivalv2", Fault::Unauthorized);
+
+        let queries = vec![
+            ArrivalQuery::new(83111),
+            ArrivalQuery::new(83139).service("15"),
+            ArrivalQuery::new(83139).service("155"),
+        ];
+        let arrivals = Bus::get_arrivals(&client, queries, 1).await;
+
+        assert_eq!(arrivals.len(), 2);
+        assert!(matches!(arrivals[&83111], Err(LTAError::Unauthorized(_))));
+        let services: Vec<&str> = arrivals[&83139]
+            .as_ref()
+            .unwrap()
+            .services
+            .iter()
+            .map(|s| s.service_no.as_str())
+            .collect();
+        assert_eq!(services, vec!["15", "155"]);
+    }
+
+    #[tokio::test]
+    async fn fetch_all_pages_until_short_page() -> LTAResult<()> {
+        let data = fetch_all_pages(|skip| async move {
+            let skip = skip.unwrap_or(0);
+            let len = if skip < 1000 { PAGE_SIZE } else { 42 };
+            Ok((skip..skip + len).collect::<Vec<_>>())
+        })
+        .await?;
+        assert_eq!(data.len(), 1042);
+        assert_eq!(data.last(), Some(&1041));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn paginate_records_with_prefetch() -> LTAResult<()> {
+        let opts = PageOptions::new().prefetch(3);
+        let get_page = |skip: Option<u32>| async move {
+            let skip = skip.unwrap_or(0);
+            Ok((skip..(skip + PAGE_SIZE).min(1250)).collect::<Vec<_>>())
+        };
+        let data: Vec<u32> = paginate::records(get_page, opts).try_collect().await?;
+        assert_eq!(data, (0..1250).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn paginate_stops_on_error() {
+        let get_page = |skip: Option<u32>| async move {
+            match skip {
+                Some(0) => Ok(vec![0; PAGE_SIZE as usize]),
+                _ => Err(LTAError::Custom("Failed".to_string())),
+            }
+        };
+        let pages: Vec<LTAResult<Vec<u32>>> = paginate::pages(get_page, PageOptions::new())
+            .collect()
+            .await;
+        assert_eq!(pages.len(), 2);
+        assert!(pages[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn watch_continues_after_error() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+        let services = Bus::get_arrival(&client, 83139, None).await?.services;
+        server.fail_next("/BusArrivalv2", Fault::ServerError);
+
+        let opts = WatchOptions::new().interval(Duration::from_millis(10));
+        let events = watch(
+            |stop| Bus::get_arrival(&client, stop, None),
+            vec![83139],
+            opts,
+        );
+        futures::pin_mut!(events);
+
+        // The first poll fails, the second reports every service. The recorded buses are all
+        // past their estimated arrival, so they are arriving too
+        assert!(events.next().await.unwrap().is_err());
+        let events: Vec<ArrivalEvent> = events.take(services.len() * 2).try_collect().await?;
+        let added: Vec<&str> = events
+            .iter()
+            .filter_map(|e| match e {
+                ArrivalEvent::ServiceAdded {
+                    bus_stop_code: 83139,
+                    service,
+                } => Some(service.service_no.as_str()),
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<&str> = services.iter().map(|s| s.service_no.as_str()).collect();
+        assert_eq!(added, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn custom_base_url() -> LTAResult<()> {
+        let client = LTAClient::with_api_key("API_KEY")?;
+        assert_eq!(join_url(&client, "/BusStops"), api_url!("/BusStops"));
+
+        let client = client.with_base_url("http://localhost:8080/");
+        assert_eq!(
+            join_url(&client, "/BusStops"),
+            "http://localhost:8080/BusStops"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn status_code_to_error() {
+        use reqwest::StatusCode;
+
+        let url = "http://localhost/BusStops";
+        let from_status = |status| LTAError::from_status(HttpErrorDetails::new(status, url, ""));
+
+        assert!(matches!(
+            from_status(StatusCode::UNAUTHORIZED),
+            LTAError::Unauthorized(_)
+        ));
+        assert!(matches!(
+            from_status(StatusCode::NOT_FOUND),
+            LTAError::NotFound(_)
+        ));
+        assert!(matches!(
+            from_status(StatusCode::TOO_MANY_REQUESTS),
+            LTAError::RateLimitReached(_)
+        ));
+        assert!(matches!(
+            from_status(StatusCode::BAD_GATEWAY),
+            LTAError::UnhandledStatusCode(_)
+        ));
+
+        let details = HttpErrorDetails::new(StatusCode::NOT_FOUND, url, "é".repeat(1000));
+        assert!(details.body.len() <= MAX_BODY_SNIPPET_LEN);
+
+        let err = LTAError::from_status(details);
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(err.url(), Some(url));
+        assert!(err.to_string().starts_with("Not found: 404 Not Found from"));
+    }
+
     #[tokio::test]
     async fn get_bus_services() -> LTAResult<()> {
         gen_test!(Bus::get_bus_services)
@@ -122,12 +554,37 @@ mod tests {
         gen_test!(Bus::get_bus_routes)
     }
 
+    #[tokio::test]
+    async fn get_bus_routes_times() -> LTAResult<()> {
+        // lta_models reads one digit each of the hour and minute of "0502"
+        let client = get_client();
+        let routes = Bus::get_bus_routes(&client, None).await?;
+        let at = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        assert_eq!(
+            (routes[0].wd_first, routes[0].wd_last),
+            (at(5, 0), at(23, 0))
+        );
+        assert_eq!(
+            (routes[1].wd_first, routes[1].wd_last),
+            (at(5, 2), at(23, 2))
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_bus_stops() -> LTAResult<()> {
         gen_test!(Bus::get_bus_stops)
     }
 
-    #[ignore]
+    #[tokio::test]
+    async fn get_all_bus_stops() -> LTAResult<()> {
+        let client = get_client();
+        let data = Bus::get_all_bus_stops(&client).await?;
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[0].road_name, "Victoria St");
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_passenger_vol() -> LTAResult<()> {
         let client = get_client();
@@ -136,6 +593,39 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn get_passenger_vol_records() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+        let expected = vec![
+            (VolType::BusStops, false, 4),
+            (VolType::OdBusStop, true, 4),
+            (VolType::Train, false, 3),
+            (VolType::OdTrain, true, 3),
+        ];
+
+        for (vol_type, od, len) in expected {
+            let records = Crowd::get_passenger_vol_records(&client, vol_type, None).await?;
+            assert_eq!(records.is_origin_destination(), od);
+            let records = records.collect::<LTAResult<Vec<_>>>()?;
+            assert_eq!(records.len(), len);
+            assert!(records
+                .iter()
+                .all(|r| matches!(r, PassengerVolRecord::Od(_)) == od));
+        }
+
+        let downloads: Vec<MockRequest> = server
+            .requests()
+            .into_iter()
+            .filter(|req| req.path.starts_with("/files/"))
+            .collect();
+        assert_eq!(downloads.len(), 4);
+        assert!(downloads
+            .iter()
+            .all(|req| req.header("AccountKey").is_none()));
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_taxi_avail() -> LTAResult<()> {
         gen_test!(Taxi::get_taxi_avail)
@@ -216,6 +706,38 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn get_geospatial_layer() -> LTAResult<()> {
+        let client = get_client();
+        let layers = Geo::get_geospatial_layer(&client, GeospatialLayerId::ArrowMarking).await?;
+        assert_eq!(layers.len(), 1);
+
+        let arrows = &layers[0];
+        assert_eq!(arrows.name, "ArrowMarking");
+        assert_eq!(arrows.fields, vec!["TYP_CD", "BEARG_NUM", "INSTL_DATE"]);
+        // The second record is marked deleted in the dbf file
+        assert_eq!(arrows.features.len(), 2);
+
+        let first = &arrows.features[0];
+        assert_eq!(
+            first.geometry,
+            Geometry::Point(Point {
+                x: 29312.43,
+                y: 31452.18
+            })
+        );
+        assert_eq!(first.attributes["BEARG_NUM"].as_f64(), Some(87.5));
+        assert_eq!(
+            first.attributes["INSTL_DATE"],
+            FieldValue::Date(NaiveDate::from_ymd_opt(2019, 3, 14).unwrap())
+        );
+        assert_eq!(
+            arrows.features[1].attributes["INSTL_DATE"],
+            FieldValue::Null
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn get_facility_maintenance() -> LTAResult<()> {
         let client = get_client();
@@ -223,4 +745,239 @@ mod tests {
         println!("{:?}", data);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn get_facility_maintenance_records() -> LTAResult<()> {
+        let client = get_client();
+        let station =
+            Facility::get_facilities_maintenance_records(&client, StationCode::NS1).await?;
+        let records = station.records;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].facility_type, FacilityType::Lift);
+        assert_eq!(records[0].location, "Exit B, Street Level - Concourse");
+        assert_eq!(records[1].facility_type, FacilityType::Escalator);
+        assert_eq!(records[1].start_date, NaiveDate::from_ymd_opt(2024, 6, 10));
+        assert_eq!(records[1].end_date, None);
+
+        // Schedules of the other stations in the file are kept
+        let unmatched: Vec<_> = station.unmatched.iter().map(|r| &r.station_code).collect();
+        assert_eq!(unmatched, vec![&StationCode::EW24, &StationCode::NS24]);
+
+        let stations = Facility::get_line_facilities_maintenance(&client, MrtLine::NSL).await;
+        let mut codes = Vec::new();
+        for (_, records) in stations {
+            codes.extend(records?.records.into_iter().map(|r| r.station_code));
+        }
+        assert_eq!(
+            codes,
+            vec![StationCode::NS1, StationCode::NS1, StationCode::NS24]
+        );
+
+        // One station failing keeps the records of the others
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+        server.fail_next("/FacilitiesMaintenance", Fault::Unauthorized);
+        let stations = Facility::get_line_facilities_maintenance(&client, MrtLine::NSL).await;
+        let failed = stations.iter().filter(|(_, r)| r.is_err()).count();
+        assert_eq!(failed, 1);
+        let records: usize = stations
+            .iter()
+            .filter_map(|(_, r)| r.as_ref().ok())
+            .map(|station| station.records.len())
+            .sum();
+        assert!(records > 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn get_all_pages_from_mock() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        server.set_fixture("/Taxi-Availability", taxi_avail_fixture(1042));
+        let client = client_for(&server);
+
+        let data = Taxi::get_all_taxi_avail(&client).await?;
+        assert_eq!(data.len(), 1042);
+
+        let skips: Vec<_> = server
+            .requests()
+            .iter()
+            .map(|req| req.query("$skip").unwrap().to_string())
+            .collect();
+        assert_eq!(skips, vec!["0", "500", "1000"]);
+        assert_eq!(server.requests()[0].header("AccountKey"), Some("API_KEY"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn injected_faults() {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+
+        server.fail_next("/BusStops", Fault::Unauthorized);
+        let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+        assert!(matches!(err, LTAError::Unauthorized(_)));
+
+        server.fail_next(
+            "/BusStops",
+            Fault::RateLimited {
+                retry_after: Some(30),
+            },
+        );
+        let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+        assert!(matches!(err, LTAError::RateLimitReached(_)));
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(30)));
+
+        server.fail_next("/BusStops", Fault::ServerError);
+        let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+        assert_eq!(
+            err.status(),
+            Some(reqwest::StatusCode::INTERNAL_SERVER_ERROR)
+        );
+
+        server.fail_next("/BusStops", Fault::MalformedJson);
+        let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+        assert!(matches!(err, LTAError::Decode { .. }));
+
+        server.require_api_key("OTHER_KEY");
+        let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+        assert!(matches!(err, LTAError::Unauthorized(_)));
+    }
+
+    #[tokio::test]
+    async fn retries_injected_faults() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let policy = RetryPolicy::new(3).base_delay(Duration::from_millis(1));
+        let client = client_for(&server).with_retry_policy(policy);
+
+        server.fail_next("/BusStops", Fault::ServerError);
+        server.fail_next("/BusStops", Fault::RateLimited { retry_after: None });
+        let data = Bus::get_bus_stops(&client, None).await?;
+        assert!(!data.is_empty());
+        assert_eq!(server.requests().len(), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_cache_misses_request_once() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server).with_cache(crate::cache::ResponseCache::new());
+
+        let requests = (0..8).map(|_| Bus::get_bus_stops(&client, None));
+        let responses = futures::future::join_all(requests).await;
+        assert!(responses
+            .iter()
+            .all(|r| r.as_ref().unwrap() == responses[0].as_ref().unwrap()));
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_cache_revalidates_with_etag() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server);
+        let dir = std::env::temp_dir().join(format!("lta-mock-disk-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir);
+
+        let stops = Bus::get_all_bus_stops_cached(&client, &cache).await?;
+        assert_eq!(Bus::get_all_bus_stops_cached(&client, &cache).await?, stops);
+        assert_eq!(server.requests().len(), 1);
+        assert_eq!(cache.metadata("BusStops").unwrap().records, stops.len());
+
+        let stale = cache.clone().max_age(Duration::from_secs(0));
+        assert_eq!(Bus::get_all_bus_stops_cached(&client, &stale).await?, stops);
+        let req = server.requests().pop().unwrap();
+        assert!(req.header("If-None-Match").is_some());
+
+        let forced = cache.force_refresh(true);
+        assert_eq!(
+            Bus::get_all_bus_stops_cached(&client, &forced).await?,
+            stops
+        );
+        let req = server.requests().pop().unwrap();
+        assert!(req.header("If-None-Match").is_none());
+        assert_eq!(server.requests().len(), 3);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disk_cache_revalidates_every_page() -> LTAResult<()> {
+        let bus_stops_fixture = |description: &str| {
+            let stops = (0..600)
+                .map(|i| {
+                    serde_json::json!({
+                        "BusStopCode": format!("{:05}", i),
+                        "RoadName": "Victoria St",
+                        "Description": if i == 550 { description } else { "Stop" },
+                        "Latitude": 1.3,
+                        "Longitude": 103.8
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::json!({ "value": stops })
+        };
+        let server = MockServer::start().unwrap();
+        server.set_fixture("/BusStops", bus_stops_fixture("Before"));
+        let client = client_for(&server);
+        let dir = std::env::temp_dir().join(format!("lta-mock-disk-pages-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).max_age(Duration::from_secs(0));
+
+        let stops = Bus::get_all_bus_stops_cached(&client, &cache).await?;
+        assert_eq!(stops.len(), 600);
+        assert_eq!(cache.metadata("BusStops").unwrap().pages.len(), 2);
+
+        server.set_fixture("/BusStops", bus_stops_fixture("After"));
+        let stops = Bus::get_all_bus_stops_cached(&client, &cache).await?;
+        assert_eq!(stops.len(), 600);
+        assert_eq!(stops[550].desc, "After");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[2..]
+            .iter()
+            .all(|r| r.header("If-None-Match").is_some()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn record_and_replay_cassette() -> LTAResult<()> {
+        let path = std::env::temp_dir().join(format!("lta-cassette-{}.json", std::process::id()));
+        let server = MockServer::start().unwrap();
+        let cassette = Cassette::record(&path);
+        let client = client_for(&server).with_cassette(cassette.clone());
+
+        let stops = Bus::get_bus_stops(&client, None).await?;
+        let volumes = Crowd::get_passenger_vol_records(&client, VolType::Train, None)
+            .await?
+            .collect::<LTAResult<Vec<_>>>()?;
+        server.fail_next("/BusServices", Fault::Unauthorized);
+        assert!(Bus::get_bus_services(&client, None).await.is_err());
+
+        // Nothing is written until the cassette is saved
+        assert!(!path.exists());
+        cassette.save()?;
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(recorded.contains("$skip"));
+        assert!(!recorded.to_lowercase().contains("accountkey"));
+
+        let base_url = server.base_url();
+        drop(server);
+        let client = LTAClient::with_api_key("API_KEY")?
+            .with_base_url(base_url)
+            .with_cassette(Cassette::replay(&path).unwrap());
+
+        assert_eq!(Bus::get_bus_stops(&client, None).await?, stops);
+        let replayed = Crowd::get_passenger_vol_records(&client, VolType::Train, None).await?;
+        assert_eq!(replayed.collect::<LTAResult<Vec<_>>>()?, volumes);
+        let err = Bus::get_bus_services(&client, None).await.unwrap_err();
+        assert!(matches!(err, LTAError::Unauthorized(_)));
+        let err = Bus::get_bus_routes(&client, None).await.unwrap_err();
+        assert!(err.to_string().starts_with("No recorded response for GET"));
+
+        std::fs::remove_file(path).unwrap();
+        Ok(())
+    }
 }
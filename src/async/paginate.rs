@@ -0,0 +1,106 @@
+//! Lazily request APIs that accept `skip`, one page at a time
+//!
+//! Pages are as long as Datamall returns them, [`PAGE_SIZE`] records each, as the API has no
+//! parameter for the page size. [`PageOptions`] only sets how many pages are requested ahead.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use futures::TryStreamExt;
+//! use lta::r#async::paginate::records;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult, PageOptions};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let client = LTAClient::with_api_key("API_KEY")?;
+//!     let opts = PageOptions::new().prefetch(2);
+//!     let routes = records(|skip| Bus::get_bus_routes(&client, skip), opts);
+//!     futures::pin_mut!(routes);
+//!
+//!     while let Some(route) = routes.try_next().await? {
+//!         println!("{:?}", route);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::{LTAResult, PAGE_SIZE};
+use futures::future::ready;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Options used when lazily paginating through APIs that accept `skip`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PageOptions {
+    /// Max number of pages requested concurrently. `1` means the next page is only requested once
+    /// the current one has been consumed
+    pub prefetch: usize,
+}
+
+impl PageOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefetch(mut self, prefetch: usize) -> Self {
+        self.prefetch = prefetch.max(1);
+        self
+    }
+}
+
+impl Default for PageOptions {
+    fn default() -> Self {
+        PageOptions { prefetch: 1 }
+    }
+}
+
+/// Returns a `Stream` of pages. `get_page` is called with `skip` increasing by [`PAGE_SIZE`]
+/// until an empty or short page is returned, or an error occurs. Errors are yielded and end the
+/// stream
+pub fn pages<T, F, Fut>(mut get_page: F, opts: PageOptions) -> impl Stream<Item = LTAResult<Vec<T>>>
+where
+    F: FnMut(Option<u32>) -> Fut,
+    Fut: Future<Output = LTAResult<Vec<T>>>,
+{
+    // Shared with the source so that no more pages are requested once the last one is seen
+    let done = Arc::new(AtomicBool::new(false));
+    let source_done = done.clone();
+
+    stream::iter((0..).step_by(PAGE_SIZE as usize))
+        .take_while(move |_| ready(!source_done.load(Ordering::SeqCst)))
+        .map(move |skip| get_page(Some(skip)))
+        .buffered(opts.prefetch.max(1))
+        .scan((), move |_, page| {
+            if done.load(Ordering::SeqCst) {
+                return ready(None);
+            }
+
+            let page = match page {
+                Ok(page) if page.is_empty() => {
+                    done.store(true, Ordering::SeqCst);
+                    return ready(None);
+                }
+                Ok(page) => {
+                    done.store(page.len() < PAGE_SIZE as usize, Ordering::SeqCst);
+                    Ok(page)
+                }
+                Err(e) => {
+                    done.store(true, Ordering::SeqCst);
+                    Err(e)
+                }
+            };
+
+            ready(Some(page))
+        })
+}
+
+/// Same as [`pages`], but yields individual records instead
+pub fn records<T, F, Fut>(get_page: F, opts: PageOptions) -> impl Stream<Item = LTAResult<T>>
+where
+    F: FnMut(Option<u32>) -> Fut,
+    Fut: Future<Output = LTAResult<Vec<T>>>,
+{
+    pages(get_page, opts)
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+}
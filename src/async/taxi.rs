@@ -1,8 +1,10 @@
+use crate::disk_cache::DiskCache;
 use crate::models::prelude::*;
 use crate::models::utils::Coordinates;
 use crate::r#async::build_req_with_skip;
 use crate::r#async::LTAClient;
-use crate::{api_url, Client, LTAResult, Taxi};
+use crate::r#async::{fetch_all_pages, fetch_all_pages_disk_cached};
+use crate::{Client, LTAResult, Taxi};
 use async_trait::async_trait;
 
 /// All APIs pertaining to taxis
@@ -18,16 +20,42 @@ pub trait TaxiRequests<C: Client> {
     ///
     /// **Update freq**: Monthly
     async fn get_taxi_stands(client: &C, skip: Option<u32>) -> LTAResult<Vec<TaxiStand>>;
+
+    /// Same as [`get_taxi_avail`](TaxiRequests::get_taxi_avail), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_taxi_avail(client: &C) -> LTAResult<Vec<Coordinates>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_taxi_avail(client, skip)).await
+    }
+
+    /// Same as [`get_taxi_stands`](TaxiRequests::get_taxi_stands), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_taxi_stands(client: &C) -> LTAResult<Vec<TaxiStand>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_taxi_stands(client, skip)).await
+    }
+
+    /// Same as [`get_all_taxi_stands`](TaxiRequests::get_all_taxi_stands), but reads the records
+    /// from `cache` while they are fresh, and stores them in it after fetching them otherwise
+    async fn get_all_taxi_stands_cached(client: &C, cache: &DiskCache) -> LTAResult<Vec<TaxiStand>>
+    where
+        C: Client<RB = reqwest::RequestBuilder> + Sync,
+    {
+        fetch_all_pages_disk_cached::<TaxiStandsResp, _, _>(client, "/TaxiStands", cache).await
+    }
 }
 
 #[async_trait]
 impl TaxiRequests<LTAClient> for Taxi {
     async fn get_taxi_avail(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<Coordinates>> {
-        build_req_with_skip::<TaxiAvailResp, _, _>(client, api_url!("/Taxi-Availability"), skip)
-            .await
+        build_req_with_skip::<TaxiAvailResp, _, _>(client, "/Taxi-Availability", skip).await
     }
 
     async fn get_taxi_stands(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<TaxiStand>> {
-        build_req_with_skip::<TaxiStandsResp, _, _>(client, api_url!("/TaxiStands"), skip).await
+        build_req_with_skip::<TaxiStandsResp, _, _>(client, "/TaxiStands", skip).await
     }
 }
@@ -1,6 +1,6 @@
-use crate::api_url;
 use crate::models::traffic::prelude::*;
-use crate::r#async::{build_req_with_query, build_req_with_skip, LTAClient};
+use crate::r#async::{build_req_with_query, build_req_with_skip, fetch_all_pages, LTAClient};
+use crate::traffic_lights::RawFaultyTrafficLightResp;
 use crate::{Client, LTAError, LTAResult, Traffic};
 use async_trait::async_trait;
 
@@ -86,41 +86,116 @@ pub trait TrafficRequests<C: Client> {
         long: f64,
         dist: Option<f64>,
     ) -> LTAResult<Vec<BikeParking>>;
+
+    /// Same as [`get_erp_rates`](TrafficRequests::get_erp_rates), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_erp_rates(client: &C) -> LTAResult<Vec<ErpRate>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_erp_rates(client, skip)).await
+    }
+
+    /// Same as [`get_carpark_avail`](TrafficRequests::get_carpark_avail), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_carpark_avail(client: &C) -> LTAResult<Vec<CarPark>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_carpark_avail(client, skip)).await
+    }
+
+    /// Same as [`get_est_travel_time`](TrafficRequests::get_est_travel_time), but keeps requesting
+    /// with increasing `skip` until every record is returned
+    async fn get_all_est_travel_time(client: &C) -> LTAResult<Vec<EstTravelTime>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_est_travel_time(client, skip)).await
+    }
+
+    /// Same as [`get_faulty_traffic_lights`](TrafficRequests::get_faulty_traffic_lights), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    async fn get_all_faulty_traffic_lights(client: &C) -> LTAResult<Vec<FaultyTrafficLight>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_faulty_traffic_lights(client, skip)).await
+    }
+
+    /// Same as [`get_road_details`](TrafficRequests::get_road_details), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_road_details(
+        client: &C,
+        road_details_type: RoadDetailsType,
+    ) -> LTAResult<Vec<RoadDetails>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_road_details(client, road_details_type.clone(), skip))
+            .await
+    }
+
+    /// Same as [`get_traffic_speed_band`](TrafficRequests::get_traffic_speed_band), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    async fn get_all_traffic_speed_band(client: &C) -> LTAResult<Vec<TrafficSpeedBand>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_traffic_speed_band(client, skip)).await
+    }
+
+    /// Same as [`get_traffic_images`](TrafficRequests::get_traffic_images), but keeps requesting
+    /// with increasing `skip` until every record is returned
+    async fn get_all_traffic_images(client: &C) -> LTAResult<Vec<TrafficImage>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_traffic_images(client, skip)).await
+    }
+
+    /// Same as [`get_traffic_incidents`](TrafficRequests::get_traffic_incidents), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    async fn get_all_traffic_incidents(client: &C) -> LTAResult<Vec<TrafficIncident>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_traffic_incidents(client, skip)).await
+    }
+
+    /// Same as [`get_vms_emas`](TrafficRequests::get_vms_emas), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    async fn get_all_vms_emas(client: &C) -> LTAResult<Vec<VMS>>
+    where
+        C: Sync,
+    {
+        fetch_all_pages(|skip| Self::get_vms_emas(client, skip)).await
+    }
 }
 
 #[async_trait]
 impl TrafficRequests<LTAClient> for Traffic {
     async fn get_erp_rates(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<ErpRate>> {
-        build_req_with_skip::<ErpRatesResp, _, _>(client, api_url!("/ERPRates"), skip).await
+        build_req_with_skip::<ErpRatesResp, _, _>(client, "/ERPRates", skip).await
     }
 
     async fn get_carpark_avail(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<CarPark>> {
-        build_req_with_skip::<CarparkAvailResp, _, _>(
-            client,
-            api_url!("/CarParkAvailabilityv2"),
-            skip,
-        )
-        .await
+        build_req_with_skip::<CarparkAvailResp, _, _>(client, "/CarParkAvailabilityv2", skip).await
     }
 
     async fn get_est_travel_time(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<EstTravelTime>> {
-        build_req_with_skip::<EstTravelTimeResp, _, _>(client, api_url!("/EstTravelTimes"), skip)
-            .await
+        build_req_with_skip::<EstTravelTimeResp, _, _>(client, "/EstTravelTimes", skip).await
     }
 
     async fn get_faulty_traffic_lights(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<FaultyTrafficLight>> {
-        build_req_with_skip::<FaultyTrafficLightResp, _, _>(
-            client,
-            api_url!("/FaultyTrafficLights"),
-            skip,
-        )
-        .await
+        build_req_with_skip::<RawFaultyTrafficLightResp, _, _>(client, "/FaultyTrafficLights", skip)
+            .await
     }
 
     async fn get_road_details(
@@ -128,49 +203,39 @@ impl TrafficRequests<LTAClient> for Traffic {
         road_details_type: RoadDetailsType,
         skip: Option<u32>,
     ) -> LTAResult<Vec<RoadDetails>> {
-        let url = match road_details_type {
-            RoadDetailsType::RoadOpening => api_url!("/RoadOpenings"),
-            RoadDetailsType::RoadWorks => api_url!("/RoadWorks"),
+        let path = match road_details_type {
+            RoadDetailsType::RoadOpening => "/RoadOpenings",
+            RoadDetailsType::RoadWorks => "/RoadWorks",
             _ => return Err(LTAError::UnknownEnumVariant),
         };
 
-        build_req_with_skip::<RoadDetailsResp, _, _>(client, url, skip).await
+        build_req_with_skip::<RoadDetailsResp, _, _>(client, path, skip).await
     }
 
     async fn get_traffic_speed_band(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<TrafficSpeedBand>> {
-        build_req_with_skip::<TrafficSpeedBandResp, _, _>(
-            client,
-            api_url!("/TrafficSpeedBandsv2"),
-            skip,
-        )
-        .await
+        build_req_with_skip::<TrafficSpeedBandResp, _, _>(client, "/TrafficSpeedBandsv2", skip)
+            .await
     }
 
     async fn get_traffic_images(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<TrafficImage>> {
-        build_req_with_skip::<TrafficImageResp, _, _>(client, api_url!("/Traffic-Imagesv2"), skip)
-            .await
+        build_req_with_skip::<TrafficImageResp, _, _>(client, "/Traffic-Imagesv2", skip).await
     }
 
     async fn get_traffic_incidents(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<TrafficIncident>> {
-        build_req_with_skip::<TrafficIncidentResp, _, _>(
-            client,
-            api_url!("/TrafficIncidents"),
-            skip,
-        )
-        .await
+        build_req_with_skip::<TrafficIncidentResp, _, _>(client, "/TrafficIncidents", skip).await
     }
 
     async fn get_vms_emas(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<VMS>> {
-        build_req_with_skip::<VMSResp, _, _>(client, api_url!("/VMS"), skip).await
+        build_req_with_skip::<VMSResp, _, _>(client, "/VMS", skip).await
     }
 
     async fn get_bike_parking(
@@ -180,11 +245,9 @@ impl TrafficRequests<LTAClient> for Traffic {
         dist: Option<f64>,
     ) -> LTAResult<Vec<BikeParking>> {
         let unwrapped_dist = dist.unwrap_or(0.5);
-        build_req_with_query::<BikeParkingResp, _, _, _>(
-            client,
-            api_url!("/BicycleParkingv2"),
-            |rb| rb.query(&[("Lat", lat), ("Long", long), ("Dist", unwrapped_dist)]),
-        )
+        build_req_with_query::<BikeParkingResp, _, _, _>(client, "/BicycleParkingv2", |rb| {
+            rb.query(&[("Lat", lat), ("Long", long), ("Dist", unwrapped_dist)])
+        })
         .await
     }
 }
@@ -1,4 +1,3 @@
-use crate::api_url;
 use crate::models::train::prelude::*;
 use crate::r#async::{build_req_with_skip, LTAClient};
 use crate::{Client, LTAResult, Train};
@@ -20,11 +19,7 @@ impl TrainRequests<LTAClient> for Train {
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<TrainServiceAlert> {
-        build_req_with_skip::<TrainServiceAlertResp, _, _>(
-            client,
-            api_url!("/TrainServiceAlerts"),
-            skip,
-        )
-        .await
+        build_req_with_skip::<TrainServiceAlertResp, _, _>(client, "/TrainServiceAlerts", skip)
+            .await
     }
 }
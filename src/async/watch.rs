@@ -0,0 +1,423 @@
+//! Poll bus arrivals on a schedule and stream what changed between polls
+//!
+//! Only the first `NextBus` of each service is compared. The first poll of a stop reports every
+//! service as [`ArrivalEvent::ServiceAdded`], so that consumers start from a full picture.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use futures::TryStreamExt;
+//! use lta::r#async::watch::{watch, WatchOptions};
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let client = LTAClient::with_api_key("API_KEY")?;
+//!     let get_arrival = |stop| Bus::get_arrival(&client, stop, None);
+//!     let events = watch(get_arrival, vec![83139, 83111], WatchOptions::new());
+//!     futures::pin_mut!(events);
+//!
+//!     while let Some(event) = events.try_next().await? {
+//!         println!("{:?}", event);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::models::bus::bus_arrival::{ArrivalBusService, BusArrivalResp, NextBus};
+use crate::models::bus_enums::BusLoad;
+use crate::models::chrono::{DateTime, Duration as ChronoDuration, FixedOffset, Utc};
+use crate::LTAResult;
+use futures::stream::{self, Stream};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::time::Duration;
+
+/// Change in the arrivals at a bus stop since the previous poll
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrivalEvent {
+    /// Service that was not in the previous poll
+    ServiceAdded {
+        bus_stop_code: u32,
+        service: ArrivalBusService,
+    },
+
+    /// Service that is no longer returned, eg after its last bus
+    ServiceRemoved {
+        bus_stop_code: u32,
+        service_no: String,
+    },
+
+    /// Estimated arrival of the next bus changed, including when the next bus is a different bus
+    EtaChanged {
+        bus_stop_code: u32,
+        service_no: String,
+        previous: DateTime<FixedOffset>,
+        current: DateTime<FixedOffset>,
+    },
+
+    LoadChanged {
+        bus_stop_code: u32,
+        service_no: String,
+        previous: BusLoad,
+        current: BusLoad,
+    },
+
+    /// Next bus is estimated to arrive within [`WatchOptions::arriving_within`]. Reported once
+    /// per bus
+    Arriving {
+        bus_stop_code: u32,
+        service_no: String,
+        bus: NextBus,
+    },
+}
+
+/// Schedule of a [`watch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchOptions {
+    /// Time between the start of two polls, defaults to 1min, the update frequency of the API
+    pub interval: Duration,
+
+    /// A bus estimated to arrive within this is arriving, defaults to 1min
+    pub arriving_within: Duration,
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn arriving_within(mut self, arriving_within: Duration) -> Self {
+        self.arriving_within = arriving_within;
+        self
+    }
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions {
+            interval: Duration::from_secs(60),
+            arriving_within: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Arrivals at a stop as of a poll
+#[derive(Debug, Clone)]
+struct Snapshot {
+    taken_at: DateTime<Utc>,
+    services: Vec<ArrivalBusService>,
+}
+
+fn is_arriving(bus: &NextBus, at: DateTime<Utc>, within: ChronoDuration) -> bool {
+    bus.est_arrival.signed_duration_since(at) <= within
+}
+
+/// Returns `true` if the first bus of `current` is the first bus of `previous`, rather than the
+/// bus after it once the first has left. Compares the estimated arrivals of all the buses of both
+/// polls, as is and shifted by one bus
+fn is_same_first_bus(previous: &ArrivalBusService, current: &ArrivalBusService) -> bool {
+    let mean_gap = |shift: usize| {
+        let gaps: Vec<i64> = current
+            .next_bus
+            .iter()
+            .zip(previous.next_bus.iter().skip(shift))
+            .filter_map(|(c, p)| {
+                let gap = c
+                    .as_ref()?
+                    .est_arrival
+                    .signed_duration_since(p.as_ref()?.est_arrival);
+                Some(gap.num_seconds().abs())
+            })
+            .collect();
+        match gaps.len() {
+            0 => None,
+            n => Some(gaps.iter().sum::<i64>() / n as i64),
+        }
+    };
+
+    match (mean_gap(0), mean_gap(1)) {
+        (Some(same), Some(shifted)) => same <= shifted,
+        (same, _) => same.is_some(),
+    }
+}
+
+/// Returns the events between two polls of `bus_stop_code`, in the order of the current services
+fn diff(
+    bus_stop_code: u32,
+    previous: Option<&Snapshot>,
+    current: &Snapshot,
+    arriving_within: ChronoDuration,
+) -> Vec<ArrivalEvent> {
+    let previous_services: HashMap<&str, &ArrivalBusService> = previous
+        .map(|p| &p.services)
+        .into_iter()
+        .flatten()
+        .map(|s| (s.service_no.as_str(), s))
+        .collect();
+    let mut events = Vec::new();
+
+    for service in &current.services {
+        let service_no = service.service_no.clone();
+        let current_bus = service.next_bus[0].as_ref();
+        let previous_service = previous_services.get(service.service_no.as_str()).copied();
+        if previous_service.is_none() {
+            events.push(ArrivalEvent::ServiceAdded {
+                bus_stop_code,
+                service: service.clone(),
+            });
+        }
+        let previous_bus = previous_service.and_then(|p| p.next_bus[0].as_ref());
+
+        if let (Some(prev), Some(curr)) = (previous_bus, current_bus) {
+            if prev.est_arrival != curr.est_arrival {
+                events.push(ArrivalEvent::EtaChanged {
+                    bus_stop_code,
+                    service_no: service_no.clone(),
+                    previous: prev.est_arrival,
+                    current: curr.est_arrival,
+                });
+            }
+            if prev.load != curr.load {
+                events.push(ArrivalEvent::LoadChanged {
+                    bus_stop_code,
+                    service_no: service_no.clone(),
+                    previous: prev.load.clone(),
+                    current: curr.load.clone(),
+                });
+            }
+        }
+
+        if let Some(bus) = current_bus {
+            // The first bus of the previous poll has been reported if it was arriving, but the bus
+            // after it has not, eg when two buses of a service arrive back to back
+            let reported = match (previous, previous_service, previous_bus) {
+                (Some(p), Some(previous_service), Some(previous_bus)) => {
+                    is_arriving(previous_bus, p.taken_at, arriving_within)
+                        && is_same_first_bus(previous_service, service)
+                }
+                _ => false,
+            };
+            if !reported && is_arriving(bus, current.taken_at, arriving_within) {
+                events.push(ArrivalEvent::Arriving {
+                    bus_stop_code,
+                    service_no,
+                    bus: bus.clone(),
+                });
+            }
+        }
+    }
+
+    let current_services: Vec<&str> = current
+        .services
+        .iter()
+        .map(|s| s.service_no.as_str())
+        .collect();
+    for service in previous.map(|p| &p.services).into_iter().flatten() {
+        if !current_services.contains(&service.service_no.as_str()) {
+            events.push(ArrivalEvent::ServiceRemoved {
+                bus_stop_code,
+                service_no: service.service_no.clone(),
+            });
+        }
+    }
+
+    events
+}
+
+struct Watcher<F> {
+    get_arrival: F,
+    bus_stop_codes: Vec<u32>,
+    opts: WatchOptions,
+    snapshots: HashMap<u32, Snapshot>,
+    pending: VecDeque<LTAResult<ArrivalEvent>>,
+    polled: bool,
+}
+
+impl<F, Fut> Watcher<F>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = LTAResult<BusArrivalResp>>,
+{
+    /// Requests every stop once, queueing their events. A stop that fails keeps its previous
+    /// snapshot, so that its changes are reported by the next successful poll
+    async fn poll(&mut self) {
+        let arriving_within =
+            ChronoDuration::from_std(self.opts.arriving_within).unwrap_or(ChronoDuration::MAX);
+
+        for &bus_stop_code in &self.bus_stop_codes {
+            let services = match (self.get_arrival)(bus_stop_code).await {
+                Ok(resp) => resp.services,
+                Err(e) => {
+                    self.pending.push_back(Err(e));
+                    continue;
+                }
+            };
+
+            let current = Snapshot {
+                taken_at: Utc::now(),
+                services,
+            };
+            let previous = self.snapshots.get(&bus_stop_code);
+            let events = diff(bus_stop_code, previous, &current, arriving_within);
+            self.pending.extend(events.into_iter().map(Ok));
+            self.snapshots.insert(bus_stop_code, current);
+        }
+    }
+}
+
+/// Returns a `Stream` of the changes in the arrivals at `bus_stop_codes`. Every stop is requested
+/// with `get_arrival` once per [`WatchOptions::interval`], one at a time so that rate limits are
+/// respected. Errors are yielded without ending the stream, which never ends unless no stops are
+/// given
+pub fn watch<F, Fut>(
+    get_arrival: F,
+    bus_stop_codes: Vec<u32>,
+    opts: WatchOptions,
+) -> impl Stream<Item = LTAResult<ArrivalEvent>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = LTAResult<BusArrivalResp>>,
+{
+    let watcher = Watcher {
+        get_arrival,
+        bus_stop_codes,
+        opts,
+        snapshots: HashMap::new(),
+        pending: VecDeque::new(),
+        polled: false,
+    };
+
+    stream::unfold(watcher, |mut watcher| async move {
+        loop {
+            if let Some(event) = watcher.pending.pop_front() {
+                return Some((event, watcher));
+            }
+            if watcher.bus_stop_codes.is_empty() {
+                return None;
+            }
+
+            if watcher.polled {
+                tokio::time::sleep(watcher.opts.interval).await;
+            }
+            watcher.polled = true;
+            watcher.poll().await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bus_enums::{BusFeature, BusType, Operator};
+
+    fn service(service_no: &str, est_arrival: DateTime<Utc>, load: BusLoad) -> ArrivalBusService {
+        let bus = NextBus {
+            origin_code: 77009,
+            dest_code: 77009,
+            est_arrival: est_arrival.with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()),
+            lat: 1.3,
+            long: 103.9,
+            visit_no: 1,
+            load,
+            feature: Some(BusFeature::WheelChairAccessible),
+            bus_type: BusType::SingleDecker,
+        };
+        ArrivalBusService {
+            service_no: service_no.to_string(),
+            operator: Operator::SBST,
+            next_bus: [Some(bus), None, None],
+        }
+    }
+
+    #[test]
+    fn reports_changes_between_polls() {
+        let t0 = Utc::now();
+        let minutes = ChronoDuration::minutes;
+        let within = minutes(1);
+        let first = Snapshot {
+            taken_at: t0,
+            services: vec![
+                service("15", t0 + minutes(3), BusLoad::SeatsAvailable),
+                service("155", t0 + minutes(10), BusLoad::SeatsAvailable),
+            ],
+        };
+        let events = diff(83139, None, &first, within);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ArrivalEvent::ServiceAdded { .. }));
+
+        let second = Snapshot {
+            taken_at: t0 + minutes(1),
+            services: vec![
+                service("15", t0 + minutes(2), BusLoad::StandingAvailable),
+                service("43", t0 + minutes(20), BusLoad::SeatsAvailable),
+            ],
+        };
+        let events = diff(83139, Some(&first), &second, within);
+        let kinds: Vec<(&str, &str)> = events
+            .iter()
+            .map(|e| match e {
+                ArrivalEvent::ServiceAdded { service, .. } => {
+                    ("added", service.service_no.as_str())
+                }
+                ArrivalEvent::ServiceRemoved { service_no, .. } => ("removed", service_no.as_str()),
+                ArrivalEvent::EtaChanged { service_no, .. } => ("eta", service_no.as_str()),
+                ArrivalEvent::LoadChanged { service_no, .. } => ("load", service_no.as_str()),
+                ArrivalEvent::Arriving { service_no, .. } => ("arriving", service_no.as_str()),
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("eta", "15"),
+                ("load", "15"),
+                ("arriving", "15"),
+                ("added", "43"),
+                ("removed", "155"),
+            ]
+        );
+
+        // Already reported as arriving
+        let third = Snapshot {
+            taken_at: t0 + minutes(2),
+            services: second.services.clone(),
+        };
+        assert!(diff(83139, Some(&second), &third, within).is_empty());
+    }
+
+    #[test]
+    fn reports_back_to_back_arrivals() {
+        let t0 = Utc::now();
+        let minutes = ChronoDuration::minutes;
+        let within = minutes(1);
+        let seconds = ChronoDuration::seconds;
+        let arrivals = |taken_at, first: DateTime<Utc>, second: DateTime<Utc>| {
+            let mut service = service("15", first, BusLoad::SeatsAvailable);
+            let mut next = service.next_bus[0].clone().unwrap();
+            next.est_arrival = second.with_timezone(&next.est_arrival.timezone());
+            service.next_bus[1] = Some(next);
+            Snapshot {
+                taken_at,
+                services: vec![service],
+            }
+        };
+        let arriving = |events: Vec<ArrivalEvent>| {
+            events
+                .iter()
+                .filter(|e| matches!(e, ArrivalEvent::Arriving { .. }))
+                .count()
+        };
+
+        // Bus A arrives, then bus B is already within a minute of the stop when A has left
+        let first = arrivals(t0, t0 + seconds(30), t0 + minutes(2));
+        let second = arrivals(t0 + minutes(1), t0 + seconds(50), t0 + minutes(9));
+        let third = arrivals(t0 + minutes(2), t0 + seconds(110), t0 + minutes(9));
+        assert_eq!(arriving(diff(83139, None, &first, within)), 1);
+        assert_eq!(arriving(diff(83139, Some(&first), &second, within)), 1);
+        // Bus B is still arriving, and has been reported
+        assert_eq!(arriving(diff(83139, Some(&second), &third, within)), 0);
+    }
+}
@@ -0,0 +1,590 @@
+//! `lta` command line client, behind the `cli` feature
+//!
+//! Reads the API key from `API_KEY` and prints results as an aligned table, JSON or CSV, eg
+//! `lta bus arrival 83139 --service 15` or `lta --format csv traffic carparks --all`. Requests
+//! are sent to `LTA_BASE_URL` instead of Datamall if it is set, eg to use a proxy.
+use clap::{Parser, Subcommand, ValueEnum};
+use lta::export::{CsvWriter, Record};
+use lta::geojson::ToFeatureCollection;
+use lta::geospatial::{FieldValue, Geometry, Shapefile};
+use lta::models::chrono::NaiveDate;
+use lta::models::crowd::passenger_vol::VolType;
+use lta::models::geo::geospatial_whole_island::GeospatialLayerId;
+use lta::models::train::prelude::TrainServiceAlert;
+use lta::passenger_vol::{NodeVolume, OdVolume, PassengerVolRecord, YearMonth};
+use lta::{
+    Bus, BusRequests, Client, Crowd, CrowdRequests, Geo, GeoRequests, LTAClient, LTAError,
+    LTAResult, Taxi, TaxiRequests, Traffic, TrafficRequests, Train, TrainRequests,
+};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, Write};
+
+#[derive(Debug, Parser)]
+#[command(name = "lta", version, about = "Query the LTA Datamall APIs")]
+struct Cli {
+    /// Output format
+    #[arg(short, long, value_enum, global = true, default_value_t = Format::Table)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Bus arrivals and bus stops
+    #[command(subcommand)]
+    Bus(BusCommand),
+
+    /// ERP rates and car park availability
+    #[command(subcommand)]
+    Traffic(TrafficCommand),
+
+    /// Taxi availability
+    #[command(subcommand)]
+    Taxi(TaxiCommand),
+
+    /// Train service alerts
+    #[command(subcommand)]
+    Train(TrainCommand),
+
+    /// Passenger volumes
+    #[command(subcommand)]
+    Crowd(CrowdCommand),
+
+    /// Geospatial layers
+    #[command(subcommand)]
+    Geo(GeoCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum BusCommand {
+    /// Arrivals of every service, or one service, at a bus stop
+    Arrival {
+        stop: u32,
+
+        #[arg(long)]
+        service: Option<String>,
+    },
+
+    /// Bus stops
+    Stops {
+        /// Request every page instead of the first 500 records
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TrafficCommand {
+    /// ERP rates
+    Erp {
+        /// Request every page instead of the first 500 records
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Car park availability
+    Carparks {
+        /// Request every page instead of the first 500 records
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TaxiCommand {
+    /// Locations of available taxis
+    Avail {
+        /// Request every page instead of the first 500 records
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TrainCommand {
+    /// Train service alerts. Tables and CSV list the status and messages, then the affected
+    /// segments if any
+    Alerts,
+}
+
+#[derive(Debug, Subcommand)]
+enum CrowdCommand {
+    /// Downloads passenger volumes, eg `BusStops`, `OdBusStop`, `Train` or `OdTrain`
+    PassengerVol {
+        #[arg(value_parser = parse_variant::<VolType>)]
+        vol_type: VolType,
+
+        /// Month of the volumes, eg `2020-12`. Defaults to the latest month
+        #[arg(long)]
+        date: Option<YearMonth>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum GeoCommand {
+    /// Downloads a geospatial layer, eg `ArrowMarking`. JSON is a GeoJSON feature collection
+    Layer {
+        #[arg(value_parser = parse_variant::<GeospatialLayerId>)]
+        id: GeospatialLayerId,
+    },
+}
+
+/// Parses enums of the models by the name of their variant, rejecting the `Unknown` catch all
+fn parse_variant<T: DeserializeOwned + Serialize>(s: &str) -> Result<T, String> {
+    serde_json::from_value::<T>(serde_json::Value::String(s.to_string()))
+        .map_err(|_| format!("unknown variant `{}`", s))
+        .and_then(|v| match serde_json::to_value(&v) {
+            Ok(serde_json::Value::String(name)) if name == "Unknown" => {
+                Err(format!("unknown variant `{}`", s))
+            }
+            _ => Ok(v),
+        })
+}
+
+/// Rows to print as a table or CSV
+struct Table {
+    columns: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    fn from_records<T: Record>(records: &[T]) -> Self {
+        Table {
+            columns: T::columns(),
+            rows: records.iter().flat_map(Record::rows).collect(),
+        }
+    }
+
+    /// Width of the widest cell of each column
+    fn widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.columns.iter().map(|c| c.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        widths
+    }
+
+    /// Columns are padded to their widest cell and separated by two spaces
+    fn render<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let widths = self.widths();
+        for row in std::iter::once(&self.columns).chain(&self.rows) {
+            write_row(&mut w, row, &widths)?;
+        }
+        Ok(())
+    }
+
+    fn write_csv<W: Write>(&self, w: W) -> LTAResult<()> {
+        let mut csv = csv::Writer::from_writer(w);
+        std::iter::once(&self.columns)
+            .chain(&self.rows)
+            .try_for_each(|row| csv.write_record(row))
+            .and_then(|_| Ok(csv.flush()?))
+            .map_err(|e| LTAError::Custom(format!("Unable to write CSV: {}", e)))
+    }
+}
+
+fn write_row<W: Write>(mut w: W, row: &[String], widths: &[usize]) -> io::Result<()> {
+    let line: Vec<String> = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{:width$}", cell, width = width))
+        .collect();
+    writeln!(w, "{}", line.join("  ").trim_end())
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> LTAResult<()> {
+    let stdout = io::stdout();
+    serde_json::to_writer_pretty(stdout.lock(), value)
+        .map_err(|e| LTAError::Custom(format!("Unable to write JSON: {}", e)))?;
+    println!();
+    Ok(())
+}
+
+fn print_table(table: &Table, format: Format) -> LTAResult<()> {
+    let stdout = io::stdout();
+    match format {
+        Format::Csv => table.write_csv(stdout.lock()),
+        _ => table
+            .render(stdout.lock())
+            .map_err(|e| LTAError::Custom(format!("Unable to write table: {}", e))),
+    }
+}
+
+fn print_records<T: Record + Serialize>(records: &[T], format: Format) -> LTAResult<()> {
+    match format {
+        Format::Json => print_json(records),
+        Format::Csv => {
+            let mut csv = CsvWriter::new(io::stdout());
+            csv.write_all(records)?;
+            csv.flush()
+        }
+        Format::Table => print_table(&Table::from_records(records), format),
+    }
+}
+
+/// Writes the status and messages of `alert`, then its affected segments as a second table or
+/// CSV section after a blank line. Segments are only listed during a disruption
+fn write_train_alert<W: Write>(
+    alert: &TrainServiceAlert,
+    format: Format,
+    mut w: W,
+) -> LTAResult<()> {
+    let write_err = |e: io::Error| LTAError::Custom(format!("Unable to write output: {}", e));
+    if format == Format::Json {
+        serde_json::to_writer_pretty(&mut w, alert)
+            .map_err(|e| LTAError::Custom(format!("Unable to write JSON: {}", e)))?;
+        return writeln!(w).map_err(write_err);
+    }
+
+    let mut tables = vec![Table::from_records(std::slice::from_ref(alert))];
+    if !alert.affected_segments.is_empty() {
+        tables.push(Table::from_records(&alert.affected_segments));
+    }
+
+    for (i, table) in tables.iter().enumerate() {
+        if i > 0 {
+            writeln!(w).map_err(write_err)?;
+        }
+        match format {
+            Format::Csv => table.write_csv(&mut w)?,
+            _ => table.render(&mut w).map_err(write_err)?,
+        }
+    }
+    Ok(())
+}
+
+/// Rows used to size the columns of a table that is printed as its records are decoded
+const TABLE_SAMPLE: usize = 1000;
+
+fn passenger_vol_rows(record: &PassengerVolRecord) -> (Vec<String>, Vec<Vec<String>>) {
+    match record {
+        PassengerVolRecord::Node(node) => (NodeVolume::columns(), node.rows()),
+        PassengerVolRecord::Od(od) => (OdVolume::columns(), od.rows()),
+    }
+}
+
+/// Writes passenger volumes as they are decoded, as the datasets have millions of rows. Tables
+/// are sized on their first rows, wider cells after them are not aligned
+fn write_passenger_vol<I, W>(records: I, format: Format, mut w: W) -> LTAResult<()>
+where
+    I: IntoIterator<Item = LTAResult<PassengerVolRecord>>,
+    W: Write,
+{
+    let write_err = |e: io::Error| LTAError::Custom(format!("Unable to write output: {}", e));
+    let mut records = records.into_iter();
+
+    match format {
+        Format::Csv => {
+            let mut csv = CsvWriter::new(w);
+            for record in records {
+                match record? {
+                    PassengerVolRecord::Node(node) => csv.write(&node)?,
+                    PassengerVolRecord::Od(od) => csv.write(&od)?,
+                }
+            }
+            csv.flush()
+        }
+        Format::Json => {
+            write!(w, "[").map_err(write_err)?;
+            for (i, record) in records.enumerate() {
+                let separator = if i == 0 { "\n" } else { ",\n" };
+                write!(w, "{}", separator).map_err(write_err)?;
+                let written = match record? {
+                    PassengerVolRecord::Node(node) => serde_json::to_writer_pretty(&mut w, &node),
+                    PassengerVolRecord::Od(od) => serde_json::to_writer_pretty(&mut w, &od),
+                };
+                written.map_err(|e| LTAError::Custom(format!("Unable to write JSON: {}", e)))?;
+            }
+            writeln!(w, "\n]").map_err(write_err)
+        }
+        Format::Table => {
+            let mut sample = Table {
+                columns: Vec::new(),
+                rows: Vec::new(),
+            };
+            for record in records.by_ref().take(TABLE_SAMPLE) {
+                let (columns, rows) = passenger_vol_rows(&record?);
+                sample.columns = columns;
+                sample.rows.extend(rows);
+            }
+            if sample.rows.is_empty() {
+                return Ok(());
+            }
+
+            let widths = sample.widths();
+            sample.render(&mut w).map_err(write_err)?;
+            for record in records {
+                let (_, rows) = passenger_vol_rows(&record?);
+                for row in rows {
+                    write_row(&mut w, &row, &widths).map_err(write_err)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// One row per feature, with the first point of its geometry in WGS84 and then its attributes
+fn layer_table(shapefiles: &[Shapefile]) -> Table {
+    let mut columns = vec!["layer", "geometry", "lat", "long"]
+        .into_iter()
+        .map(String::from)
+        .collect::<Vec<_>>();
+    for field in shapefiles.iter().flat_map(|s| &s.fields) {
+        if !columns.contains(field) {
+            columns.push(field.clone());
+        }
+    }
+
+    let mut rows = Vec::new();
+    for shapefile in shapefiles {
+        for feature in &shapefile.features {
+            let (kind, first) = match &feature.geometry {
+                Geometry::Null => ("Null", None),
+                Geometry::Point(p) => ("Point", Some(p)),
+                Geometry::MultiPoint(points) => ("MultiPoint", points.first()),
+                Geometry::PolyLine(parts) => ("PolyLine", parts.iter().flatten().next()),
+                Geometry::Polygon(rings) => ("Polygon", rings.iter().flatten().next()),
+            };
+            let coords = first.map(|p| p.to_wgs84());
+
+            let mut row = vec![
+                shapefile.name.clone(),
+                kind.to_string(),
+                coords
+                    .as_ref()
+                    .map(|c| c.lat.to_string())
+                    .unwrap_or_default(),
+                coords
+                    .as_ref()
+                    .map(|c| c.long.to_string())
+                    .unwrap_or_default(),
+            ];
+            row.extend(columns[4..].iter().map(|field| {
+                feature
+                    .attributes
+                    .get(field)
+                    .map(|value| match value {
+                        FieldValue::Text(s) => s.clone(),
+                        FieldValue::Number(n) => n.to_string(),
+                        FieldValue::Logical(b) => b.to_string(),
+                        FieldValue::Date(d) => d.to_string(),
+                        FieldValue::Null => String::new(),
+                    })
+                    .unwrap_or_default()
+            }));
+            rows.push(row);
+        }
+    }
+
+    Table { columns, rows }
+}
+
+async fn run(cli: Cli, client: &LTAClient) -> LTAResult<()> {
+    let format = cli.format;
+    match cli.command {
+        Command::Bus(BusCommand::Arrival { stop, service }) => {
+            let arrival = Bus::get_arrival(client, stop, service.as_deref()).await?;
+            match format {
+                Format::Json => print_json(&arrival),
+                _ => print_records(std::slice::from_ref(&arrival), format),
+            }
+        }
+        Command::Bus(BusCommand::Stops { all }) => {
+            let stops = if all {
+                Bus::get_all_bus_stops(client).await?
+            } else {
+                Bus::get_bus_stops(client, None).await?
+            };
+            print_records(&stops, format)
+        }
+        Command::Traffic(TrafficCommand::Erp { all }) => {
+            let rates = if all {
+                Traffic::get_all_erp_rates(client).await?
+            } else {
+                Traffic::get_erp_rates(client, None).await?
+            };
+            print_records(&rates, format)
+        }
+        Command::Traffic(TrafficCommand::Carparks { all }) => {
+            let carparks = if all {
+                Traffic::get_all_carpark_avail(client).await?
+            } else {
+                Traffic::get_carpark_avail(client, None).await?
+            };
+            print_records(&carparks, format)
+        }
+        Command::Taxi(TaxiCommand::Avail { all }) => {
+            let taxis = if all {
+                Taxi::get_all_taxi_avail(client).await?
+            } else {
+                Taxi::get_taxi_avail(client, None).await?
+            };
+            print_records(&taxis, format)
+        }
+        Command::Train(TrainCommand::Alerts) => {
+            let alert = Train::get_train_service_alert(client, None).await?;
+            let stdout = io::stdout();
+            write_train_alert(&alert, format, stdout.lock())
+        }
+        Command::Crowd(CrowdCommand::PassengerVol { vol_type, date }) => {
+            let date = date.and_then(|ym| NaiveDate::from_ymd_opt(ym.year, ym.month, 1));
+            let records = Crowd::get_passenger_vol_records(client, vol_type, date).await?;
+            let stdout = io::stdout();
+            write_passenger_vol(records, format, io::BufWriter::new(stdout.lock()))
+        }
+        Command::Geo(GeoCommand::Layer { id }) => {
+            let shapefiles = Geo::get_geospatial_layer(client, id).await?;
+            match format {
+                Format::Json => {
+                    let features: Vec<_> =
+                        shapefiles.into_iter().flat_map(|s| s.features).collect();
+                    print_json(&features.to_feature_collection())
+                }
+                _ => print_table(&layer_table(&shapefiles), format),
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let result = match std::env::var("API_KEY") {
+        Ok(api_key) => match LTAClient::with_api_key(api_key) {
+            Ok(client) => {
+                let client = match std::env::var("LTA_BASE_URL") {
+                    Ok(base_url) => client.with_base_url(base_url),
+                    Err(_) => client,
+                };
+                run(cli, &client).await
+            }
+            Err(e) => Err(e),
+        },
+        Err(_) => Err(LTAError::Custom("API_KEY is not set".to_string())),
+    };
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lta::passenger_vol::PassengerVolRecords;
+
+    #[test]
+    fn aligns_table_columns() {
+        let table = Table {
+            columns: vec!["service_no".to_string(), "operator".to_string()],
+            rows: vec![
+                vec!["15".to_string(), "GAS".to_string()],
+                vec!["1N".to_string(), "SBST".to_string()],
+            ],
+        };
+        let mut out = Vec::new();
+        table.render(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "service_no  operator\n15          GAS\n1N          SBST\n"
+        );
+    }
+
+    #[test]
+    fn writes_passenger_vol_as_decoded() {
+        let csv = "YEAR_MONTH,DAY_TYPE,TIME_PER_HOUR,PT_TYPE,PT_CODE,TOTAL_TAP_IN_VOLUME,\
+                   TOTAL_TAP_OUT_VOLUME\n2020-12,WEEKDAY,17,BUS,01012,18,6\n\
+                   2020-12,WEEKDAY,18,BUS,01012,20,7\n";
+        let records = || PassengerVolRecords::from_csv(io::Cursor::new(csv)).unwrap();
+
+        let mut out = Vec::new();
+        write_passenger_vol(records(), Format::Json, &mut out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+
+        let mut out = Vec::new();
+        write_passenger_vol(records(), Format::Csv, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+
+        // Rows already decoded are written before an error
+        let failing = records().chain(std::iter::once(Err(LTAError::InvalidAPIKey)));
+        let mut out = Vec::new();
+        assert!(write_passenger_vol(failing, Format::Table, &mut out).is_err());
+        let mut out = Vec::new();
+        let failing = records().chain(std::iter::once(Err(LTAError::InvalidAPIKey)));
+        assert!(write_passenger_vol(failing, Format::Csv, &mut out).is_err());
+        assert_eq!(String::from_utf8(out).unwrap().lines().count(), 3);
+    }
+
+    #[test]
+    fn writes_train_alert_status_messages_and_segments() {
+        let json = r#"{
+            "Status": 2,
+            "AffectedSegments": [{
+                "Line": "NSL",
+                "Direction": "Jurong East",
+                "Stations": "NS1-NS2-NS3",
+                "FreePublicBus": "NS1-NS2-NS3",
+                "FreeMrtShuttle": "NS1-NS2-NS3",
+                "MRTShuttleDirection": "Jurong East"
+            }],
+            "Message": [{
+                "Content": "1710hrs : NSL - No train service between Jurong East and Choa Chu Kang",
+                "CreatedDate": "2017-12-01 17:54:21"
+            }]
+        }"#;
+        let alert: TrainServiceAlert = serde_json::from_str(json).unwrap();
+
+        let mut out = Vec::new();
+        write_train_alert(&alert, Format::Csv, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "status,content,created_date");
+        assert!(lines[1].starts_with("2,1710hrs"));
+        assert_eq!(lines[2], "");
+        assert!(lines[3].starts_with("line,direction,stations"));
+        assert!(lines[4].starts_with("NSL,Jurong East"));
+
+        // Normal service still prints its status
+        let normal: TrainServiceAlert =
+            serde_json::from_str(r#"{"Status": 1, "AffectedSegments": [], "Message": []}"#)
+                .unwrap();
+        let mut out = Vec::new();
+        write_train_alert(&normal, Format::Table, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            vec!["status  content  created_date", "1"]
+        );
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let cli =
+            Cli::try_parse_from(["lta", "-f", "csv", "crowd", "passenger-vol", "OdTrain"]).unwrap();
+        assert_eq!(cli.format, Format::Csv);
+        assert!(matches!(
+            cli.command,
+            Command::Crowd(CrowdCommand::PassengerVol {
+                vol_type: VolType::OdTrain,
+                date: None
+            })
+        ));
+        assert!(Cli::try_parse_from(["lta", "crowd", "passenger-vol", "Unknown"]).is_err());
+    }
+}
@@ -1,7 +1,10 @@
-use crate::blocking::{build_req_with_query, build_req_with_skip, LTAClient};
+use crate::blocking::{build_req_with_query, build_req_with_skip, fetch_all_pages, LTAClient};
 use crate::models::bus::prelude::*;
+use crate::r#async::bus::ArrivalQuery;
+use crate::timetable::RawBusRouteResp;
 use crate::LTAResult;
-use crate::{api_url, Bus, Client};
+use crate::{Bus, Client};
+use std::collections::HashMap;
 
 /// All API pertaining to buses
 pub trait BusRequests<C: Client> {
@@ -40,6 +43,41 @@ pub trait BusRequests<C: Client> {
     ///
     /// **Update freq**: Ad-Hoc
     fn get_bus_stops(client: &C, skip: Option<u32>) -> LTAResult<Vec<BusStop>>;
+
+    /// Returns the arrivals at every stop of `queries`, keyed by bus stop code. Stops are
+    /// requested one at a time, each through the client's rate limiter and retry policy. A stop
+    /// that cannot be requested has its error in the map, without failing the other stops.
+    /// Queries of the same stop are combined into one request
+    fn get_arrivals(
+        client: &C,
+        queries: Vec<ArrivalQuery>,
+    ) -> HashMap<u32, LTAResult<BusArrivalResp>> {
+        ArrivalQuery::merge(queries)
+            .into_iter()
+            .map(|query| {
+                let resp = Self::get_arrival(client, query.bus_stop_code, query.service_no());
+                (query.bus_stop_code, resp.map(|resp| query.filter(resp)))
+            })
+            .collect()
+    }
+
+    /// Same as [`get_bus_services`](BusRequests::get_bus_services), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_bus_services(client: &C) -> LTAResult<Vec<BusService>> {
+        fetch_all_pages(|skip| Self::get_bus_services(client, skip))
+    }
+
+    /// Same as [`get_bus_routes`](BusRequests::get_bus_routes), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_bus_routes(client: &C) -> LTAResult<Vec<BusRoute>> {
+        fetch_all_pages(|skip| Self::get_bus_routes(client, skip))
+    }
+
+    /// Same as [`get_bus_stops`](BusRequests::get_bus_stops), but keeps requesting with increasing
+    /// `skip` until every record is returned
+    fn get_all_bus_stops(client: &C) -> LTAResult<Vec<BusStop>> {
+        fetch_all_pages(|skip| Self::get_bus_stops(client, skip))
+    }
 }
 
 impl BusRequests<LTAClient> for Bus {
@@ -48,29 +86,31 @@ impl BusRequests<LTAClient> for Bus {
         bus_stop_code: u32,
         service_no: Option<&str>,
     ) -> LTAResult<BusArrivalResp> {
-        let url = api_url!("/BusArrivalv2");
+        let path = "/BusArrivalv2";
         match service_no {
-            Some(srv_no) => build_req_with_query::<RawBusArrivalResp, _, _, _>(client, url, |rb| {
-                rb.query(&[
-                    ("BusStopCode", bus_stop_code.to_string().as_str()),
-                    ("ServiceNo", srv_no),
-                ])
-            }),
-            None => build_req_with_query::<RawBusArrivalResp, _, _, _>(client, url, |rb| {
+            Some(srv_no) => {
+                build_req_with_query::<RawBusArrivalResp, _, _, _>(client, path, |rb| {
+                    rb.query(&[
+                        ("BusStopCode", bus_stop_code.to_string().as_str()),
+                        ("ServiceNo", srv_no),
+                    ])
+                })
+            }
+            None => build_req_with_query::<RawBusArrivalResp, _, _, _>(client, path, |rb| {
                 rb.query(&[("BusStopCode", bus_stop_code.to_string())])
             }),
         }
     }
 
     fn get_bus_services(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusService>> {
-        build_req_with_skip::<BusServiceResp, _, _>(client, api_url!("/BusServices"), skip)
+        build_req_with_skip::<BusServiceResp, _, _>(client, "/BusServices", skip)
     }
 
     fn get_bus_routes(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusRoute>> {
-        build_req_with_skip::<BusRouteResp, _, _>(client, api_url!("/BusRoutes"), skip)
+        build_req_with_skip::<RawBusRouteResp, _, _>(client, "/BusRoutes", skip)
     }
 
     fn get_bus_stops(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<BusStop>> {
-        build_req_with_skip::<BusStopsResp, _, _>(client, api_url!("/BusStops"), skip)
+        build_req_with_skip::<BusStopsResp, _, _>(client, "/BusStops", skip)
     }
 }
@@ -1,7 +1,8 @@
-use crate::blocking::{build_req_with_query, build_req_with_skip, LTAClient};
+use crate::blocking::{build_req_with_query, build_req_with_skip, download_link, LTAClient};
 use crate::models::chrono::NaiveDate;
 use crate::models::crowd::passenger_vol;
-use crate::{vol_type_to_url, Client, Crowd, LTAResult};
+use crate::passenger_vol::PassengerVolRecords;
+use crate::{vol_type_to_path, Client, Crowd, LTAError, LTAResult};
 use lta_models::crowd::passenger_vol::VolType;
 
 /// All APIs pertaining to transportation crowd
@@ -17,6 +18,27 @@ pub trait CrowdRequests<C: Client> {
         date: Option<NaiveDate>,
         skip: Option<u32>,
     ) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_passenger_vol_by`](CrowdRequests::get_passenger_vol_by), but follows the
+    /// link before it expires and decodes the CSV file in the archive into
+    /// [`PassengerVolRecord`](crate::passenger_vol::PassengerVolRecord)s.
+    ///
+    /// The archive is downloaded into memory, records are decoded as the iterator is advanced
+    fn get_passenger_vol_records(
+        client: &C,
+        vol_type: passenger_vol::VolType,
+        date: Option<NaiveDate>,
+    ) -> LTAResult<PassengerVolRecords>
+    where
+        C: Client<RB = reqwest::blocking::RequestBuilder>,
+    {
+        let links = Self::get_passenger_vol_by(client, vol_type, date, None)?;
+        let link = links
+            .first()
+            .ok_or_else(|| LTAError::Custom("No passenger volume link returned".to_string()))?;
+        let archive = download_link(client, link)?;
+        PassengerVolRecords::from_zip(archive)
+    }
 }
 
 impl CrowdRequests<LTAClient> for Crowd {
@@ -28,16 +50,16 @@ impl CrowdRequests<LTAClient> for Crowd {
     ) -> LTAResult<Vec<String>> {
         let fmt_date = date.map(|f| f.format(passenger_vol::FORMAT).to_string());
 
-        let url = vol_type_to_url(vol_type)?;
+        let path = vol_type_to_path(vol_type)?;
 
         match fmt_date {
             Some(nd) => build_req_with_query::<passenger_vol::PassengerVolRawResp, _, _, _>(
                 client,
-                url,
+                path,
                 |rb| rb.query(&[("Date", nd)]),
             ),
             None => {
-                build_req_with_skip::<passenger_vol::PassengerVolRawResp, _, _>(client, url, skip)
+                build_req_with_skip::<passenger_vol::PassengerVolRawResp, _, _>(client, path, skip)
             }
         }
     }
@@ -1,5 +1,7 @@
-use crate::blocking::{build_req_with_query, LTAClient};
+use crate::blocking::{build_req_with_query, download_link, LTAClient};
+use crate::facilities::{parse_maintenance, station_codes, StationMaintenance};
 use crate::models::facility::facilities_maintenance::FacilityMaintenanceRawResp;
+use crate::models::train::prelude::MrtLine;
 use crate::models::train::StationCode;
 use crate::{Client, Facility, LTAResult};
 
@@ -8,6 +10,45 @@ pub trait FacilityReqeusts<C: Client> {
     ///
     /// **Update Freq**: Adhoc
     fn get_facilities_maintenance(client: &C, station_code: StationCode) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_facilities_maintenance`](FacilityReqeusts::get_facilities_maintenance), but
+    /// downloads the linked files and returns the maintenance schedules of `station_code`.
+    /// Schedules in the files for other station codes are returned in
+    /// [`unmatched`](StationMaintenance::unmatched)
+    fn get_facilities_maintenance_records(
+        client: &C,
+        station_code: StationCode,
+    ) -> LTAResult<StationMaintenance>
+    where
+        C: Client<RB = reqwest::blocking::RequestBuilder>,
+    {
+        let mut records = Vec::new();
+        for link in Self::get_facilities_maintenance(client, station_code.clone())? {
+            let json = download_link(client, &link)?;
+            records.extend(parse_maintenance(&json, &link)?);
+        }
+
+        Ok(StationMaintenance::split(records, &station_code))
+    }
+
+    /// Returns the maintenance schedules of every station of `line`, in station order. Stations
+    /// are requested one at a time. A station that cannot be requested has its error in its
+    /// place, without failing the other stations
+    fn get_line_facilities_maintenance(
+        client: &C,
+        line: MrtLine,
+    ) -> Vec<(StationCode, LTAResult<StationMaintenance>)>
+    where
+        C: Client<RB = reqwest::blocking::RequestBuilder>,
+    {
+        station_codes(&line)
+            .into_iter()
+            .map(|code| {
+                let records = Self::get_facilities_maintenance_records(client, code.clone());
+                (code, records)
+            })
+            .collect()
+    }
 }
 
 impl FacilityReqeusts<LTAClient> for Facility {
@@ -17,7 +58,7 @@ impl FacilityReqeusts<LTAClient> for Facility {
     ) -> LTAResult<Vec<String>> {
         build_req_with_query::<FacilityMaintenanceRawResp, _, _, _>(
             client,
-            api_url!("/FacilitiesMaintenance"),
+            "/FacilitiesMaintenance",
             |rb| rb.query(&[("StationCode", station_code)]),
         )
     }
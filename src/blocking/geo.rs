@@ -1,4 +1,5 @@
-use crate::blocking::{build_req_with_query, LTAClient};
+use crate::blocking::{build_req_with_query, download_link, LTAClient};
+use crate::geospatial::Shapefile;
 use crate::models::geo::geospatial_whole_island::{
     GeospatialLayerId, GeospatialWholeIslandRawResp,
 };
@@ -9,6 +10,22 @@ pub trait GeoRequests<C: Client> {
     ///
     /// **Update Freq**: Adhoc
     fn get_geospatial_whole_island(client: &C, id: GeospatialLayerId) -> LTAResult<Vec<String>>;
+
+    /// Same as [`get_geospatial_whole_island`](GeoRequests::get_geospatial_whole_island), but
+    /// downloads the archives before the links expire and decodes the shapefiles in them into
+    /// [`Feature`](crate::geospatial::Feature)s
+    fn get_geospatial_layer(client: &C, id: GeospatialLayerId) -> LTAResult<Vec<Shapefile>>
+    where
+        C: Client<RB = reqwest::blocking::RequestBuilder>,
+    {
+        let mut shapefiles = Vec::new();
+        for link in Self::get_geospatial_whole_island(client, id)? {
+            let archive = download_link(client, &link)?;
+            shapefiles.extend(Shapefile::from_zip(&archive)?);
+        }
+
+        Ok(shapefiles)
+    }
 }
 
 impl GeoRequests<LTAClient> for Geo {
@@ -18,7 +35,7 @@ impl GeoRequests<LTAClient> for Geo {
     ) -> LTAResult<Vec<String>> {
         build_req_with_query::<GeospatialWholeIslandRawResp, _, _, _>(
             client,
-            api_url!("/GeospatialWholeIsland"),
+            "/GeospatialWholeIsland",
             |rb| rb.query(&[("ID", id)]),
         )
     }
@@ -3,11 +3,15 @@ pub mod client;
 pub mod crowd;
 pub mod facility;
 pub mod geo;
+pub mod paginate;
 pub mod taxi;
 pub mod traffic;
 pub mod train;
 
-use crate::{Client, LTAError, LTAResult};
+use crate::cassette::{CassetteMode, RecordedRequest};
+use crate::rate_limit::RateLimiter;
+use crate::{join_url, Client, LTAError, LTAResult, RawResponse};
+use std::time::Duration;
 
 pub use client::LTAClient;
 use reqwest::blocking;
@@ -21,53 +25,174 @@ pub mod prelude {
 
 pub(crate) fn build_req_with_skip<T, T2, C>(
     client: &C,
-    url: &str,
+    path: &str,
     skip: Option<u32>,
 ) -> LTAResult<T2>
 where
     C: Client<RB = blocking::RequestBuilder>,
     for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
 {
     let skip = skip.unwrap_or(0);
-    let rb = client.req_builder(url).query(&[("$skip", skip)]);
-    rb.send()
-        .map_err(LTAError::BackendError)
-        .and_then(handle_status_code)?
-        .json::<T>()
-        .map(Into::into)
-        .map_err(LTAError::BackendError)
+    let url = join_url(client, path);
+    let rb = client.req_builder(&url).query(&[("$skip", skip)]);
+    send_req::<T, T2, C>(client, rb, path, &url)
 }
 
-pub(crate) fn build_req_with_query<T, T2, F, C>(client: &C, url: &str, query: F) -> LTAResult<T2>
+pub(crate) fn build_req_with_query<T, T2, F, C>(client: &C, path: &str, query: F) -> LTAResult<T2>
 where
     F: FnOnce(blocking::RequestBuilder) -> blocking::RequestBuilder,
     C: Client<RB = blocking::RequestBuilder>,
     for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
 {
-    let rb = client.req_builder(url);
-    query(rb)
-        .send()
-        .map_err(LTAError::BackendError)
-        .and_then(handle_status_code)?
-        .json::<T>()
-        .map(Into::into)
-        .map_err(LTAError::BackendError)
+    let url = join_url(client, path);
+    let rb = client.req_builder(&url);
+    send_req::<T, T2, C>(client, query(rb), path, &url)
 }
 
-fn handle_status_code(res: blocking::Response) -> LTAResult<blocking::Response> {
-    use reqwest::StatusCode;
+/// helper function to keep requesting pages with increasing `$skip` until an empty or short page
+/// is returned
+pub(crate) fn fetch_all_pages<T, F>(get_page: F) -> LTAResult<Vec<T>>
+where
+    F: FnMut(Option<u32>) -> LTAResult<Vec<T>>,
+{
+    paginate::records(get_page).collect()
+}
+
+/// helper function to send a request and decode the response body, reading from and storing
+/// in the client's cache if it has one
+fn send_req<T, T2, C>(
+    client: &C,
+    rb: blocking::RequestBuilder,
+    path: &str,
+    url: &str,
+) -> LTAResult<T2>
+where
+    C: Client<RB = blocking::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<T2>,
+    T2: Clone + Send + Sync + 'static,
+{
+    let cache = match client.cache() {
+        Some(cache) => cache,
+        None => return send_req_with_retry::<T, T2, C>(client, rb, url),
+    };
+
+    let key = match rb.try_clone().and_then(|rb| rb.build().ok()) {
+        Some(req) => req.url().to_string(),
+        None => return send_req_with_retry::<T, T2, C>(client, rb, url),
+    };
+
+    if let Some(data) = cache.get::<T2>(&key) {
+        return Ok(data);
+    }
+
+    let in_flight = cache.in_flight(&key);
+    let _lock = in_flight.blocking_lock();
+    if let Some(data) = cache.get::<T2>(&key) {
+        return Ok(data);
+    }
+
+    let data = send_req_with_retry::<T, T2, C>(client, rb, url)?;
+    cache.insert(path, key, data.clone());
+    Ok(data)
+}
+
+/// helper function to send a request, waiting for the client's rate limiter and retrying
+/// according to its retry policy
+fn send_req_with_retry<T, T2, C>(
+    client: &C,
+    mut rb: blocking::RequestBuilder,
+    url: &str,
+) -> LTAResult<T2>
+where
+    C: Client<RB = blocking::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<T2>,
+{
+    let retry_policy = client.retry_policy();
+    let mut attempt = 1;
+
+    loop {
+        let delay = client.rate_limiter().map(RateLimiter::reserve);
+        if let Some(delay) = delay.filter(|d| *d > Duration::from_secs(0)) {
+            std::thread::sleep(delay);
+        }
+
+        let next_rb = rb.try_clone();
+        let err = match send_req_once::<T, T2, C>(client, rb, url) {
+            Ok(data) => return Ok(data),
+            Err(err) => err,
+        };
+
+        match (retry_policy.next_delay(attempt, &err), next_rb) {
+            (Some(delay), Some(next_rb)) => {
+                std::thread::sleep(delay);
+                rb = next_rb;
+                attempt += 1;
+            }
+            _ => return Err(err),
+        }
+    }
+}
 
-    let status_code = res.status();
+fn send_req_once<T, T2, C>(client: &C, rb: blocking::RequestBuilder, url: &str) -> LTAResult<T2>
+where
+    C: Client<RB = blocking::RequestBuilder>,
+    for<'de> T: serde::Deserialize<'de> + Into<T2>,
+{
+    let res = send_raw(client, rb, url)?.error_for_status()?;
+    res.json::<T>().map(Into::into)
+}
 
-    if status_code.is_success() {
-        return Ok(res);
+/// helper function to send a request and read the response body, recording it to or replaying
+/// it from the client's cassette if it has one
+fn send_raw<C>(client: &C, rb: blocking::RequestBuilder, url: &str) -> LTAResult<RawResponse>
+where
+    C: Client<RB = blocking::RequestBuilder>,
+{
+    let recorded = client.cassette().map(|cassette| {
+        let req = rb.try_clone().and_then(|rb| rb.build().ok());
+        let req = req.map(|req| RecordedRequest::new(req.method(), req.url(), req.headers()));
+        (cassette, req)
+    });
+
+    if let Some((cassette, req)) = &recorded {
+        if cassette.mode() == CassetteMode::Replay {
+            let req = req
+                .as_ref()
+                .ok_or_else(|| LTAError::Custom(format!("Unable to replay request to {}", url)))?;
+            return cassette.replay_response(req);
+        }
     }
 
-    match status_code {
-        StatusCode::UNAUTHORIZED => Err(LTAError::Unauthorized),
-        StatusCode::NOT_FOUND => Err(LTAError::Unauthorized),
-        _ => Err(LTAError::UnhandledStatusCode),
+    let res = rb.send().map_err(|e| LTAError::transport(url, e))?;
+    let (status, headers) = (res.status(), res.headers().clone());
+    let url = res.url().to_string();
+    let body = res
+        .bytes()
+        .map_err(|e| LTAError::transport(&url, e))?
+        .to_vec();
+    let res = RawResponse {
+        status,
+        url,
+        headers,
+        body,
+    };
+
+    if let Some((cassette, Some(req))) = recorded {
+        cassette.record_interaction(req, &res);
     }
+
+    Ok(res)
+}
+
+/// helper function to download a link returned by an API, eg a passenger volume archive
+pub(crate) fn download_link<C>(client: &C, link: &str) -> LTAResult<Vec<u8>>
+where
+    C: Client<RB = blocking::RequestBuilder>,
+{
+    let rb = client.link_req_builder(link);
+    Ok(send_raw(client, rb, link)?.error_for_status()?.body)
 }
 
 #[cfg(test)]
@@ -75,28 +200,37 @@ mod tests {
     use crate::blocking::geo::GeoRequests;
     use crate::blocking::prelude::*;
     use crate::blocking::*;
+    use crate::cassette::Cassette;
+    use crate::mock::{Fault, MockServer};
+    use crate::passenger_vol::PassengerVolRecord;
     use crate::prelude::*;
-    use crate::LTAResult;
+    use crate::{ArrivalQuery, LTAError, LTAResult, PAGE_SIZE};
     use crate::{Client, Facility, Geo};
     use lta_models::geo::geospatial_whole_island::GeospatialLayerId;
     use lta_models::prelude::*;
+    use lta_models::traffic::erp_rates;
+    use serde_json::json;
     use std::env;
     use std::fs::File;
     use std::io::prelude::*;
 
     macro_rules! gen_test {
-        ($f: expr) => {{
+        ($f: expr, |$data: ident| $check: block) => {{
             let client = get_client();
-            let data = $f(&client, None);
-            println!("{:?}", data);
+            let $data = $f(&client, None)?;
+            $check
             Ok(())
         }};
     }
 
     fn get_client() -> LTAClient {
-        let api_key = env::var("API_KEY").expect("API_KEY does not exist!");
-        let client = LTAClient::with_api_key(api_key).unwrap();
-        client
+        client_for(MockServer::shared(), "API_KEY")
+    }
+
+    fn client_for(server: &MockServer, api_key: &str) -> LTAClient {
+        LTAClient::with_api_key(api_key)
+            .unwrap()
+            .with_base_url(server.base_url())
     }
 
     #[test]
@@ -107,32 +241,159 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn get_arrivals_of_many_stops() {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server, "API_KEY");
+        server.fail_next("/BusArrivalv2", Fault::ServerError);
+
+        let queries = vec![
+            ArrivalQuery::new(83111),
+            ArrivalQuery::new(83139).service("15"),
+        ];
+        let arrivals = Bus::get_arrivals(&client, queries);
+
+        assert!(arrivals[&83111].is_err());
+        let resp = arrivals[&83139].as_ref().unwrap();
+        assert_eq!(resp.services.len(), 1);
+        assert_eq!(resp.services[0].service_no, "15");
+    }
+
     #[test]
     fn get_bus_arrivals_must_fail() {
-        let api_key = "FAKE_KEY";
-        let client = LTAClient::with_api_key(api_key).unwrap();
+        let server = MockServer::start().unwrap();
+        server.require_api_key("API_KEY");
+        let client = client_for(&server, "FAKE_KEY");
         let data = Bus::get_arrival(&client, 83139, None);
-        if let Ok(_) = data {
+        if data.is_ok() {
             panic!("Should not be Ok()")
         }
     }
 
+    #[test]
+    fn get_all_pages_from_mock() -> LTAResult<()> {
+        let server = MockServer::start().unwrap();
+        let value: Vec<_> = (0..1042)
+            .map(|i| json!({ "Longitude": 103.8, "Latitude": i as f64 / 1000.0 }))
+            .collect();
+        server.set_fixture("/Taxi-Availability", json!({ "value": value }));
+        let client = client_for(&server, "API_KEY");
+
+        let data = Taxi::get_all_taxi_avail(&client)?;
+        assert_eq!(data.len(), 1042);
+        assert_eq!(server.requests().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_cache_misses_request_once() {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server, "API_KEY").with_cache(crate::cache::ResponseCache::new());
+
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| Bus::get_bus_stops(&client, None)))
+                .collect();
+            for thread in threads {
+                assert!(thread.join().unwrap().is_ok());
+            }
+        });
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn injected_faults() {
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server, "API_KEY");
+
+        server.fail_next("/BusStops", Fault::RateLimited { retry_after: None });
+        let err = Bus::get_bus_stops(&client, None).unwrap_err();
+        assert!(matches!(err, LTAError::RateLimitReached(_)));
+
+        server.fail_next("/BusStops", Fault::MalformedJson);
+        let err = Bus::get_bus_stops(&client, None).unwrap_err();
+        assert!(matches!(err, LTAError::Decode { .. }));
+    }
+
+    #[test]
+    fn fetch_all_pages_until_short_page() -> LTAResult<()> {
+        let data = fetch_all_pages(|skip| {
+            let skip = skip.unwrap_or(0);
+            let len = if skip < 1000 { PAGE_SIZE } else { 42 };
+            Ok((skip..skip + len).collect::<Vec<_>>())
+        })?;
+        assert_eq!(data.len(), 1042);
+        assert_eq!(data.last(), Some(&1041));
+        Ok(())
+    }
+
+    #[test]
+    fn paginate_records() -> LTAResult<()> {
+        let get_page = |skip: Option<u32>| {
+            let skip = skip.unwrap_or(0);
+            Ok((skip..(skip + PAGE_SIZE).min(1250)).collect::<Vec<_>>())
+        };
+        let data = paginate::records(get_page).collect::<LTAResult<Vec<u32>>>()?;
+        assert_eq!(data, (0..1250).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn record_and_replay_cassette() -> LTAResult<()> {
+        let path =
+            std::env::temp_dir().join(format!("lta-blocking-cassette-{}.json", std::process::id()));
+        let server = MockServer::start().unwrap();
+        let client = client_for(&server, "API_KEY").with_cassette(Cassette::record(&path));
+        let arrivals = Bus::get_arrival(&client, 83139, Some("15"))?;
+        // Dropping the last handle writes the cassette
+        drop(client);
+
+        let base_url = server.base_url();
+        drop(server);
+        let client = LTAClient::with_api_key("API_KEY")?
+            .with_base_url(base_url)
+            .with_cassette(Cassette::replay(&path).unwrap());
+
+        assert_eq!(Bus::get_arrival(&client, 83139, Some("15"))?, arrivals);
+        assert!(Bus::get_arrival(&client, 83139, None).is_err());
+
+        std::fs::remove_file(path).unwrap();
+        Ok(())
+    }
+
     #[test]
     fn get_bus_services() -> LTAResult<()> {
-        gen_test!(Bus::get_bus_services)
+        gen_test!(Bus::get_bus_services, |data| {
+            assert_eq!(data.len(), 3);
+            assert_eq!(data[0].service_no, "10");
+        })
     }
 
     #[test]
     fn get_bus_routes() -> LTAResult<()> {
-        gen_test!(Bus::get_bus_routes)
+        gen_test!(Bus::get_bus_routes, |data| {
+            assert_eq!(data.len(), 3);
+            assert_eq!(data[0].bus_stop_code, 75009);
+        })
     }
 
     #[test]
     fn get_bus_stops() -> LTAResult<()> {
-        gen_test!(Bus::get_bus_stops)
+        gen_test!(Bus::get_bus_stops, |data| {
+            assert_eq!(data.len(), 3);
+            assert_eq!(data[0].bus_stop_code, 1012);
+        })
+    }
+
+    #[test]
+    fn get_all_bus_stops() -> LTAResult<()> {
+        let client = get_client();
+        let data = Bus::get_all_bus_stops(&client)?;
+        assert_eq!(data.len(), 3);
+        assert_eq!(data[0].road_name, "Victoria St");
+        Ok(())
     }
 
-    #[ignore]
     #[test]
     fn get_passenger_vol() -> LTAResult<()> {
         let client = get_client();
@@ -141,34 +402,68 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn get_passenger_vol_records() -> LTAResult<()> {
+        let client = get_client();
+        let records = Crowd::get_passenger_vol_records(&client, VolType::OdTrain, None)?;
+        let trips: u32 = records
+            .map(|r| match r? {
+                PassengerVolRecord::Od(od) => Ok(od.total_trips),
+                PassengerVolRecord::Node(node) => panic!("Expected OD record, got {:?}", node),
+            })
+            .sum::<LTAResult<_>>()?;
+        assert_eq!(trips, 1093 + 1254 + 402);
+        Ok(())
+    }
+
     #[test]
     fn get_taxi_avail() -> LTAResult<()> {
-        gen_test!(Taxi::get_taxi_avail)
+        gen_test!(Taxi::get_taxi_avail, |data| {
+            assert_eq!(data.len(), 3);
+            assert_eq!(data[0].long, 103.72232);
+        })
     }
 
     #[test]
     fn get_taxi_stands() -> LTAResult<()> {
-        gen_test!(Taxi::get_taxi_stands)
+        gen_test!(Taxi::get_taxi_stands, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].taxi_code, "A01");
+        })
     }
 
     #[test]
     fn get_erp_rates() -> LTAResult<()> {
-        gen_test!(Traffic::get_erp_rates)
+        gen_test!(Traffic::get_erp_rates, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].zone_id, erp_rates::ZoneId::AY1);
+        })
     }
 
     #[test]
     fn get_cp_avail() -> LTAResult<()> {
-        gen_test!(Traffic::get_carpark_avail)
+        gen_test!(Traffic::get_carpark_avail, |data| {
+            assert_eq!(data.len(), 3);
+            assert_eq!(data[0].dev, "Suntec City");
+        })
     }
 
     #[test]
     fn get_est_travel_time() -> LTAResult<()> {
-        gen_test!(Traffic::get_est_travel_time)
+        gen_test!(Traffic::get_est_travel_time, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].far_end_pt, "TUAS CHECKPOINT");
+        })
     }
 
     #[test]
     fn get_faulty_traffic_lights() -> LTAResult<()> {
-        gen_test!(Traffic::get_faulty_traffic_lights)
+        gen_test!(Traffic::get_faulty_traffic_lights, |data| {
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].alarm_id, "GL703034136");
+            assert_eq!(data[0].technical_alarm_type, TechnicalAlarmType::Blackout);
+            assert_eq!(data[0].end_date, None);
+        })
     }
 
     #[test]
@@ -181,22 +476,34 @@ mod tests {
 
     #[test]
     fn get_traffic_images() -> LTAResult<()> {
-        gen_test!(Traffic::get_traffic_images)
+        gen_test!(Traffic::get_traffic_images, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].camera_id, 1001);
+        })
     }
 
     #[test]
     fn get_traffic_incidents() -> LTAResult<()> {
-        gen_test!(Traffic::get_traffic_incidents)
+        gen_test!(Traffic::get_traffic_incidents, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].incident_type, IncidentType::VehicleBreakdown);
+        })
     }
 
     #[test]
     fn get_traffic_speed_band() -> LTAResult<()> {
-        gen_test!(Traffic::get_traffic_speed_band)
+        gen_test!(Traffic::get_traffic_speed_band, |data| {
+            assert_eq!(data.len(), 2);
+            assert_eq!(data[0].road_name, "KENT ROAD");
+        })
     }
 
     #[test]
     fn get_vms() -> LTAResult<()> {
-        gen_test!(Traffic::get_vms_emas)
+        gen_test!(Traffic::get_vms_emas, |data| {
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].equipment_id, "amvms_v9104");
+        })
     }
 
     #[test]
@@ -209,7 +516,10 @@ mod tests {
 
     #[test]
     fn get_train_service_alerts() -> LTAResult<()> {
-        gen_test!(Train::get_train_service_alert)
+        gen_test!(Train::get_train_service_alert, |data| {
+            assert_eq!(data.affected_segments.len(), 1);
+            assert_eq!(data.affected_segments[0].line, MrtLine::NSL);
+        })
     }
 
     #[test]
@@ -279,6 +589,15 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn get_geospatial_layer() -> LTAResult<()> {
+        let client = get_client();
+        let layers = Geo::get_geospatial_layer(&client, GeospatialLayerId::ArrowMarking)?;
+        let features: usize = layers.iter().map(|layer| layer.features.len()).sum();
+        assert_eq!(features, 2);
+        Ok(())
+    }
+
     #[test]
     fn get_facility_maintenance() -> LTAResult<()> {
         let client = get_client();
@@ -286,4 +605,17 @@ mod tests {
         println!("{:?}", data);
         Ok(())
     }
+
+    #[test]
+    fn get_line_facilities_maintenance() -> LTAResult<()> {
+        let client = get_client();
+        let stations = Facility::get_line_facilities_maintenance(&client, MrtLine::EWL);
+        let mut records = Vec::new();
+        for (_, station) in stations {
+            records.extend(station?.records);
+        }
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].facility_id, "B1L03");
+        Ok(())
+    }
 }
@@ -0,0 +1,80 @@
+//! Lazily request APIs that accept `skip`, one page at a time
+//!
+//! Pages are as long as Datamall returns them, [`PAGE_SIZE`] records each. Pages are requested
+//! one at a time, so unlike the async streams there are no options to set.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::blocking::bus::BusRequests;
+//! use lta::blocking::paginate::records;
+//! use lta::blocking::LTAClient;
+//! use lta::{Bus, Client, LTAResult};
+//!
+//! fn main() -> LTAResult<()> {
+//!     let client = LTAClient::with_api_key("API_KEY")?;
+//!     let routes = records(|skip| Bus::get_bus_routes(&client, skip));
+//!
+//!     for route in routes {
+//!         println!("{:?}", route?);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::{LTAResult, PAGE_SIZE};
+
+/// `Iterator` of pages, created by [`pages`]
+#[derive(Debug)]
+pub struct Pages<F> {
+    get_page: F,
+    next_skip: Option<u32>,
+}
+
+impl<T, F> Iterator for Pages<F>
+where
+    F: FnMut(Option<u32>) -> LTAResult<Vec<T>>,
+{
+    type Item = LTAResult<Vec<T>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let skip = self.next_skip.take()?;
+
+        match (self.get_page)(Some(skip)) {
+            Ok(page) if page.is_empty() => None,
+            Ok(page) => {
+                if page.len() >= PAGE_SIZE as usize {
+                    self.next_skip = Some(skip + PAGE_SIZE);
+                }
+                Some(Ok(page))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Returns an `Iterator` of pages. `get_page` is called with `skip` increasing by [`PAGE_SIZE`]
+/// until an empty or short page is returned, or an error occurs. Errors are yielded and end the
+/// iterator
+pub fn pages<T, F>(get_page: F) -> Pages<F>
+where
+    F: FnMut(Option<u32>) -> LTAResult<Vec<T>>,
+{
+    Pages {
+        get_page,
+        next_skip: Some(0),
+    }
+}
+
+/// Same as [`pages`], but yields individual records instead
+pub fn records<T, F>(get_page: F) -> impl Iterator<Item = LTAResult<T>>
+where
+    F: FnMut(Option<u32>) -> LTAResult<Vec<T>>,
+{
+    pages(get_page).flat_map(|page| {
+        let (records, err) = match page {
+            Ok(page) => (page, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+
+        records.into_iter().map(Ok).chain(err.map(Err))
+    })
+}
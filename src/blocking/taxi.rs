@@ -1,7 +1,7 @@
-use crate::blocking::{build_req_with_skip, LTAClient};
+use crate::blocking::{build_req_with_skip, fetch_all_pages, LTAClient};
 use crate::models::prelude::*;
 use crate::models::utils::Coordinates;
-use crate::{api_url, Client, LTAResult, Taxi};
+use crate::{Client, LTAResult, Taxi};
 
 /// All APIs pertaining to taxis
 pub trait TaxiRequests<C: Client> {
@@ -15,14 +15,26 @@ pub trait TaxiRequests<C: Client> {
     ///
     /// **Update freq**: Monthly
     fn get_taxi_stands(client: &C, skip: Option<u32>) -> LTAResult<Vec<TaxiStand>>;
+
+    /// Same as [`get_taxi_avail`](TaxiRequests::get_taxi_avail), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_taxi_avail(client: &C) -> LTAResult<Vec<Coordinates>> {
+        fetch_all_pages(|skip| Self::get_taxi_avail(client, skip))
+    }
+
+    /// Same as [`get_taxi_stands`](TaxiRequests::get_taxi_stands), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_taxi_stands(client: &C) -> LTAResult<Vec<TaxiStand>> {
+        fetch_all_pages(|skip| Self::get_taxi_stands(client, skip))
+    }
 }
 
 impl TaxiRequests<LTAClient> for Taxi {
     fn get_taxi_avail(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<Coordinates>> {
-        build_req_with_skip::<TaxiAvailResp, _, _>(client, api_url!("/Taxi-Availability"), skip)
+        build_req_with_skip::<TaxiAvailResp, _, _>(client, "/Taxi-Availability", skip)
     }
 
     fn get_taxi_stands(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<TaxiStand>> {
-        build_req_with_skip::<TaxiStandsResp, _, _>(client, api_url!("/TaxiStands"), skip)
+        build_req_with_skip::<TaxiStandsResp, _, _>(client, "/TaxiStands", skip)
     }
 }
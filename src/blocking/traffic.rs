@@ -1,6 +1,6 @@
-use crate::api_url;
-use crate::blocking::{build_req_with_query, build_req_with_skip, LTAClient};
+use crate::blocking::{build_req_with_query, build_req_with_skip, fetch_all_pages, LTAClient};
 use crate::models::traffic::prelude::*;
+use crate::traffic_lights::RawFaultyTrafficLightResp;
 use crate::{Client, LTAError, LTAResult, Traffic};
 
 pub trait TrafficRequests<C: Client> {
@@ -78,34 +78,83 @@ pub trait TrafficRequests<C: Client> {
         long: f64,
         dist: Option<f64>,
     ) -> LTAResult<Vec<BikeParking>>;
+
+    /// Same as [`get_erp_rates`](TrafficRequests::get_erp_rates), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_erp_rates(client: &C) -> LTAResult<Vec<ErpRate>> {
+        fetch_all_pages(|skip| Self::get_erp_rates(client, skip))
+    }
+
+    /// Same as [`get_carpark_avail`](TrafficRequests::get_carpark_avail), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_carpark_avail(client: &C) -> LTAResult<Vec<CarPark>> {
+        fetch_all_pages(|skip| Self::get_carpark_avail(client, skip))
+    }
+
+    /// Same as [`get_est_travel_time`](TrafficRequests::get_est_travel_time), but keeps requesting
+    /// with increasing `skip` until every record is returned
+    fn get_all_est_travel_time(client: &C) -> LTAResult<Vec<EstTravelTime>> {
+        fetch_all_pages(|skip| Self::get_est_travel_time(client, skip))
+    }
+
+    /// Same as [`get_faulty_traffic_lights`](TrafficRequests::get_faulty_traffic_lights), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    fn get_all_faulty_traffic_lights(client: &C) -> LTAResult<Vec<FaultyTrafficLight>> {
+        fetch_all_pages(|skip| Self::get_faulty_traffic_lights(client, skip))
+    }
+
+    /// Same as [`get_road_details`](TrafficRequests::get_road_details), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_road_details(
+        client: &C,
+        road_details_type: RoadDetailsType,
+    ) -> LTAResult<Vec<RoadDetails>> {
+        fetch_all_pages(|skip| Self::get_road_details(client, road_details_type.clone(), skip))
+    }
+
+    /// Same as [`get_traffic_speed_band`](TrafficRequests::get_traffic_speed_band), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    fn get_all_traffic_speed_band(client: &C) -> LTAResult<Vec<TrafficSpeedBand>> {
+        fetch_all_pages(|skip| Self::get_traffic_speed_band(client, skip))
+    }
+
+    /// Same as [`get_traffic_images`](TrafficRequests::get_traffic_images), but keeps requesting
+    /// with increasing `skip` until every record is returned
+    fn get_all_traffic_images(client: &C) -> LTAResult<Vec<TrafficImage>> {
+        fetch_all_pages(|skip| Self::get_traffic_images(client, skip))
+    }
+
+    /// Same as [`get_traffic_incidents`](TrafficRequests::get_traffic_incidents), but keeps
+    /// requesting with increasing `skip` until every record is returned
+    fn get_all_traffic_incidents(client: &C) -> LTAResult<Vec<TrafficIncident>> {
+        fetch_all_pages(|skip| Self::get_traffic_incidents(client, skip))
+    }
+
+    /// Same as [`get_vms_emas`](TrafficRequests::get_vms_emas), but keeps requesting with
+    /// increasing `skip` until every record is returned
+    fn get_all_vms_emas(client: &C) -> LTAResult<Vec<VMS>> {
+        fetch_all_pages(|skip| Self::get_vms_emas(client, skip))
+    }
 }
 
 impl TrafficRequests<LTAClient> for Traffic {
     fn get_erp_rates(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<ErpRate>> {
-        build_req_with_skip::<ErpRatesResp, _, _>(client, api_url!("/ERPRates"), skip)
+        build_req_with_skip::<ErpRatesResp, _, _>(client, "/ERPRates", skip)
     }
 
     fn get_carpark_avail(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<CarPark>> {
-        build_req_with_skip::<CarparkAvailResp, _, _>(
-            client,
-            api_url!("/CarParkAvailabilityv2"),
-            skip,
-        )
+        build_req_with_skip::<CarparkAvailResp, _, _>(client, "/CarParkAvailabilityv2", skip)
     }
 
     fn get_est_travel_time(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<EstTravelTime>> {
-        build_req_with_skip::<EstTravelTimeResp, _, _>(client, api_url!("/EstTravelTimes"), skip)
+        build_req_with_skip::<EstTravelTimeResp, _, _>(client, "/EstTravelTimes", skip)
     }
 
     fn get_faulty_traffic_lights(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<FaultyTrafficLight>> {
-        build_req_with_skip::<FaultyTrafficLightResp, _, _>(
-            client,
-            api_url!("/FaultyTrafficLights"),
-            skip,
-        )
+        build_req_with_skip::<RawFaultyTrafficLightResp, _, _>(client, "/FaultyTrafficLights", skip)
     }
 
     fn get_road_details(
@@ -113,43 +162,35 @@ impl TrafficRequests<LTAClient> for Traffic {
         road_details_type: RoadDetailsType,
         skip: Option<u32>,
     ) -> LTAResult<Vec<RoadDetails>> {
-        let url = match road_details_type {
-            RoadDetailsType::RoadOpening => api_url!("/RoadOpenings"),
-            RoadDetailsType::RoadWorks => api_url!("/RoadWorks"),
+        let path = match road_details_type {
+            RoadDetailsType::RoadOpening => "/RoadOpenings",
+            RoadDetailsType::RoadWorks => "/RoadWorks",
             _ => return Err(LTAError::UnknownEnumVariant),
         };
 
-        build_req_with_skip::<RoadDetailsResp, _, _>(client, url, skip)
+        build_req_with_skip::<RoadDetailsResp, _, _>(client, path, skip)
     }
 
     fn get_traffic_speed_band(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<TrafficSpeedBand>> {
-        build_req_with_skip::<TrafficSpeedBandResp, _, _>(
-            client,
-            api_url!("/TrafficSpeedBandsv2"),
-            skip,
-        )
+        build_req_with_skip::<TrafficSpeedBandResp, _, _>(client, "/TrafficSpeedBandsv2", skip)
     }
 
     fn get_traffic_images(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<TrafficImage>> {
-        build_req_with_skip::<TrafficImageResp, _, _>(client, api_url!("/Traffic-Imagesv2"), skip)
+        build_req_with_skip::<TrafficImageResp, _, _>(client, "/Traffic-Imagesv2", skip)
     }
 
     fn get_traffic_incidents(
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<Vec<TrafficIncident>> {
-        build_req_with_skip::<TrafficIncidentResp, _, _>(
-            client,
-            api_url!("/TrafficIncidents"),
-            skip,
-        )
+        build_req_with_skip::<TrafficIncidentResp, _, _>(client, "/TrafficIncidents", skip)
     }
 
     fn get_vms_emas(client: &LTAClient, skip: Option<u32>) -> LTAResult<Vec<VMS>> {
-        build_req_with_skip::<VMSResp, _, _>(client, api_url!("/VMS"), skip)
+        build_req_with_skip::<VMSResp, _, _>(client, "/VMS", skip)
     }
 
     fn get_bike_parking(
@@ -159,10 +200,8 @@ impl TrafficRequests<LTAClient> for Traffic {
         dist: Option<f64>,
     ) -> LTAResult<Vec<BikeParking>> {
         let unwrapped_dist = dist.unwrap_or(0.5);
-        build_req_with_query::<BikeParkingResp, _, _, _>(
-            client,
-            api_url!("/BicycleParkingv2"),
-            |rb| rb.query(&[("Lat", lat), ("Long", long), ("Dist", unwrapped_dist)]),
-        )
+        build_req_with_query::<BikeParkingResp, _, _, _>(client, "/BicycleParkingv2", |rb| {
+            rb.query(&[("Lat", lat), ("Long", long), ("Dist", unwrapped_dist)])
+        })
     }
 }
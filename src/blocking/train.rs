@@ -1,4 +1,3 @@
-use crate::api_url;
 use crate::blocking::{build_req_with_skip, LTAClient};
 use crate::models::train::prelude::*;
 use crate::{Client, LTAResult, Train};
@@ -16,10 +15,6 @@ impl TrainRequests<LTAClient> for Train {
         client: &LTAClient,
         skip: Option<u32>,
     ) -> LTAResult<TrainServiceAlert> {
-        build_req_with_skip::<TrainServiceAlertResp, _, _>(
-            client,
-            api_url!("/TrainServiceAlerts"),
-            skip,
-        )
+        build_req_with_skip::<TrainServiceAlertResp, _, _>(client, "/TrainServiceAlerts", skip)
     }
 }
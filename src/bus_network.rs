@@ -0,0 +1,323 @@
+//! Directed graph of bus stops, built from the flat rows of `BusRequests::get_all_bus_routes`
+//!
+//! Each direction of a service is a route, eg service `190` direction `1`. Consecutive stops of a
+//! route are joined by an [`Edge`] carrying the distance between them, so that a stop served by
+//! many services has one outgoing edge per route.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::bus_network::{BusNetwork, RouteId};
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let routes = Bus::get_all_bus_routes(&client).await?;
+//!     let stops = Bus::get_all_bus_stops(&client).await?;
+//!     let network = BusNetwork::new(&routes, &stops);
+//!
+//!     for ride in network.services_between(9048, 14229) {
+//!         println!("{} {:.1}km", ride.route.service_no, ride.distance);
+//!     }
+//!     let downstream = network.downstream(&RouteId::new("190", 1), 9048);
+//!     println!("{} stops after 9048", downstream.len());
+//!     Ok(())
+//! }
+//! ```
+use crate::models::bus::prelude::{BusRoute, BusStop};
+use std::collections::HashMap;
+
+/// One direction of a bus service
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RouteId {
+    pub service_no: String,
+    pub direction: u32,
+}
+
+impl RouteId {
+    pub fn new<S: Into<String>>(service_no: S, direction: u32) -> Self {
+        RouteId {
+            service_no: service_no.into(),
+            direction,
+        }
+    }
+}
+
+/// Stop of a route
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteStop {
+    pub bus_stop_code: u32,
+
+    pub stop_seq: u32,
+
+    /// Distance from the start of the route, in km
+    pub dist: f64,
+}
+
+/// Ride between two consecutive stops of a route
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: u32,
+    pub to: u32,
+    pub route: RouteId,
+
+    /// In km
+    pub distance: f64,
+}
+
+/// Ride on one route from a stop to a later stop
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ride {
+    pub route: RouteId,
+
+    /// Position of the boarding stop in [`BusNetwork::route`]
+    pub board: usize,
+
+    /// Position of the alighting stop in [`BusNetwork::route`]
+    pub alight: usize,
+
+    /// In km
+    pub distance: f64,
+}
+
+impl Ride {
+    /// Number of stops travelled
+    pub fn stops(&self) -> usize {
+        self.alight - self.board
+    }
+}
+
+/// Bus stops joined by the routes that serve them
+#[derive(Debug, Clone, Default)]
+pub struct BusNetwork {
+    stops: HashMap<u32, BusStop>,
+    routes: HashMap<RouteId, Vec<RouteStop>>,
+    edges: HashMap<u32, Vec<Edge>>,
+    routes_at: HashMap<u32, Vec<RouteId>>,
+}
+
+impl BusNetwork {
+    /// Builds the network from every bus route and bus stop. Rows of a route may be in any order,
+    /// they are sorted by `stop_seq`. Stops without a [`BusStop`] are kept, without a location
+    pub fn new(routes: &[BusRoute], stops: &[BusStop]) -> Self {
+        let mut network = BusNetwork {
+            stops: stops.iter().map(|s| (s.bus_stop_code, s.clone())).collect(),
+            ..BusNetwork::default()
+        };
+
+        for row in routes {
+            let id = RouteId::new(row.service_no.clone(), row.direction);
+            network.routes.entry(id).or_default().push(RouteStop {
+                bus_stop_code: row.bus_stop_code,
+                stop_seq: row.stop_seq,
+                dist: row.dist,
+            });
+        }
+
+        for (id, route) in network.routes.iter_mut() {
+            route.sort_by_key(|s| s.stop_seq);
+            for pair in route.windows(2) {
+                network
+                    .edges
+                    .entry(pair[0].bus_stop_code)
+                    .or_default()
+                    .push(Edge {
+                        from: pair[0].bus_stop_code,
+                        to: pair[1].bus_stop_code,
+                        route: id.clone(),
+                        // Some rows repeat the distance of the previous stop
+                        distance: (pair[1].dist - pair[0].dist).max(0.0),
+                    });
+            }
+            for stop in route.iter() {
+                let routes = network.routes_at.entry(stop.bus_stop_code).or_default();
+                if !routes.contains(id) {
+                    routes.push(id.clone());
+                }
+            }
+        }
+
+        // Keeps queries independent of the order of the hash maps
+        network.routes_at.values_mut().for_each(|r| r.sort());
+        network
+            .edges
+            .values_mut()
+            .for_each(|e| e.sort_by(|a, b| a.route.cmp(&b.route)));
+        network
+    }
+
+    pub fn stop(&self, bus_stop_code: u32) -> Option<&BusStop> {
+        self.stops.get(&bus_stop_code)
+    }
+
+    /// Returns every stop with a [`BusStop`]
+    pub fn stops(&self) -> impl Iterator<Item = &BusStop> {
+        self.stops.values()
+    }
+
+    /// Returns the stops of `route`, in order
+    pub fn route(&self, route: &RouteId) -> Option<&[RouteStop]> {
+        self.routes.get(route).map(Vec::as_slice)
+    }
+
+    /// Returns every route, in no particular order
+    pub fn routes(&self) -> impl Iterator<Item = (&RouteId, &[RouteStop])> {
+        self.routes.iter().map(|(id, stops)| (id, stops.as_slice()))
+    }
+
+    /// Returns the routes serving `bus_stop_code`, ordered by service and direction
+    pub fn routes_at(&self, bus_stop_code: u32) -> &[RouteId] {
+        self.routes_at
+            .get(&bus_stop_code)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the rides to the next stop of every route serving `bus_stop_code`
+    pub fn edges_from(&self, bus_stop_code: u32) -> &[Edge] {
+        self.edges
+            .get(&bus_stop_code)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the ride from `from` to `to` on `route`, if `to` comes after `from`. Loop services
+    /// that call at a stop twice take the ride with the fewest stops
+    pub fn ride(&self, route: &RouteId, from: u32, to: u32) -> Option<Ride> {
+        let stops = self.routes.get(route)?;
+        let (board, alight) = stops
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.bus_stop_code == from)
+            .filter_map(|(board, _)| {
+                let after = stops[board + 1..]
+                    .iter()
+                    .position(|s| s.bus_stop_code == to)?;
+                Some((board, board + 1 + after))
+            })
+            .min_by_key(|(board, alight)| alight - board)?;
+
+        Some(Ride {
+            route: route.clone(),
+            board,
+            alight,
+            distance: (stops[alight].dist - stops[board].dist).max(0.0),
+        })
+    }
+
+    /// Returns the rides from `from` to `to` without transfers, shortest first
+    pub fn services_between(&self, from: u32, to: u32) -> Vec<Ride> {
+        let mut rides: Vec<Ride> = self
+            .routes_at(from)
+            .iter()
+            .filter_map(|route| self.ride(route, from, to))
+            .collect();
+        rides.sort_by(|a, b| {
+            a.distance
+                .total_cmp(&b.distance)
+                .then(a.route.cmp(&b.route))
+        });
+        rides
+    }
+
+    /// Returns the stops after the first call of `route` at `bus_stop_code`, in order
+    pub fn downstream(&self, route: &RouteId, bus_stop_code: u32) -> &[RouteStop] {
+        let stops = match self.routes.get(route) {
+            Some(stops) => stops,
+            None => return &[],
+        };
+
+        match stops.iter().position(|s| s.bus_stop_code == bus_stop_code) {
+            Some(i) => &stops[i + 1..],
+            None => &[],
+        }
+    }
+
+    /// Returns the stops served by both services, in either direction, in the order of the
+    /// routes of `service_a`
+    pub fn transfer_stops(&self, service_a: &str, service_b: &str) -> Vec<u32> {
+        let mut transfers = Vec::new();
+        let mut routes_a: Vec<(&RouteId, &Vec<RouteStop>)> = self
+            .routes
+            .iter()
+            .filter(|(id, _)| id.service_no == service_a)
+            .collect();
+        routes_a.sort_by_key(|(id, _)| *id);
+
+        for stop in routes_a.into_iter().flat_map(|(_, stops)| stops) {
+            let serves_b = self
+                .routes_at(stop.bus_stop_code)
+                .iter()
+                .any(|id| id.service_no == service_b);
+            if serves_b && !transfers.contains(&stop.bus_stop_code) {
+                transfers.push(stop.bus_stop_code);
+            }
+        }
+
+        transfers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bus_enums::Operator;
+
+    fn route(service_no: &str, direction: u32, stops: &[(u32, f64)]) -> Vec<BusRoute> {
+        stops
+            .iter()
+            .enumerate()
+            .map(|(i, (code, dist))| BusRoute {
+                service_no: service_no.to_string(),
+                operator: Operator::SBST,
+                direction,
+                stop_seq: i as u32 + 1,
+                bus_stop_code: *code,
+                dist: *dist,
+                wd_first: None,
+                wd_last: None,
+                sat_first: None,
+                sat_last: None,
+                sun_first: None,
+                sun_last: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn queries_routes() {
+        let mut routes = route("190", 1, &[(1, 0.0), (2, 0.5), (3, 1.2), (4, 2.0)]);
+        routes.extend(route("190", 2, &[(4, 0.0), (3, 0.8), (2, 1.5), (1, 2.0)]));
+        routes.extend(route("7", 1, &[(5, 0.0), (2, 0.4), (3, 0.9), (6, 1.6)]));
+        // Loop service calling at 5 at both ends
+        routes.extend(route("36", 1, &[(5, 0.0), (6, 1.0), (4, 2.5), (5, 4.0)]));
+        routes.reverse();
+        let network = BusNetwork::new(&routes, &[]);
+
+        let rides = network.services_between(2, 3);
+        let services: Vec<&str> = rides.iter().map(|r| r.route.service_no.as_str()).collect();
+        assert_eq!(services, vec!["7", "190"]);
+        assert_eq!(rides[1].stops(), 1);
+        assert!((rides[1].distance - 0.7).abs() < 1e-9);
+        assert!(network
+            .services_between(3, 1)
+            .iter()
+            .all(|r| r.route.direction == 2));
+
+        let loop_ride = network.ride(&RouteId::new("36", 1), 5, 5).unwrap();
+        assert_eq!((loop_ride.board, loop_ride.alight), (0, 3));
+
+        let downstream: Vec<u32> = network
+            .downstream(&RouteId::new("190", 1), 2)
+            .iter()
+            .map(|s| s.bus_stop_code)
+            .collect();
+        assert_eq!(downstream, vec![3, 4]);
+
+        assert_eq!(network.transfer_stops("190", "7"), vec![2, 3]);
+        assert_eq!(network.transfer_stops("7", "36"), vec![5, 6]);
+        assert_eq!(network.edges_from(2).len(), 3);
+        assert_eq!(network.routes_at(4).len(), 3);
+    }
+}
@@ -0,0 +1,243 @@
+//! In-memory caching of decoded responses
+//!
+//! ## Example
+//! ```rust
+//! use lta::cache::ResponseCache;
+//! use lta::{Client, LTAClient, LTAResult};
+//! use std::time::Duration;
+//!
+//! fn cached_client() -> LTAResult<LTAClient> {
+//!     let cache = ResponseCache::new().with_ttl("/BusArrivalv2", Duration::from_secs(20));
+//!     Ok(LTAClient::with_api_key("API_KEY")?.with_cache(cache))
+//! }
+//! ```
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+
+const MINUTE: Duration = Duration::from_secs(60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returns how long responses of an API path stay fresh, based on the update frequency in the
+/// Datamall docs. APIs that are updated ad-hoc are kept for a day
+pub fn default_ttl(path: &str) -> Duration {
+    match path {
+        "/BusArrivalv2" | "/Taxi-Availability" | "/CarParkAvailabilityv2" | "/Traffic-Imagesv2" => {
+            MINUTE
+        }
+        "/FaultyTrafficLights" | "/TrafficIncidents" | "/VMS" => 2 * MINUTE,
+        "/EstTravelTimes" | "/TrafficSpeedBandsv2" => 5 * MINUTE,
+        // Alerts are ad-hoc but have to reach users quickly
+        "/TrainServiceAlerts" => MINUTE,
+        // Pre-signed links expire after 5 mins
+        "/PV/Bus"
+        | "/PV/ODBus"
+        | "/PV/Train"
+        | "/PV/ODTrain"
+        | "/GeospatialWholeIsland"
+        | "/FacilitiesMaintenance" => 4 * MINUTE,
+        "/TaxiStands" | "/BicycleParkingv2" => 30 * DAY,
+        _ => DAY,
+    }
+}
+
+/// Cache of decoded responses, keyed by request URL including the query.
+///
+/// Cloning a `ResponseCache` returns a handle to the same cache, so every clone of a client
+/// shares the same entries.
+///
+/// Concurrent requests of a URL that is not cached send a single request, the others wait for its
+/// response.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseCache {
+    inner: Arc<RwLock<Inner>>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    ttl_overrides: HashMap<String, Duration>,
+    in_flight: HashMap<String, Arc<AsyncMutex<()>>>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: String,
+    data: Arc<dyn Any + Send + Sync>,
+    expires_at: Instant,
+}
+
+impl ResponseCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides how long responses of an API path, eg `/BusArrivalv2`, stay fresh
+    pub fn with_ttl<S: Into<String>>(self, path: S, ttl: Duration) -> Self {
+        self.write().ttl_overrides.insert(path.into(), ttl);
+        self
+    }
+
+    /// Returns how long responses of an API path stay fresh
+    pub fn ttl(&self, path: &str) -> Duration {
+        self.read()
+            .ttl_overrides
+            .get(path)
+            .copied()
+            .unwrap_or_else(|| default_ttl(path))
+    }
+
+    /// Removes every cached response of an API path, eg `/BusStops`
+    pub fn invalidate(&self, path: &str) {
+        self.write().entries.retain(|_, entry| entry.path != path);
+    }
+
+    /// Removes the cached response of a request URL, including the query
+    pub fn invalidate_url(&self, url: &str) {
+        self.write().entries.remove(url);
+    }
+
+    /// Removes every cached response
+    pub fn clear(&self) {
+        self.write().entries.clear();
+    }
+
+    /// Number of cached responses, including expired ones that have not been removed yet
+    pub fn len(&self) -> usize {
+        self.read().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a clone of the cached response, if it is still fresh
+    pub(crate) fn get<T: Clone + 'static>(&self, url: &str) -> Option<T> {
+        let inner = self.read();
+        let entry = inner.entries.get(url)?;
+
+        if entry.expires_at <= Instant::now() {
+            return None;
+        }
+
+        entry.data.downcast_ref::<T>().cloned()
+    }
+
+    pub(crate) fn insert<T: Send + Sync + 'static>(&self, path: &str, url: String, data: T) {
+        let now = Instant::now();
+        let expires_at = now + self.ttl(path);
+        let mut inner = self.write();
+
+        inner.entries.retain(|_, entry| entry.expires_at > now);
+        inner.entries.insert(
+            url,
+            Entry {
+                path: path.to_string(),
+                data: Arc::new(data),
+                expires_at,
+            },
+        );
+    }
+
+    /// Returns the lock of a request URL, held from a cache miss until its response is inserted
+    pub(crate) fn in_flight(&self, url: &str) -> InFlight<'_> {
+        let lock = self
+            .write()
+            .in_flight
+            .entry(url.to_string())
+            .or_default()
+            .clone();
+
+        InFlight {
+            cache: self,
+            url: url.to_string(),
+            lock,
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Inner> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Inner> {
+        self.inner.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Request of a URL that missed the cache. Concurrent requests of the same URL wait for the lock,
+/// then read the response that the first one inserted instead of requesting it again. Errors are
+/// not cached, so after a failed request the next one in line sends its own
+pub(crate) struct InFlight<'a> {
+    cache: &'a ResponseCache,
+    url: String,
+    lock: Arc<AsyncMutex<()>>,
+}
+
+impl InFlight<'_> {
+    pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+
+    /// Must not be called from an async context
+    #[cfg(feature = "blocking")]
+    pub(crate) fn blocking_lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.blocking_lock()
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut inner = self.cache.write();
+        // Other requests clone the lock while holding the cache, so no one can be waiting if the
+        // cache and this request hold the only references
+        if Arc::strong_count(&self.lock) == 2 {
+            inner.in_flight.remove(&self.url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{default_ttl, ResponseCache};
+    use std::time::Duration;
+
+    const URL: &str = "http://localhost/BusStops?%24skip=0";
+
+    #[test]
+    fn get_and_invalidate() {
+        let cache = ResponseCache::new();
+        cache.insert("/BusStops", URL.to_string(), vec![1, 2, 3]);
+
+        assert_eq!(cache.get::<Vec<i32>>(URL), Some(vec![1, 2, 3]));
+        assert_eq!(cache.get::<String>(URL), None);
+        assert_eq!(cache.get::<Vec<i32>>("http://localhost/BusRoutes"), None);
+
+        cache.invalidate("/BusStops");
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn ttl_overrides() {
+        let cache = ResponseCache::new().with_ttl("/BusStops", Duration::from_secs(0));
+        assert_eq!(cache.ttl("/BusArrivalv2"), Duration::from_secs(60));
+        assert_eq!(
+            default_ttl("/TrafficSpeedBandsv2"),
+            Duration::from_secs(300)
+        );
+
+        cache.insert("/BusStops", URL.to_string(), vec![1, 2, 3]);
+        assert_eq!(cache.get::<Vec<i32>>(URL), None);
+    }
+
+    #[test]
+    fn clones_share_entries() {
+        let cache = ResponseCache::new();
+        cache.clone().insert("/BusStops", URL.to_string(), 42);
+
+        assert_eq!(cache.get::<i32>(URL), Some(42));
+        cache.clone().clear();
+        assert_eq!(cache.len(), 0);
+    }
+}
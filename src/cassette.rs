@@ -0,0 +1,316 @@
+//! Recording of requests and responses to a cassette file, to replay them later without network
+//!
+//! In [`CassetteMode::Record`], every request sent by a client is added to the cassette along
+//! with its response, and the cassette is written to its file by [`Cassette::save`] or once its
+//! last handle is dropped. The `AccountKey` header is never written. In [`CassetteMode::Replay`],
+//! requests are answered from the cassette, in the order they were recorded, and requests that
+//! were not recorded fail.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::cassette::Cassette;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let cassette = Cassette::record("stops.json");
+//!     let client = LTAClient::with_api_key(api_key)?.with_cassette(cassette.clone());
+//!     let recorded = Bus::get_bus_stops(&client, None).await?;
+//!     cassette.save()?;
+//!
+//!     let cassette = Cassette::replay("stops.json").expect("Unable to read cassette");
+//!     let client = LTAClient::with_api_key("API_KEY")?.with_cassette(cassette);
+//!     assert_eq!(Bus::get_bus_stops(&client, None).await?, recorded);
+//!     Ok(())
+//! }
+//! ```
+use crate::{LTAError, LTAResult, RawResponse};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Method, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Header that carries the API key, never written to cassettes
+const ACCOUNT_KEY: &str = "accountkey";
+
+/// Whether a [`Cassette`] records or replays requests
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Request and the response it got
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordedRequest {
+    pub method: String,
+
+    /// URL without the query
+    pub url: String,
+
+    /// Query pairs, in order
+    pub query: Vec<(String, String)>,
+
+    /// Headers with lowercase names, except `AccountKey`
+    pub headers: Vec<(String, String)>,
+}
+
+impl RecordedRequest {
+    pub(crate) fn new(method: &Method, url: &Url, headers: &HeaderMap) -> Self {
+        let mut base = url.clone();
+        base.set_query(None);
+
+        RecordedRequest {
+            method: method.to_string(),
+            url: base.to_string(),
+            query: url.query_pairs().into_owned().collect(),
+            headers: header_pairs(headers),
+        }
+    }
+
+    /// Returns `true` if both are the same request, ignoring headers
+    fn matches(&self, other: &RecordedRequest) -> bool {
+        self.method == other.method && self.url == other.url && self.query == other.query
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+
+    /// Headers with lowercase names
+    pub headers: Vec<(String, String)>,
+
+    /// Body, if it is valid UTF-8
+    pub body: String,
+
+    /// Hex encoded body, if it is not valid UTF-8, eg a ZIP archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body_hex: Option<String>,
+}
+
+impl RecordedResponse {
+    fn from_raw(res: &RawResponse) -> Self {
+        let (body, body_hex) = match std::str::from_utf8(&res.body) {
+            Ok(body) => (body.to_string(), None),
+            Err(_) => (String::new(), Some(to_hex(&res.body))),
+        };
+
+        RecordedResponse {
+            status: res.status.as_u16(),
+            headers: header_pairs(&res.headers),
+            body,
+            body_hex,
+        }
+    }
+
+    fn to_raw(&self, url: String) -> RawResponse {
+        let headers = self
+            .headers
+            .iter()
+            .filter_map(|(k, v)| {
+                let name = HeaderName::from_bytes(k.as_bytes()).ok()?;
+                Some((name, HeaderValue::from_str(v).ok()?))
+            })
+            .collect();
+
+        RawResponse {
+            status: StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            url,
+            headers,
+            body: match &self.body_hex {
+                Some(hex) => from_hex(hex),
+                None => self.body.clone().into_bytes(),
+            },
+        }
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok())
+        .collect()
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|(k, _)| k.as_str() != ACCOUNT_KEY)
+        .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+        .collect()
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Tape {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    tape: Tape,
+    replayed: Vec<bool>,
+
+    /// `true` if interactions were recorded since the cassette was last written
+    unsaved: bool,
+}
+
+#[derive(Debug)]
+struct Shared {
+    path: PathBuf,
+    mode: CassetteMode,
+    inner: Mutex<Inner>,
+}
+
+impl Shared {
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn save(&self) -> LTAResult<()> {
+        let mut inner = self.inner();
+        if self.mode != CassetteMode::Record || !inner.unsaved {
+            return Ok(());
+        }
+
+        serde_json::to_vec_pretty(&inner.tape)
+            .map_err(io::Error::from)
+            .and_then(|bytes| fs::write(&self.path, bytes))
+            .map_err(|e| {
+                LTAError::Custom(format!(
+                    "Unable to write cassette {}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })?;
+        inner.unsaved = false;
+        Ok(())
+    }
+}
+
+/// Writes what was not saved yet once the last handle is dropped. Errors are lost, call
+/// [`Cassette::save`] to get them
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
+
+/// Cassette file that requests are recorded to or replayed from.
+///
+/// Cloning a `Cassette` returns a handle to the same cassette, so every clone of a client
+/// records to and replays from the same file.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    shared: Arc<Shared>,
+}
+
+impl Cassette {
+    fn new(path: PathBuf, mode: CassetteMode, inner: Inner) -> Self {
+        Cassette {
+            shared: Arc::new(Shared {
+                path,
+                mode,
+                inner: Mutex::new(inner),
+            }),
+        }
+    }
+
+    /// Records to `path`. The file is replaced by [`save`](Cassette::save), or once the last
+    /// handle of the cassette is dropped
+    pub fn record<P: Into<PathBuf>>(path: P) -> Self {
+        Self::new(path.into(), CassetteMode::Record, Inner::default())
+    }
+
+    /// Replays the interactions recorded in `path`
+    pub fn replay<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let tape: Tape = serde_json::from_slice(&fs::read(&path)?)?;
+        let replayed = vec![false; tape.interactions.len()];
+
+        let inner = Inner {
+            tape,
+            replayed,
+            unsaved: false,
+        };
+        Ok(Self::new(path, CassetteMode::Replay, inner))
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.shared.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.shared.path
+    }
+
+    /// Writes the interactions recorded so far to the file. Does nothing when replaying or if
+    /// nothing was recorded since the last save
+    pub fn save(&self) -> LTAResult<()> {
+        self.shared.save()
+    }
+
+    /// Returns the interactions recorded so far, or loaded for replaying
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.inner().tape.interactions.clone()
+    }
+
+    /// Returns the response to the first matching request that has not been replayed yet. Once
+    /// every matching request has been replayed, the last one is replayed again
+    pub(crate) fn replay_response(&self, req: &RecordedRequest) -> LTAResult<RawResponse> {
+        let mut inner = self.inner();
+        let matching: Vec<usize> = (0..inner.tape.interactions.len())
+            .filter(|&i| inner.tape.interactions[i].request.matches(req))
+            .collect();
+
+        let idx = matching
+            .iter()
+            .copied()
+            .find(|&i| !inner.replayed[i])
+            .or_else(|| matching.last().copied())
+            .ok_or_else(|| {
+                LTAError::Custom(format!(
+                    "No recorded response for {} {} in {}",
+                    req.method,
+                    req.url,
+                    self.path().display()
+                ))
+            })?;
+
+        inner.replayed[idx] = true;
+        let mut url = Url::parse(&req.url).map_err(|e| LTAError::Custom(e.to_string()))?;
+        if !req.query.is_empty() {
+            url.query_pairs_mut().extend_pairs(&req.query);
+        }
+        Ok(inner.tape.interactions[idx]
+            .response
+            .to_raw(url.to_string()))
+    }
+
+    /// Appends the interaction, which is written to disk by [`save`](Cassette::save)
+    pub(crate) fn record_interaction(&self, request: RecordedRequest, res: &RawResponse) {
+        let mut inner = self.inner();
+        inner.tape.interactions.push(Interaction {
+            request,
+            response: RecordedResponse::from_raw(res),
+        });
+        inner.unsaved = true;
+    }
+
+    fn inner(&self) -> MutexGuard<'_, Inner> {
+        self.shared.inner()
+    }
+}
@@ -0,0 +1,306 @@
+//! Conversion between SVY21 (EPSG:3414) and WGS84 coordinates
+//!
+//! Geospatial layers are in SVY21, a transverse Mercator projection of the WGS84 ellipsoid,
+//! while bus stops, taxis and most traffic datasets are in WGS84 latitude and longitude. The
+//! forward and inverse series used here round trip to within a millimetre across Singapore.
+//!
+//! ## Example
+//! ```rust
+//! use lta::coordinates::CoordinatesExt;
+//! use lta::models::utils::Coordinates;
+//!
+//! let stop = Coordinates::new(1.29684825487647, 103.85253591654006);
+//! let svy21 = stop.to_svy21();
+//! let back = Coordinates::from_svy21(svy21);
+//! assert!((back.lat - stop.lat).abs() < 1e-9);
+//! assert!((back.long - stop.long).abs() < 1e-9);
+//! ```
+use crate::geospatial::Point;
+use crate::models::utils::Coordinates;
+use std::f64::consts::PI;
+
+/// Semi-major axis of the WGS84 ellipsoid, in metres
+const A: f64 = 6_378_137.0;
+
+/// Flattening of the WGS84 ellipsoid
+const F: f64 = 1.0 / 298.257_223_563;
+
+/// Latitude of the SVY21 origin, 1°22'N
+const ORIGIN_LAT: f64 = 1.0 + 22.0 / 60.0;
+
+/// Longitude of the SVY21 origin, 103°50'E
+const ORIGIN_LONG: f64 = 103.0 + 50.0 / 60.0;
+
+const FALSE_NORTHING: f64 = 38_744.572;
+
+const FALSE_EASTING: f64 = 28_001.642;
+
+/// Scale factor on the central meridian
+const K: f64 = 1.0;
+
+/// Mean radius of the earth, in metres
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// SVY21 coordinate, in metres
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Svy21 {
+    pub northing: f64,
+    pub easting: f64,
+}
+
+impl Svy21 {
+    pub fn new(northing: f64, easting: f64) -> Self {
+        Svy21 { northing, easting }
+    }
+
+    /// Converts to WGS84 latitude and longitude, in degrees
+    pub fn to_wgs84(&self) -> Coordinates {
+        let b = A * (1.0 - F);
+        let n = (A - b) / (A + b);
+        let (n2, n3, n4) = (n * n, n.powi(3), n.powi(4));
+
+        let m_prime = meridian_arc(ORIGIN_LAT.to_radians()) + (self.northing - FALSE_NORTHING) / K;
+        let g =
+            A * (1.0 - n) * (1.0 - n2) * (1.0 + 9.0 * n2 / 4.0 + 225.0 * n4 / 64.0) * (PI / 180.0);
+        let sigma = m_prime * PI / (180.0 * g);
+
+        // Footpoint latitude
+        let lat_prime = sigma
+            + (3.0 * n / 2.0 - 27.0 * n3 / 32.0) * (2.0 * sigma).sin()
+            + (21.0 * n2 / 16.0 - 55.0 * n4 / 32.0) * (4.0 * sigma).sin()
+            + (151.0 * n3 / 96.0) * (6.0 * sigma).sin()
+            + (1097.0 * n4 / 512.0) * (8.0 * sigma).sin();
+
+        let sin2 = lat_prime.sin().powi(2);
+        let rho = radius_meridian(sin2);
+        let v = radius_prime_vertical(sin2);
+        let psi = v / rho;
+        let (psi2, psi3, psi4) = (psi * psi, psi.powi(3), psi.powi(4));
+        let t = lat_prime.tan();
+        let (t2, t4, t6) = (t * t, t.powi(4), t.powi(6));
+
+        let e_prime = self.easting - FALSE_EASTING;
+        let x = e_prime / (K * v);
+        let (x3, x5, x7) = (x.powi(3), x.powi(5), x.powi(7));
+
+        let lat_factor = t / (K * rho);
+        let lat_term1 = lat_factor * (e_prime * x / 2.0);
+        let lat_term2 =
+            lat_factor * (e_prime * x3 / 24.0) * (-4.0 * psi2 + 9.0 * psi * (1.0 - t2) + 12.0 * t2);
+        let lat_term3 = lat_factor
+            * (e_prime * x5 / 720.0)
+            * (8.0 * psi4 * (11.0 - 24.0 * t2) - 12.0 * psi3 * (21.0 - 71.0 * t2)
+                + 15.0 * psi2 * (15.0 - 98.0 * t2 + 15.0 * t4)
+                + 180.0 * psi * (5.0 * t2 - 3.0 * t4)
+                + 360.0 * t4);
+        let lat_term4 = lat_factor
+            * (e_prime * x7 / 40320.0)
+            * (1385.0 - 3633.0 * t2 + 4095.0 * t4 + 1575.0 * t6);
+        let lat = lat_prime - lat_term1 + lat_term2 - lat_term3 + lat_term4;
+
+        let sec = 1.0 / lat_prime.cos();
+        let long_term1 = x * sec;
+        let long_term2 = (x3 * sec / 6.0) * (psi + 2.0 * t2);
+        let long_term3 = (x5 * sec / 120.0)
+            * (-4.0 * psi3 * (1.0 - 6.0 * t2)
+                + psi2 * (9.0 - 68.0 * t2)
+                + 72.0 * psi * t2
+                + 24.0 * t4);
+        let long_term4 = (x7 * sec / 5040.0) * (61.0 + 662.0 * t2 + 1320.0 * t4 + 720.0 * t6);
+        let long = ORIGIN_LONG.to_radians() + long_term1 - long_term2 + long_term3 - long_term4;
+
+        Coordinates::new(lat.to_degrees(), long.to_degrees())
+    }
+}
+
+/// Converts WGS84 latitude and longitude, in degrees, to SVY21
+pub fn to_svy21(coords: &Coordinates) -> Svy21 {
+    let lat = coords.lat.to_radians();
+    let (sin, cos) = lat.sin_cos();
+    let sin2 = sin * sin;
+    let rho = radius_meridian(sin2);
+    let v = radius_prime_vertical(sin2);
+    let psi = v / rho;
+    let (psi2, psi3, psi4) = (psi * psi, psi.powi(3), psi.powi(4));
+    let t = lat.tan();
+    let (t2, t4, t6) = (t * t, t.powi(4), t.powi(6));
+
+    let w = (coords.long - ORIGIN_LONG).to_radians();
+    let (w2, w4, w6, w8) = (w * w, w.powi(4), w.powi(6), w.powi(8));
+    let m = meridian_arc(lat) - meridian_arc(ORIGIN_LAT.to_radians());
+
+    let n_term1 = w2 / 2.0 * v * sin * cos;
+    let n_term2 = w4 / 24.0 * v * sin * cos.powi(3) * (4.0 * psi2 + psi - t2);
+    let n_term3 = w6 / 720.0
+        * v
+        * sin
+        * cos.powi(5)
+        * (8.0 * psi4 * (11.0 - 24.0 * t2) - 28.0 * psi3 * (1.0 - 6.0 * t2)
+            + psi2 * (1.0 - 32.0 * t2)
+            - psi * 2.0 * t2
+            + t4);
+    let n_term4 = w8 / 40320.0 * v * sin * cos.powi(7) * (1385.0 - 3111.0 * t2 + 543.0 * t4 - t6);
+    let northing = FALSE_NORTHING + K * (m + n_term1 + n_term2 + n_term3 + n_term4);
+
+    let e_term1 = w2 / 6.0 * cos.powi(2) * (psi - t2);
+    let e_term2 = w4 / 120.0
+        * cos.powi(4)
+        * (4.0 * psi3 * (1.0 - 6.0 * t2) + psi2 * (1.0 + 8.0 * t2) - psi * 2.0 * t2 + t4);
+    let e_term3 = w6 / 5040.0 * cos.powi(6) * (61.0 - 479.0 * t2 + 179.0 * t4 - t6);
+    let easting = FALSE_EASTING + K * v * w * cos * (1.0 + e_term1 + e_term2 + e_term3);
+
+    Svy21 { northing, easting }
+}
+
+/// Returns the great circle distance between two WGS84 coordinates, in metres
+pub fn haversine_distance(a: &Coordinates, b: &Coordinates) -> f64 {
+    let (lat_a, lat_b) = (a.lat.to_radians(), b.lat.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_long = (b.long - a.long).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_long / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// Squared eccentricity of the WGS84 ellipsoid
+fn e2() -> f64 {
+    2.0 * F - F * F
+}
+
+/// Length of the meridian from the equator to `lat`, in radians
+fn meridian_arc(lat: f64) -> f64 {
+    let e2 = e2();
+    let (e4, e6) = (e2 * e2, e2.powi(3));
+    let a0 = 1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0;
+    let a2 = 3.0 / 8.0 * (e2 + e4 / 4.0 + 15.0 * e6 / 128.0);
+    let a4 = 15.0 / 256.0 * (e4 + 3.0 * e6 / 4.0);
+    let a6 = 35.0 * e6 / 3072.0;
+
+    A * (a0 * lat - a2 * (2.0 * lat).sin() + a4 * (4.0 * lat).sin() - a6 * (6.0 * lat).sin())
+}
+
+/// Radius of curvature in the meridian
+fn radius_meridian(sin2_lat: f64) -> f64 {
+    A * (1.0 - e2()) / (1.0 - e2() * sin2_lat).powf(1.5)
+}
+
+/// Radius of curvature in the prime vertical
+fn radius_prime_vertical(sin2_lat: f64) -> f64 {
+    A / (1.0 - e2() * sin2_lat).sqrt()
+}
+
+/// Conversion helpers on [`Coordinates`], which is defined in `lta_models`
+pub trait CoordinatesExt {
+    fn from_svy21(svy21: Svy21) -> Self;
+
+    fn to_svy21(&self) -> Svy21;
+
+    /// Great circle distance to `other`, in metres
+    fn distance_to(&self, other: &Coordinates) -> f64;
+}
+
+impl CoordinatesExt for Coordinates {
+    fn from_svy21(svy21: Svy21) -> Self {
+        svy21.to_wgs84()
+    }
+
+    fn to_svy21(&self) -> Svy21 {
+        to_svy21(self)
+    }
+
+    fn distance_to(&self, other: &Coordinates) -> f64 {
+        haversine_distance(self, other)
+    }
+}
+
+impl From<Svy21> for Coordinates {
+    fn from(svy21: Svy21) -> Self {
+        svy21.to_wgs84()
+    }
+}
+
+impl From<&Coordinates> for Svy21 {
+    fn from(coords: &Coordinates) -> Self {
+        to_svy21(coords)
+    }
+}
+
+/// Points of the geospatial layers, whose `x` is the easting and `y` the northing
+impl From<Point> for Svy21 {
+    fn from(point: Point) -> Self {
+        Svy21::new(point.y, point.x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origin_maps_to_false_origin() {
+        let svy21 = Coordinates::new(ORIGIN_LAT, ORIGIN_LONG).to_svy21();
+        assert!((svy21.northing - FALSE_NORTHING).abs() < 1e-6);
+        assert!((svy21.easting - FALSE_EASTING).abs() < 1e-6);
+
+        let coords = Svy21::new(FALSE_NORTHING, FALSE_EASTING).to_wgs84();
+        assert!((coords.lat - ORIGIN_LAT).abs() < 1e-12);
+        assert!((coords.long - ORIGIN_LONG).abs() < 1e-12);
+    }
+
+    #[test]
+    fn matches_reference_points() {
+        // EPSG:3414 northings and eastings from Karney's (2011) Krüger series for the transverse
+        // Mercator projection, which is independent of the series used here and accurate to a few
+        // nanometres
+        let points = [
+            (1.3187, 103.6390, 33_441.507, 6_374.204),
+            (1.3644, 103.9915, 38_494.514, 45_603.739),
+            (1.4382, 103.7890, 46_654.414, 23_068.022),
+            (1.2494, 103.8303, 25_777.828, 27_664.053),
+        ];
+
+        for (lat, long, northing, easting) in points.iter().copied() {
+            let svy21 = Coordinates::new(lat, long).to_svy21();
+            assert!((svy21.northing - northing).abs() < 0.01, "{:?}", svy21);
+            assert!((svy21.easting - easting).abs() < 0.01, "{:?}", svy21);
+
+            // 1e-7 degrees is about a centimetre
+            let coords = Svy21::new(northing, easting).to_wgs84();
+            assert!((coords.lat - lat).abs() < 1e-7, "{} {}", lat, coords.lat);
+            assert!(
+                (coords.long - long).abs() < 1e-7,
+                "{} {}",
+                long,
+                coords.long
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_across_singapore() {
+        // Tuas, Changi, Woodlands and Sentosa
+        let points = [
+            (1.3187, 103.6390),
+            (1.3644, 103.9915),
+            (1.4382, 103.7890),
+            (1.2494, 103.8303),
+        ];
+
+        for (lat, long) in points.iter().copied() {
+            let svy21 = Coordinates::new(lat, long).to_svy21();
+            let back = Coordinates::from_svy21(svy21);
+            // 1e-8 degrees is about a millimetre
+            assert!((back.lat - lat).abs() < 1e-8, "{} {}", lat, back.lat);
+            assert!((back.long - long).abs() < 1e-8, "{} {}", long, back.long);
+        }
+
+        // One arc minute of latitude north of the origin is about a nautical mile
+        let north = Coordinates::new(ORIGIN_LAT + 1.0 / 60.0, ORIGIN_LONG).to_svy21();
+        assert!((north.northing - FALSE_NORTHING - 1843.0).abs() < 1.0);
+
+        // On a sphere, one minute of arc is 1853m rather than the 1843m of the ellipsoid
+        let origin = Coordinates::new(ORIGIN_LAT, ORIGIN_LONG);
+        let north = Coordinates::new(ORIGIN_LAT + 1.0 / 60.0, ORIGIN_LONG);
+        assert!((origin.distance_to(&north) - 1853.2).abs() < 0.1);
+    }
+}
@@ -0,0 +1,253 @@
+//! Persistent cache of datasets that rarely change, eg bus stops, bus routes and taxi stands
+//!
+//! Every dataset is stored as `<name>.json`, in the same format as the Datamall response, next to
+//! `<name>.meta.json` which records when it was fetched and the `ETag`/`Last-Modified` of each
+//! page of the response, if any. Once a dataset is stale, every page is revalidated with a
+//! conditional request, and only the pages that changed are downloaded again.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::disk_cache::DiskCache;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let client = LTAClient::with_api_key("API_KEY")?;
+//!     let cache = DiskCache::new("./lta-cache").max_age(Duration::from_secs(7 * 24 * 60 * 60));
+//!     let bus_stops = Bus::get_all_bus_stops_cached(&client, &cache).await?;
+//!     println!("{}", bus_stops.len());
+//!     Ok(())
+//! }
+//! ```
+use reqwest::header::{HeaderMap, ETAG, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Records of a Datamall response, kept undecoded so that they can be stored as is
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub(crate) struct RawPage {
+    pub value: Vec<Value>,
+}
+
+/// Metadata stored next to every cached dataset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct CacheMetadata {
+    /// Seconds since the unix epoch when the dataset was last fetched or revalidated
+    pub fetched_at: u64,
+
+    /// Validators of every page of the dataset, in `$skip` order
+    pub pages: Vec<PageMetadata>,
+
+    /// Number of records in the dataset
+    pub records: usize,
+}
+
+/// Validators of one page of a cached dataset
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PageMetadata {
+    /// `ETag` header of the response, if any
+    pub etag: Option<String>,
+
+    /// `Last-Modified` header of the response, if any
+    pub last_modified: Option<String>,
+
+    /// Number of records in the page
+    pub records: usize,
+}
+
+impl CacheMetadata {
+    /// Time since the dataset was last fetched or revalidated
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(unix_now().saturating_sub(self.fetched_at))
+    }
+
+    /// Returns `true` if a conditional request can be sent to revalidate the dataset
+    pub fn has_validators(&self) -> bool {
+        self.pages.iter().any(PageMetadata::has_validators)
+    }
+}
+
+impl PageMetadata {
+    pub(crate) fn from_headers(headers: &HeaderMap, records: usize) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToString::to_string)
+        };
+
+        PageMetadata {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            records,
+        }
+    }
+
+    /// Returns `true` if a conditional request can be sent to revalidate the page
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+}
+
+/// Directory of cached datasets
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+    max_age: Duration,
+    force_refresh: bool,
+}
+
+impl DiskCache {
+    /// Stores datasets in `dir`, which is created if it does not exist. Datasets stay fresh for a
+    /// day by default
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        DiskCache {
+            dir: dir.into(),
+            max_age: Duration::from_secs(24 * 60 * 60),
+            force_refresh: false,
+        }
+    }
+
+    /// How long a dataset is read from disk before it is revalidated or downloaded again
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Always download datasets again, even if they are fresh
+    pub fn force_refresh(mut self, force_refresh: bool) -> Self {
+        self.force_refresh = force_refresh;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the metadata of a cached dataset, eg `BusStops`
+    pub fn metadata(&self, name: &str) -> Option<CacheMetadata> {
+        let bytes = fs::read(self.meta_path(name)).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    /// Removes a cached dataset, eg `BusStops`
+    pub fn invalidate(&self, name: &str) -> io::Result<()> {
+        for path in [self.meta_path(name), self.data_path(name)].iter() {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns `true` if the dataset can be used without revalidating it
+    pub fn is_fresh(&self, metadata: &CacheMetadata) -> bool {
+        !self.force_refresh && metadata.age() < self.max_age
+    }
+
+    /// Returns metadata that can be used to revalidate the dataset, unless refreshing is forced
+    pub(crate) fn validators(&self, metadata: &CacheMetadata) -> Option<CacheMetadata> {
+        if self.force_refresh || !metadata.has_validators() {
+            return None;
+        }
+
+        Some(metadata.clone())
+    }
+
+    pub(crate) fn load(&self, name: &str) -> Option<(CacheMetadata, RawPage)> {
+        let metadata = self.metadata(name)?;
+        let bytes = fs::read(self.data_path(name)).ok()?;
+        let page = serde_json::from_slice(&bytes).ok()?;
+        Some((metadata, page))
+    }
+
+    pub(crate) fn store(
+        &self,
+        name: &str,
+        metadata: &CacheMetadata,
+        page: &RawPage,
+    ) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write_atomic(&self.data_path(name), &serde_json::to_vec(page)?)?;
+        write_atomic(&self.meta_path(name), &serde_json::to_vec_pretty(metadata)?)
+    }
+
+    fn data_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn meta_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.meta.json", name))
+    }
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(tmp, path)
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::disk_cache::{unix_now, CacheMetadata, DiskCache, PageMetadata, RawPage};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn store_and_load() {
+        let dir = std::env::temp_dir().join(format!("lta-disk-cache-{}", std::process::id()));
+        let cache = DiskCache::new(&dir).max_age(Duration::from_secs(60));
+        let page = RawPage {
+            value: vec![json!({ "BusStopCode": "01012" })],
+        };
+        let metadata = CacheMetadata {
+            fetched_at: unix_now(),
+            pages: vec![PageMetadata {
+                etag: Some("\"abc\"".to_string()),
+                last_modified: None,
+                records: 1,
+            }],
+            records: 1,
+        };
+
+        cache.store("BusStops", &metadata, &page).unwrap();
+        let (loaded_metadata, loaded_page) = cache.load("BusStops").unwrap();
+        assert_eq!(loaded_metadata, metadata);
+        assert_eq!(loaded_page.value, page.value);
+        assert!(cache.is_fresh(&loaded_metadata));
+        assert!(cache.validators(&loaded_metadata).is_some());
+
+        let cache = cache.force_refresh(true);
+        assert!(!cache.is_fresh(&loaded_metadata));
+        assert!(cache.validators(&loaded_metadata).is_none());
+
+        cache.invalidate("BusStops").unwrap();
+        assert!(cache.load("BusStops").is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stale_metadata() {
+        let cache = DiskCache::new("unused").max_age(Duration::from_secs(60));
+        let metadata = CacheMetadata {
+            fetched_at: unix_now() - 120,
+            ..CacheMetadata::default()
+        };
+
+        assert!(!cache.is_fresh(&metadata));
+        assert!(cache.validators(&metadata).is_none());
+    }
+}
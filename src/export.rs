@@ -0,0 +1,673 @@
+//! Flat file exports of API results, as CSV or newline delimited JSON
+//!
+//! CSV columns are named after the fields of each model, in declaration order, so that files
+//! written by different versions line up. Nested fields are flattened, eg the three `NextBus`
+//! entries of a bus arrival become `next_bus_1_*` to `next_bus_3_*` columns, and responses with a
+//! list of entries are written as one row per entry, eg one row per service of a bus arrival.
+//! Both writers accept records one at a time, so pages can be written as they are fetched.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use futures::TryStreamExt;
+//! use lta::export::CsvWriter;
+//! use lta::r#async::paginate::records;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult, PageOptions};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let client = LTAClient::with_api_key("API_KEY")?;
+//!     let routes = records(|skip| Bus::get_bus_routes(&client, skip), PageOptions::new());
+//!     futures::pin_mut!(routes);
+//!
+//!     let file = std::fs::File::create("bus_routes.csv").expect("Unable to create file");
+//!     let mut csv = CsvWriter::new(file);
+//!     while let Some(route) = routes.try_next().await? {
+//!         csv.write(&route)?;
+//!     }
+//!     csv.flush()
+//! }
+//! ```
+use crate::facilities::FacilityMaintenance;
+use crate::models::bus::prelude::*;
+use crate::models::taxi::prelude::TaxiStand;
+use crate::models::traffic::prelude::*;
+use crate::models::train::prelude::{AffectedSegment, TrainServiceAlert, TrainServiceAlertMessage};
+use crate::models::utils::Coordinates;
+use crate::passenger_vol::{NodeVolume, OdVolume};
+use crate::{LTAError, LTAResult};
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+
+/// Separator of the elements of list fields, eg the vehicle types of an ERP rate
+const LIST_SEPARATOR: &str = ";";
+
+/// Model that can be written as CSV rows
+pub trait Record {
+    /// Names of the columns, in the order of the cells of [`Record::rows`]
+    fn columns() -> Vec<String>;
+
+    /// Cells of every row of the model. Most models are written as one row
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+/// Formats a field as a CSV cell. Strings are written without quotes, `None` as an empty cell
+/// and lists of scalars joined by `;`. Anything else is written as JSON
+pub(crate) fn cell<T: Serialize + ?Sized>(value: &T) -> String {
+    match serde_json::to_value(value).unwrap_or(Value::Null) {
+        Value::Array(values) if values.iter().all(|v| !v.is_array() && !v.is_object()) => values
+            .into_iter()
+            .map(scalar)
+            .collect::<Vec<_>>()
+            .join(LIST_SEPARATOR),
+        value => scalar(value),
+    }
+}
+
+fn scalar(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Models whose fields are all written as a single cell
+macro_rules! impl_record {
+    ($($model: ty { $($field: ident),+ $(,)? }),+ $(,)?) => {
+        $(
+            impl Record for $model {
+                fn columns() -> Vec<String> {
+                    vec![$(stringify!($field).to_string()),+]
+                }
+
+                fn rows(&self) -> Vec<Vec<String>> {
+                    vec![vec![$(cell(&self.$field)),+]]
+                }
+            }
+        )+
+    };
+}
+
+impl_record!(
+    BusStop {
+        bus_stop_code,
+        road_name,
+        desc,
+        lat,
+        long
+    },
+    BusRoute {
+        service_no,
+        operator,
+        direction,
+        stop_seq,
+        bus_stop_code,
+        dist,
+        wd_first,
+        wd_last,
+        sat_first,
+        sat_last,
+        sun_first,
+        sun_last,
+    },
+    TaxiStand {
+        taxi_code,
+        lat,
+        long,
+        is_barrier_free,
+        owner,
+        stand_type,
+        name
+    },
+    Coordinates { lat, long },
+    ErpRate {
+        vehicle_type,
+        day_type,
+        start_time,
+        end_time,
+        zone_id,
+        charge_amt,
+        effective_date,
+    },
+    EstTravelTime {
+        name,
+        direction,
+        far_end_pt,
+        start_pt,
+        end_pt,
+        est_travel_time
+    },
+    FaultyTrafficLight {
+        alarm_id,
+        node_id,
+        technical_alarm_type,
+        start_date,
+        end_date,
+        message
+    },
+    RoadDetails {
+        event_id,
+        start_date,
+        end_date,
+        service_dept,
+        road_name,
+        other
+    },
+    TrafficImage {
+        camera_id,
+        lat,
+        long,
+        image_link
+    },
+    TrafficIncident {
+        incident_type,
+        lat,
+        long,
+        msg
+    },
+    VMS {
+        equipment_id,
+        lat,
+        long,
+        msg
+    },
+    BikeParking {
+        desc,
+        lat,
+        long,
+        rack_type,
+        rack_count,
+        shelter_indicator
+    },
+    AffectedSegment {
+        line,
+        direction,
+        stations,
+        free_public_bus,
+        free_mrt_shuttle,
+        mrt_shuttle_dir
+    },
+    TrainServiceAlertMessage {
+        content,
+        created_date
+    },
+    NodeVolume {
+        year_month,
+        day_type,
+        hour,
+        pt_type,
+        pt_code,
+        tap_in,
+        tap_out
+    },
+    OdVolume {
+        year_month,
+        day_type,
+        hour,
+        pt_type,
+        origin_pt_code,
+        destination_pt_code,
+        total_trips,
+    },
+    FacilityMaintenance {
+        line,
+        station_code,
+        station_name,
+        facility_type,
+        facility_id,
+        location,
+        start_date,
+        end_date,
+    },
+);
+
+/// Frequencies are flattened into `*_freq_min` and `*_freq_max` columns
+impl Record for BusService {
+    fn columns() -> Vec<String> {
+        let mut columns: Vec<String> = [
+            "service_no",
+            "operator",
+            "no_direction",
+            "category",
+            "origin_code",
+            "dest_code",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect();
+        for freq in &["am_peak", "am_offpeak", "pm_peak", "pm_offpeak"] {
+            columns.push(format!("{}_freq_min", freq));
+            columns.push(format!("{}_freq_max", freq));
+        }
+        columns.push("loop_desc".to_string());
+        columns
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let mut cells = vec![
+            cell(&self.service_no),
+            cell(&self.operator),
+            cell(&self.no_direction),
+            cell(&self.category),
+            cell(&self.origin_code),
+            cell(&self.dest_code),
+        ];
+        for freq in &[
+            &self.am_peak_freq,
+            &self.am_offpeak_freq,
+            &self.pm_peak_freq,
+            &self.pm_offpeak_freq,
+        ] {
+            cells.push(cell(&freq.min));
+            cells.push(cell(&freq.max));
+        }
+        cells.push(cell(&self.loop_desc));
+        vec![cells]
+    }
+}
+
+/// Fields of each `NextBus`, flattened into `next_bus_{1,2,3}_*` columns
+const NEXT_BUS_FIELDS: [&str; 9] = [
+    "origin_code",
+    "dest_code",
+    "est_arrival",
+    "lat",
+    "long",
+    "visit_no",
+    "load",
+    "feature",
+    "bus_type",
+];
+
+/// One row per service at the stop. Buses that are not coming have empty `next_bus_*` cells
+impl Record for BusArrivalResp {
+    fn columns() -> Vec<String> {
+        let mut columns = vec![
+            "bus_stop_code".to_string(),
+            "service_no".to_string(),
+            "operator".to_string(),
+        ];
+        for n in 1..=3 {
+            columns.extend(
+                NEXT_BUS_FIELDS
+                    .iter()
+                    .map(|field| format!("next_bus_{}_{}", n, field)),
+            );
+        }
+        columns
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.services
+            .iter()
+            .map(|service| {
+                let mut cells = vec![
+                    cell(&self.bus_stop_code),
+                    cell(&service.service_no),
+                    cell(&service.operator),
+                ];
+                for next_bus in &service.next_bus {
+                    match next_bus {
+                        Some(bus) => cells.extend(vec![
+                            cell(&bus.origin_code),
+                            cell(&bus.dest_code),
+                            cell(&bus.est_arrival),
+                            cell(&bus.lat),
+                            cell(&bus.long),
+                            cell(&bus.visit_no),
+                            cell(&bus.load),
+                            cell(&bus.feature),
+                            cell(&bus.bus_type),
+                        ]),
+                        None => cells.extend(NEXT_BUS_FIELDS.iter().map(|_| String::new())),
+                    }
+                }
+                cells
+            })
+            .collect()
+    }
+}
+
+/// Coordinates are flattened into `lat` and `long` columns, empty if the car park has none
+/// One row per message, with the status of the alert. Alerts without messages are written as a
+/// row of their status, so that the status is never dropped. The status is written as Datamall
+/// gives it, `1` for normal service and `2` for a disruption
+impl Record for TrainServiceAlert {
+    fn columns() -> Vec<String> {
+        let mut columns = vec!["status".to_string()];
+        columns.extend(TrainServiceAlertMessage::columns());
+        columns
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let status = cell(&self.status);
+        if self.message.is_empty() {
+            let blank = TrainServiceAlertMessage::columns()
+                .into_iter()
+                .map(|_| String::new());
+            return vec![std::iter::once(status).chain(blank).collect()];
+        }
+
+        self.message
+            .iter()
+            .flat_map(Record::rows)
+            .map(|row| std::iter::once(status.clone()).chain(row).collect())
+            .collect()
+    }
+}
+
+impl Record for CarPark {
+    fn columns() -> Vec<String> {
+        [
+            "carpark_id",
+            "area",
+            "dev",
+            "lat",
+            "long",
+            "avail_lots",
+            "lot_type",
+            "agency",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            cell(&self.carpark_id),
+            cell(&self.area),
+            cell(&self.dev),
+            cell(&self.coords.as_ref().map(|c| c.lat)),
+            cell(&self.coords.as_ref().map(|c| c.long)),
+            cell(&self.avail_lots),
+            cell(&self.lot_type),
+            cell(&self.agency),
+        ]]
+    }
+}
+
+/// The start and end of the segment are flattened into `start_*` and `end_*` columns
+impl Record for TrafficSpeedBand {
+    fn columns() -> Vec<String> {
+        [
+            "link_id",
+            "road_name",
+            "road_category",
+            "speed_band",
+            "min_speed",
+            "max_speed",
+            "start_lat",
+            "start_long",
+            "end_lat",
+            "end_long",
+        ]
+        .iter()
+        .map(|c| c.to_string())
+        .collect()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let loc = self.coord_start_end.as_ref();
+        vec![vec![
+            cell(&self.link_id),
+            cell(&self.road_name),
+            cell(&self.road_category),
+            cell(&self.speed_band),
+            cell(&self.min_speed),
+            cell(&self.max_speed),
+            cell(&loc.map(|l| l.start.lat)),
+            cell(&loc.map(|l| l.start.long)),
+            cell(&loc.map(|l| l.end.lat)),
+            cell(&loc.map(|l| l.end.long)),
+        ]]
+    }
+}
+
+fn write_error<E: std::fmt::Display>(e: E) -> LTAError {
+    LTAError::Custom(format!("Unable to write export: {}", e))
+}
+
+/// Writes records as CSV, with a header row before the first record
+pub struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    has_header: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W) -> Self {
+        CsvWriter {
+            writer: csv::Writer::from_writer(writer),
+            has_header: false,
+        }
+    }
+
+    pub fn write<T: Record>(&mut self, record: &T) -> LTAResult<()> {
+        if !self.has_header {
+            self.writer
+                .write_record(T::columns())
+                .map_err(write_error)?;
+            self.has_header = true;
+        }
+
+        record
+            .rows()
+            .into_iter()
+            .try_for_each(|row| self.writer.write_record(row))
+            .map_err(write_error)
+    }
+
+    pub fn write_all<'a, T, I>(&mut self, records: I) -> LTAResult<()>
+    where
+        T: Record + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        records.into_iter().try_for_each(|r| self.write(r))
+    }
+
+    pub fn flush(&mut self) -> LTAResult<()> {
+        self.writer.flush().map_err(write_error)
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn into_inner(self) -> LTAResult<W> {
+        self.writer.into_inner().map_err(write_error)
+    }
+}
+
+/// Writes records as newline delimited JSON, one object per line, named as the models serialize
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer }
+    }
+
+    pub fn write<T: Serialize>(&mut self, record: &T) -> LTAResult<()> {
+        serde_json::to_writer(&mut self.writer, record).map_err(write_error)?;
+        self.writer.write_all(b"\n").map_err(write_error)
+    }
+
+    pub fn write_all<'a, T, I>(&mut self, records: I) -> LTAResult<()>
+    where
+        T: Serialize + 'a,
+        I: IntoIterator<Item = &'a T>,
+    {
+        records.into_iter().try_for_each(|r| self.write(r))
+    }
+
+    pub fn flush(&mut self) -> LTAResult<()> {
+        self.writer.flush().map_err(write_error)
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn into_inner(mut self) -> LTAResult<W> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Writes `records` to `writer` as CSV
+pub fn to_csv<T: Record, W: Write>(records: &[T], writer: W) -> LTAResult<W> {
+    let mut csv = CsvWriter::new(writer);
+    csv.write_all(records)?;
+    csv.into_inner()
+}
+
+/// Writes `records` to `writer` as newline delimited JSON
+pub fn to_ndjson<T: Serialize, W: Write>(records: &[T], writer: W) -> LTAResult<W> {
+    let mut ndjson = NdjsonWriter::new(writer);
+    ndjson.write_all(records)?;
+    ndjson.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bus::bus_arrival::{ArrivalBusService, NextBus};
+    use crate::models::bus_enums::{BusFeature, BusLoad, BusType, Operator};
+    use crate::models::chrono::DateTime;
+    use crate::models::train::prelude::TrainStatus;
+
+    fn next_bus(est_arrival: &str) -> NextBus {
+        NextBus {
+            origin_code: 77009,
+            dest_code: 77009,
+            est_arrival: DateTime::parse_from_rfc3339(est_arrival).unwrap(),
+            lat: 1.3,
+            long: 103.9,
+            visit_no: 1,
+            load: BusLoad::SeatsAvailable,
+            feature: Some(BusFeature::WheelChairAccessible),
+            bus_type: BusType::DoubleDecker,
+        }
+    }
+
+    #[test]
+    fn flattens_next_buses() {
+        let service = ArrivalBusService {
+            service_no: "15".to_string(),
+            operator: Operator::GAS,
+            next_bus: [
+                Some(next_bus("2020-12-01T08:02:00+08:00")),
+                Some(next_bus("2020-12-01T08:10:00+08:00")),
+                None,
+            ],
+        };
+        let arrivals = vec![
+            BusArrivalResp {
+                bus_stop_code: 83139,
+                services: vec![service.clone()],
+            },
+            BusArrivalResp {
+                bus_stop_code: 83111,
+                services: vec![service],
+            },
+        ];
+        let csv = to_csv(&arrivals, Vec::new()).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+
+        let header: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(header.len(), 3 + 3 * NEXT_BUS_FIELDS.len());
+        assert_eq!(header[3], "next_bus_1_origin_code");
+        assert_eq!(header[21], "next_bus_3_origin_code");
+
+        let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(row[..2], ["83139", "15"]);
+        assert_eq!(row[5], "2020-12-01T08:02:00+08:00");
+        assert_eq!(row[14], "2020-12-01T08:10:00+08:00");
+        assert!(row[21..].iter().all(|c| c.is_empty()));
+
+        let row: Vec<&str> = lines.next().unwrap().split(',').collect();
+        assert_eq!(row[..2], ["83111", "15"]);
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn writes_train_service_alert_messages() {
+        let message = TrainServiceAlertMessage {
+            content: "1710hrs : NSL - No train service between Jurong East and Woodlands"
+                .to_string(),
+            created_date: "2017-12-01 17:54:21".to_string(),
+        };
+        let csv = String::from_utf8(to_csv(&[message], Vec::new()).unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "content,created_date",
+                "1710hrs : NSL - No train service between Jurong East and Woodlands,2017-12-01 17:54:21",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_train_service_alert_status() {
+        let message = TrainServiceAlertMessage {
+            content: "1710hrs : NSL - No train service between Jurong East and Woodlands"
+                .to_string(),
+            created_date: "2017-12-01 17:54:21".to_string(),
+        };
+        let alert = TrainServiceAlert {
+            status: TrainStatus::Disrupted,
+            affected_segments: Vec::new(),
+            message: vec![message],
+        };
+        let csv = String::from_utf8(to_csv(&[alert], Vec::new()).unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "status,content,created_date",
+                "2,1710hrs : NSL - No train service between Jurong East and Woodlands,2017-12-01 17:54:21",
+            ]
+        );
+
+        let normal = TrainServiceAlert {
+            status: TrainStatus::Normal,
+            affected_segments: Vec::new(),
+            message: Vec::new(),
+        };
+        assert_eq!(normal.rows(), vec![vec!["1", "", ""]]);
+    }
+
+    #[test]
+    fn writes_one_json_object_per_line() {
+        let stops = vec![
+            BusStop {
+                bus_stop_code: 1012,
+                road_name: "Victoria St".to_string(),
+                desc: "Hotel Grand Pacific".to_string(),
+                lat: 1.29684825487647,
+                long: 103.85253591654006,
+            },
+            BusStop {
+                bus_stop_code: 1013,
+                road_name: "Victoria St, Bugis".to_string(),
+                desc: "St. Joseph's Ch".to_string(),
+                lat: 1.29770970610083,
+                long: 103.8532247463225,
+            },
+        ];
+
+        let ndjson = String::from_utf8(to_ndjson(&stops, Vec::new()).unwrap()).unwrap();
+        let decoded: Vec<Value> = ndjson
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1], serde_json::to_value(&stops[1]).unwrap());
+
+        let csv = String::from_utf8(to_csv(&stops, Vec::new()).unwrap()).unwrap();
+        assert_eq!(
+            csv.lines().collect::<Vec<_>>(),
+            vec![
+                "bus_stop_code,road_name,desc,lat,long",
+                "1012,Victoria St,Hotel Grand Pacific,1.29684825487647,103.85253591654006",
+                "1013,\"Victoria St, Bugis\",St. Joseph's Ch,1.29770970610083,103.8532247463225",
+            ]
+        );
+    }
+}
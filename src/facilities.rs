@@ -0,0 +1,175 @@
+//! Typed facilities maintenance schedules, read from the JSON files that
+//! `FacilityReqeusts::get_facilities_maintenance` links to, and the station codes of each line
+use crate::models::chrono::NaiveDate;
+use crate::models::train::prelude::{MrtLine, StationCode};
+use crate::{LTAError, LTAResult};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// Number of stations requested at a time when querying a whole line
+pub const LINE_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+pub enum FacilityType {
+    Lift,
+    Escalator,
+
+    #[default]
+    #[serde(other)]
+    Unknown,
+}
+
+/// Maintenance of a lift or escalator at a station
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FacilityMaintenance {
+    #[serde(rename = "Line")]
+    pub line: MrtLine,
+
+    #[serde(rename = "StationCode")]
+    pub station_code: StationCode,
+
+    #[serde(rename = "StationName")]
+    pub station_name: String,
+
+    #[serde(rename = "FacilityType", default)]
+    pub facility_type: FacilityType,
+
+    #[serde(rename = "FacilityID", alias = "LiftID", default)]
+    pub facility_id: String,
+
+    /// Where the facility is in the station, eg `Exit B, Street Level - Concourse`
+    #[serde(rename = "Location", alias = "LiftDesc", default)]
+    pub location: String,
+
+    #[serde(rename = "StartDate", default, deserialize_with = "from_str_to_date")]
+    pub start_date: Option<NaiveDate>,
+
+    /// `None` if the end of the maintenance has not been announced
+    #[serde(rename = "EndDate", default, deserialize_with = "from_str_to_date")]
+    pub end_date: Option<NaiveDate>,
+}
+
+impl FacilityMaintenance {
+    /// Returns `true` if the facility is under maintenance on `date`
+    pub fn is_active_on(&self, date: NaiveDate) -> bool {
+        self.start_date.into_iter().all(|start| start <= date)
+            && self.end_date.into_iter().all(|end| date <= end)
+    }
+}
+
+/// Accepts dates with or without a time, eg `2024-06-03` and `2024-06-03T00:00:00`. Blank dates
+/// are `None`
+fn from_str_to_date<'de, D>(deserializer: D) -> Result<Option<NaiveDate>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    let date = s.trim().get(..10).unwrap_or_else(|| s.trim());
+    if date.is_empty() {
+        return Ok(None);
+    }
+
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Decodes a maintenance file, either an array of records or an object with a `value` array
+pub fn parse_maintenance(json: &[u8], url: &str) -> LTAResult<Vec<FacilityMaintenance>> {
+    let value: Value = serde_json::from_slice(json).map_err(|e| LTAError::decode(url, e))?;
+    let records = match value {
+        Value::Object(mut obj) => obj.remove("value").unwrap_or(Value::Array(Vec::new())),
+        other => other,
+    };
+
+    serde_json::from_value(records).map_err(|e| LTAError::decode(url, e))
+}
+
+/// Maintenance schedules of the files linked for a station. The files can hold schedules of
+/// other stations too, eg the other codes of an interchange, which are kept in `unmatched`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StationMaintenance {
+    /// Schedules of the requested station
+    pub records: Vec<FacilityMaintenance>,
+
+    /// Schedules whose station code is not the requested one, including codes that the models
+    /// do not know
+    pub unmatched: Vec<FacilityMaintenance>,
+}
+
+impl StationMaintenance {
+    /// Splits `records` into the schedules of `station_code` and the rest
+    pub fn split(records: Vec<FacilityMaintenance>, station_code: &StationCode) -> Self {
+        let (records, unmatched) = records
+            .into_iter()
+            .partition(|r| r.station_code == *station_code);
+
+        StationMaintenance { records, unmatched }
+    }
+}
+
+/// Returns the station codes of `line` in order. Interchanges shared by the LRT loops, eg `STC`,
+/// are included in each loop
+pub fn station_codes(line: &MrtLine) -> Vec<StationCode> {
+    use StationCode::*;
+
+    match line {
+        MrtLine::CCL => vec![
+            CC1, CC2, CC3, CC4, CC5, CC6, CC7, CC8, CC9, CC10, CC11, CC12, CC13, CC14, CC15, CC16,
+            CC17, CC19, CC20, CC21, CC22, CC23, CC24, CC25, CC26, CC27, CC28, CC29, CC33, CC34,
+        ],
+        MrtLine::CEL => vec![CE1, CE2],
+        MrtLine::CGL => vec![CG, CG1, CG2],
+        MrtLine::DTL => vec![
+            DT1, DT2, DT3, DT5, DT6, DT7, DT8, DT9, DT10, DT11, DT12, DT13, DT14, DT15, DT16, DT17,
+            DT18, DT19, DT20, DT21, DT22, DT23, DT24, DT25, DT26, DT27, DT28, DT29, DT30, DT31,
+            DT32, DT33, DT34, DT35,
+        ],
+        MrtLine::EWL => vec![
+            EW1, EW2, EW3, EW4, EW5, EW6, EW7, EW8, EW9, EW10, EW11, EW12, EW13, EW14, EW15, EW16,
+            EW17, EW18, EW19, EW20, EW21, EW22, EW23, EW24, EW25, EW26, EW27, EW28, EW29, EW30,
+            EW31, EW32, EW33,
+        ],
+        MrtLine::NEL => vec![
+            NE1, NE3, NE4, NE5, NE6, NE7, NE8, NE9, NE10, NE11, NE12, NE13, NE14, NE15, NE16, NE17,
+        ],
+        MrtLine::NSL => vec![
+            NS1, NS2, NS3, NS4, NS5, NS7, NS8, NS9, NS10, NS11, NS12, NS13, NS14, NS15, NS16, NS17,
+            NS18, NS19, NS20, NS21, NS22, NS23, NS24, NS25, NS26, NS27, NS28,
+        ],
+        MrtLine::PEL => vec![PTC, PE1, PE2, PE3, PE4, PE5, PE6, PE7],
+        MrtLine::PWL => vec![PTC, PW1, PW2, PW3, PW4, PW5, PW6, PW7],
+        MrtLine::SEL => vec![STC, SE1, SE2, SE3, SE4, SE5],
+        MrtLine::SWL => vec![STC, SW1, SW2, SW3, SW4, SW5, SW6, SW7, SW8],
+        MrtLine::BPL => vec![
+            BP1, BP2, BP3, BP4, BP5, BP6, BP7, BP8, BP9, BP10, BP11, BP12, BP13, BP14,
+        ],
+        MrtLine::Unknown => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_station_codes_of_line() {
+        let codes = station_codes(&MrtLine::CGL);
+        assert_eq!(
+            codes,
+            vec![StationCode::CG, StationCode::CG1, StationCode::CG2]
+        );
+
+        let codes = station_codes(&MrtLine::SEL);
+        assert_eq!(codes.first(), Some(&StationCode::STC));
+        assert_eq!(codes.last(), Some(&StationCode::SE5));
+
+        // NS6 is not a station yet
+        let codes = station_codes(&MrtLine::NSL);
+        assert_eq!(codes.len(), 27);
+        assert_eq!(codes[4..6], [StationCode::NS5, StationCode::NS7]);
+
+        let codes = station_codes(&MrtLine::CEL);
+        assert_eq!(codes, vec![StationCode::CE1, StationCode::CE2]);
+    }
+}
@@ -0,0 +1,324 @@
+//! Conversion of location bearing models into GeoJSON, behind the `geojson` feature
+//!
+//! Every model becomes a `Feature` with a `Point`, or a `LineString` for speed band segments, in
+//! WGS84 longitude and latitude. Every other field of the model is kept as a property, named as
+//! the model serializes it. Models without coordinates, eg car parks that Datamall has no
+//! location for, are left out of collections.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::geojson::ToFeatureCollection;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let bus_stops = Bus::get_all_bus_stops(&client).await?;
+//!     println!("{}", bus_stops.to_feature_collection());
+//!     Ok(())
+//! }
+//! ```
+use crate::geospatial::{self, FieldValue};
+use crate::models::bus::prelude::BusStop;
+use crate::models::taxi::prelude::TaxiStand;
+use crate::models::traffic::prelude::*;
+use crate::models::utils::Coordinates;
+use ::geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue, Value};
+use serde::Serialize;
+
+/// Model that can be placed on a map
+pub trait ToFeature {
+    /// Returns `None` if the model has no coordinates
+    fn to_feature(&self) -> Option<Feature>;
+}
+
+/// Collection of models that can be placed on a map
+pub trait ToFeatureCollection {
+    fn to_feature_collection(&self) -> FeatureCollection;
+}
+
+impl<T: ToFeature> ToFeatureCollection for [T] {
+    fn to_feature_collection(&self) -> FeatureCollection {
+        FeatureCollection {
+            bbox: None,
+            features: self.iter().filter_map(ToFeature::to_feature).collect(),
+            foreign_members: None,
+        }
+    }
+}
+
+impl<T: ToFeature> ToFeatureCollection for Vec<T> {
+    fn to_feature_collection(&self) -> FeatureCollection {
+        self.as_slice().to_feature_collection()
+    }
+}
+
+fn position(lat: f64, long: f64) -> Vec<f64> {
+    vec![long, lat]
+}
+
+fn point(lat: f64, long: f64) -> Value {
+    Value::Point(position(lat, long))
+}
+
+/// Serializes `model` into properties, without the fields holding its coordinates
+fn properties<T: Serialize>(model: &T, coord_fields: &[&str]) -> JsonObject {
+    match serde_json::to_value(model) {
+        Ok(JsonValue::Object(mut props)) => {
+            for field in coord_fields {
+                props.remove(*field);
+            }
+            props
+        }
+        _ => JsonObject::new(),
+    }
+}
+
+fn feature(geometry: Value, properties: JsonObject) -> Feature {
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(geometry)),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+/// Models with `lat` and `long` fields
+macro_rules! impl_point_feature {
+    ($($model: ty),+) => {
+        $(
+            impl ToFeature for $model {
+                fn to_feature(&self) -> Option<Feature> {
+                    let props = properties(self, &["lat", "long"]);
+                    Some(feature(point(self.lat, self.long), props))
+                }
+            }
+        )+
+    };
+}
+
+impl_point_feature!(
+    BusStop,
+    TaxiStand,
+    BikeParking,
+    TrafficImage,
+    TrafficIncident,
+    Coordinates
+);
+
+impl ToFeature for CarPark {
+    fn to_feature(&self) -> Option<Feature> {
+        let coords = self.coords.as_ref()?;
+        let props = properties(self, &["coords"]);
+        Some(feature(point(coords.lat, coords.long), props))
+    }
+}
+
+impl ToFeature for TrafficSpeedBand {
+    fn to_feature(&self) -> Option<Feature> {
+        let loc = self.coord_start_end.as_ref()?;
+        let line = Value::LineString(vec![
+            position(loc.start.lat, loc.start.long),
+            position(loc.end.lat, loc.end.long),
+        ]);
+        Some(feature(line, properties(self, &["coord_start_end"])))
+    }
+}
+
+/// Twice the signed area of a ring, positive if it is anticlockwise
+fn signed_area(ring: &[geospatial::Point]) -> f64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum()
+}
+
+/// Returns `true` if `point` is inside `ring`, by casting a ray towards increasing x
+fn contains(ring: &[geospatial::Point], point: &geospatial::Point) -> bool {
+    let mut inside = false;
+    for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Groups the rings of a shapefile polygon into polygons of an outer ring followed by its holes.
+/// Shapefile outer rings are clockwise and holes anticlockwise, while GeoJSON wants the opposite,
+/// so every ring is reversed. Holes go to the first outer ring around them
+fn polygons(rings: &[Vec<geospatial::Point>]) -> Vec<Vec<Vec<geospatial::Point>>> {
+    let reversed = |ring: &Vec<geospatial::Point>| ring.iter().rev().copied().collect();
+    let (outers, holes): (Vec<_>, Vec<_>) = rings
+        .iter()
+        .filter(|ring| !ring.is_empty())
+        .partition(|ring| signed_area(ring) <= 0.0);
+
+    let mut polygons: Vec<Vec<Vec<geospatial::Point>>> =
+        outers.iter().map(|ring| vec![reversed(ring)]).collect();
+    for hole in holes {
+        let outer = outers.iter().position(|outer| contains(outer, &hole[0]));
+        match outer {
+            Some(i) => polygons[i].push(reversed(hole)),
+            // Anticlockwise rings outside of every outer ring are outer rings wound the wrong way
+            None => polygons.push(vec![hole.clone()]),
+        }
+    }
+    polygons
+}
+
+/// Features of the geospatial layers, converted from SVY21 and with their attributes as
+/// properties
+impl ToFeature for geospatial::Feature {
+    fn to_feature(&self) -> Option<Feature> {
+        let to_position = |p: &geospatial::Point| {
+            let coords = p.to_wgs84();
+            position(coords.lat, coords.long)
+        };
+        let to_line = |points: &Vec<geospatial::Point>| points.iter().map(to_position).collect();
+
+        let geometry = match &self.geometry {
+            geospatial::Geometry::Null => return None,
+            geospatial::Geometry::Point(p) => Value::Point(to_position(p)),
+            geospatial::Geometry::MultiPoint(points) => {
+                Value::MultiPoint(points.iter().map(to_position).collect())
+            }
+            geospatial::Geometry::PolyLine(parts) => match parts.as_slice() {
+                [part] => Value::LineString(to_line(part)),
+                _ => Value::MultiLineString(parts.iter().map(to_line).collect()),
+            },
+            geospatial::Geometry::Polygon(rings) => {
+                let mut polygons: Vec<Vec<Vec<Vec<f64>>>> = polygons(rings)
+                    .into_iter()
+                    .map(|polygon| polygon.iter().map(to_line).collect())
+                    .collect();
+                match polygons.len() {
+                    1 => Value::Polygon(polygons.remove(0)),
+                    _ => Value::MultiPolygon(polygons),
+                }
+            }
+        };
+
+        let props = self
+            .attributes
+            .iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    FieldValue::Text(s) => JsonValue::from(s.as_str()),
+                    FieldValue::Number(n) => JsonValue::from(*n),
+                    FieldValue::Logical(b) => JsonValue::from(*b),
+                    FieldValue::Date(d) => JsonValue::from(d.to_string()),
+                    FieldValue::Null => JsonValue::Null,
+                };
+                (name.clone(), value)
+            })
+            .collect();
+
+        Some(feature(geometry, props))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::utils::Location;
+
+    #[test]
+    fn keeps_other_fields_as_properties() {
+        let stop = BusStop {
+            bus_stop_code: 1012,
+            road_name: "Victoria St".to_string(),
+            desc: "Hotel Grand Pacific".to_string(),
+            lat: 1.29684825487647,
+            long: 103.85253591654006,
+        };
+        let collection = vec![stop].to_feature_collection();
+        let feature = &collection.features[0];
+
+        let geometry = feature.geometry.as_ref().unwrap();
+        assert_eq!(
+            geometry.value,
+            Value::Point(vec![103.85253591654006, 1.29684825487647])
+        );
+        let props = feature.properties.as_ref().unwrap();
+        assert_eq!(props["bus_stop_code"], 1012);
+        assert_eq!(props["road_name"], "Victoria St");
+        assert!(!props.contains_key("lat"));
+    }
+
+    #[test]
+    fn multipart_polygons() {
+        let square = |x: f64, y: f64, size: f64| {
+            let corners = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0), (0.0, 0.0)];
+            corners
+                .iter()
+                .map(|(dx, dy)| geospatial::Point {
+                    x: x + dx * size,
+                    y: y + dy * size,
+                })
+                .collect::<Vec<_>>()
+        };
+        // Two clockwise outer rings, the first with an anticlockwise hole
+        let mut hole = square(30_100.0, 30_100.0, 100.0);
+        hole.reverse();
+        let rings = vec![
+            square(30_000.0, 30_000.0, 300.0),
+            square(31_000.0, 30_000.0, 100.0),
+            hole,
+        ];
+        let feature = geospatial::Feature {
+            geometry: geospatial::Geometry::Polygon(rings),
+            attributes: Default::default(),
+        }
+        .to_feature()
+        .unwrap();
+
+        let polygons = match feature.geometry.unwrap().value {
+            Value::MultiPolygon(polygons) => polygons,
+            other => panic!("Expected a MultiPolygon, got {:?}", other),
+        };
+        assert_eq!(polygons.len(), 2);
+        assert_eq!(polygons[0].len(), 2);
+        assert_eq!(polygons[1].len(), 1);
+
+        // Outer rings anticlockwise and holes clockwise, in longitude and latitude
+        let area = |ring: &Vec<Vec<f64>>| {
+            let points: Vec<geospatial::Point> = ring
+                .iter()
+                .map(|p| geospatial::Point { x: p[0], y: p[1] })
+                .collect();
+            signed_area(&points)
+        };
+        assert!(area(&polygons[0][0]) > 0.0);
+        assert!(area(&polygons[0][1]) < 0.0);
+        assert!(area(&polygons[1][0]) > 0.0);
+    }
+
+    #[test]
+    fn speed_bands_are_line_strings() {
+        let band = TrafficSpeedBand {
+            link_id: 103000000,
+            road_name: "KENT ROAD".to_string(),
+            road_category: RoadCategory::SlipRoads,
+            speed_band: 2,
+            min_speed: 10,
+            max_speed: 19,
+            coord_start_end: Some(Location::new(1.31, 103.85, 1.32, 103.86)),
+        };
+        let no_location = TrafficSpeedBand {
+            coord_start_end: None,
+            ..band.clone()
+        };
+
+        let collection = vec![band, no_location].to_feature_collection();
+        assert_eq!(collection.features.len(), 1);
+        assert_eq!(
+            collection.features[0].geometry.as_ref().unwrap().value,
+            Value::LineString(vec![vec![103.85, 1.31], vec![103.86, 1.32]])
+        );
+    }
+}
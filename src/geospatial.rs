@@ -0,0 +1,471 @@
+//! Features of the geospatial layers, decoded from the shapefiles that
+//! `GeoRequests::get_geospatial_whole_island` links to
+//!
+//! Each archive holds one or more shapefiles, ie a `.shp` file with the geometries, a `.dbf` file
+//! with the attributes and a `.prj` file with the projection. [`Shapefile::from_zip`] decodes
+//! every shapefile in an archive without GDAL.
+//!
+//! Coordinates are kept in the projection of the layer, which is SVY21 for every Datamall layer,
+//! ie `x` is the easting and `y` the northing in metres. [`Point::to_wgs84`] converts them to
+//! latitude and longitude. Z and M values are not decoded.
+use crate::coordinates::Svy21;
+use crate::models::chrono::NaiveDate;
+use crate::models::utils::Coordinates;
+use crate::{LTAError, LTAResult};
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+
+/// File code at the start of every `.shp` file
+const SHP_FILE_CODE: i32 = 9994;
+
+/// Length of the `.shp` file header
+const SHP_HEADER_LEN: usize = 100;
+
+/// Marks the end of the field descriptors of a `.dbf` file
+const DBF_HEADER_END: u8 = 0x0d;
+
+/// Marks the end of the records of a `.dbf` file
+const DBF_EOF: u8 = 0x1a;
+
+/// Max number of bytes reserved up front for a file of an archive, whatever size its header says
+const MAX_PREALLOC: u64 = 1 << 20;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+impl Point {
+    /// Converts a point of an SVY21 layer to WGS84 latitude and longitude
+    pub fn to_wgs84(&self) -> Coordinates {
+        Svy21::from(*self).to_wgs84()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    /// Shape without geometry
+    Null,
+
+    Point(Point),
+
+    MultiPoint(Vec<Point>),
+
+    /// Lines made of one or more parts
+    PolyLine(Vec<Vec<Point>>),
+
+    /// Polygons made of one or more rings. Outer rings are clockwise, holes are anticlockwise
+    Polygon(Vec<Vec<Point>>),
+}
+
+/// Value of an attribute in the `.dbf` file
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Text(String),
+    Number(f64),
+    Logical(bool),
+    Date(NaiveDate),
+
+    /// Blank numbers, dates and logicals
+    Null,
+}
+
+impl FieldValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            FieldValue::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            FieldValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+}
+
+/// Geometry of a shape and its attributes, keyed by field name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    pub geometry: Geometry,
+    pub attributes: BTreeMap<String, FieldValue>,
+}
+
+/// Every feature of a shapefile
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shapefile {
+    /// Name of the `.shp` file, without folders and extension
+    pub name: String,
+
+    /// Contents of the `.prj` file, `None` if the archive does not have one
+    pub projection: Option<String>,
+
+    /// Names of the attribute fields, in the order of the `.dbf` file
+    pub fields: Vec<String>,
+
+    pub features: Vec<Feature>,
+}
+
+impl Shapefile {
+    /// Decodes every shapefile in a ZIP archive as downloaded from a geospatial layer link. The
+    /// `.dbf` and `.prj` files of a shapefile are those with the same path as its `.shp` file
+    pub fn from_zip(archive: &[u8]) -> LTAResult<Vec<Self>> {
+        let invalid_zip =
+            |e: zip::result::ZipError| invalid(format!("Invalid geospatial archive: {}", e));
+        let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(invalid_zip)?;
+
+        let mut files = BTreeMap::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).map_err(invalid_zip)?;
+            if file.is_dir() {
+                continue;
+            }
+
+            let mut bytes = Vec::with_capacity(file.size().min(MAX_PREALLOC) as usize);
+            file.read_to_end(&mut bytes)
+                .map_err(|e| invalid(format!("Unable to read {}: {}", file.name(), e)))?;
+            files.insert(
+                file.name().to_ascii_lowercase(),
+                (file.name().to_string(), bytes),
+            );
+        }
+
+        let mut shapefiles = Vec::new();
+        for (key, (path, shp)) in &files {
+            let stem = match key.strip_suffix(".shp") {
+                Some(stem) => stem,
+                None => continue,
+            };
+
+            let name = path[..stem.len()].rsplit('/').next().unwrap_or(stem);
+            let dbf = files
+                .get(&format!("{}.dbf", stem))
+                .map(|(_, b)| b.as_slice());
+            let mut shapefile = Shapefile::parse(name, shp, dbf)?;
+            shapefile.projection = files
+                .get(&format!("{}.prj", stem))
+                .map(|(_, b)| String::from_utf8_lossy(b).trim().to_string());
+            shapefiles.push(shapefile);
+        }
+
+        if shapefiles.is_empty() {
+            return Err(invalid("No shapefile in geospatial archive".to_string()));
+        }
+
+        Ok(shapefiles)
+    }
+
+    /// Decodes the contents of a `.shp` file and its `.dbf` file. Shapes are paired with
+    /// attributes by their position, shapes whose attributes are marked deleted are skipped
+    pub fn parse(name: &str, shp: &[u8], dbf: Option<&[u8]>) -> LTAResult<Self> {
+        let geometries = parse_shp(shp)?;
+        let (fields, rows) = match dbf {
+            Some(dbf) => parse_dbf(dbf)?,
+            None => (Vec::new(), Vec::new()),
+        };
+
+        let mut rows = rows.into_iter();
+        let features = geometries
+            .into_iter()
+            .filter_map(|geometry| {
+                let attributes = match rows.next() {
+                    Some(Some(attributes)) => attributes,
+                    Some(None) => return None,
+                    None => BTreeMap::new(),
+                };
+                Some(Feature {
+                    geometry,
+                    attributes,
+                })
+            })
+            .collect();
+
+        Ok(Shapefile {
+            name: name.to_string(),
+            projection: None,
+            fields,
+            features,
+        })
+    }
+}
+
+fn invalid(msg: String) -> LTAError {
+    LTAError::Custom(msg)
+}
+
+/// Cursor over a byte slice that fails on truncated input
+struct Bytes<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Bytes { buf, pos: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.buf.get(self.pos).copied()
+    }
+
+    fn take(&mut self, n: usize) -> LTAResult<&'a [u8]> {
+        if n > self.remaining() {
+            return Err(invalid("Truncated shapefile".to_string()));
+        }
+
+        let bytes = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> LTAResult<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("Slice of length N"))
+    }
+
+    fn i32_be(&mut self) -> LTAResult<i32> {
+        Ok(i32::from_be_bytes(self.array()?))
+    }
+
+    fn i32_le(&mut self) -> LTAResult<i32> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    fn count(&mut self) -> LTAResult<usize> {
+        let n = self.i32_le()?;
+        n.try_into()
+            .map_err(|_| invalid(format!("Invalid count {} in shapefile", n)))
+    }
+
+    fn point(&mut self) -> LTAResult<Point> {
+        let x = f64::from_le_bytes(self.array()?);
+        let y = f64::from_le_bytes(self.array()?);
+        Ok(Point { x, y })
+    }
+
+    fn points(&mut self, n: usize) -> LTAResult<Vec<Point>> {
+        // Checked first so that a corrupt count does not allocate
+        if n > self.remaining() / 16 {
+            return Err(invalid("Truncated shapefile".to_string()));
+        }
+
+        (0..n).map(|_| self.point()).collect()
+    }
+}
+
+fn parse_shp(shp: &[u8]) -> LTAResult<Vec<Geometry>> {
+    let mut header = Bytes::new(shp);
+    if header.i32_be()? != SHP_FILE_CODE {
+        return Err(invalid("Not a shapefile".to_string()));
+    }
+
+    let mut bytes = Bytes::new(shp);
+    bytes.take(SHP_HEADER_LEN)?;
+    let mut geometries = Vec::new();
+    while bytes.remaining() >= 8 {
+        let _record_number = bytes.i32_be()?;
+        let words = bytes.i32_be()?;
+        let len = usize::try_from(words)
+            .map_err(|_| invalid(format!("Invalid record length {} in shapefile", words)))?;
+        let len = len
+            .checked_mul(2)
+            .ok_or_else(|| invalid(format!("Invalid record length {} in shapefile", words)))?;
+        geometries.push(parse_shape(bytes.take(len)?)?);
+    }
+
+    Ok(geometries)
+}
+
+fn parse_shape(content: &[u8]) -> LTAResult<Geometry> {
+    let mut bytes = Bytes::new(content);
+    let shape_type = bytes.i32_le()?;
+
+    // Z and M shapes start with the same fields as their 2D shape
+    let geometry = match shape_type {
+        0 => Geometry::Null,
+        1 | 11 | 21 => Geometry::Point(bytes.point()?),
+        3 | 13 | 23 => Geometry::PolyLine(parse_parts(&mut bytes)?),
+        5 | 15 | 25 => Geometry::Polygon(parse_parts(&mut bytes)?),
+        8 | 18 | 28 => {
+            bytes.take(32)?;
+            let n = bytes.count()?;
+            Geometry::MultiPoint(bytes.points(n)?)
+        }
+        other => {
+            return Err(invalid(format!(
+                "Unsupported shape type {} in shapefile",
+                other
+            )))
+        }
+    };
+
+    Ok(geometry)
+}
+
+fn parse_parts(bytes: &mut Bytes<'_>) -> LTAResult<Vec<Vec<Point>>> {
+    // Bounding box
+    bytes.take(32)?;
+    let num_parts = bytes.count()?;
+    let num_points = bytes.count()?;
+    if num_parts > bytes.remaining() / 4 {
+        return Err(invalid("Truncated shapefile".to_string()));
+    }
+
+    let starts = (0..num_parts)
+        .map(|_| bytes.count())
+        .collect::<LTAResult<Vec<_>>>()?;
+    let mut points = bytes.points(num_points)?;
+
+    let mut parts = Vec::with_capacity(num_parts);
+    for (i, start) in starts.iter().enumerate().rev() {
+        let end = starts.get(i + 1).copied().unwrap_or(num_points);
+        if *start > end || end > num_points {
+            return Err(invalid("Invalid part index in shapefile".to_string()));
+        }
+
+        parts.push(points.split_off(*start));
+    }
+    parts.reverse();
+
+    Ok(parts)
+}
+
+type Attributes = BTreeMap<String, FieldValue>;
+
+/// Returns the field names and the attributes of every record, `None` for deleted records
+fn parse_dbf(dbf: &[u8]) -> LTAResult<(Vec<String>, Vec<Option<Attributes>>)> {
+    let mut bytes = Bytes::new(dbf);
+    bytes.take(4)?;
+    let num_records = u32::from_le_bytes(bytes.array()?) as usize;
+    let header_len = u16::from_le_bytes(bytes.array()?) as usize;
+    let record_len = u16::from_le_bytes(bytes.array()?) as usize;
+    bytes.take(20)?;
+    // Every record starts with its deletion flag
+    if record_len == 0 && num_records > 0 {
+        return Err(invalid("Invalid record length 0 in dbf file".to_string()));
+    }
+
+    let mut fields = Vec::new();
+    while bytes.remaining() > 0 && bytes.peek() != Some(DBF_HEADER_END) && bytes.pos < header_len {
+        let descriptor = bytes.take(32)?;
+        let name = descriptor[..11].split(|b| *b == 0).next().unwrap_or(&[]);
+        let name = String::from_utf8_lossy(name).trim().to_string();
+        fields.push((name, descriptor[11], descriptor[16] as usize));
+    }
+
+    let mut bytes = Bytes::new(dbf);
+    bytes.take(header_len)?;
+    let mut records = Vec::with_capacity(num_records.min(bytes.remaining() / record_len.max(1)));
+    for _ in 0..num_records {
+        if bytes.remaining() < record_len || bytes.peek() == Some(DBF_EOF) {
+            break;
+        }
+
+        let record = bytes.take(record_len)?;
+        if record[0] == b'*' {
+            records.push(None);
+            continue;
+        }
+
+        let mut offset = 1;
+        let mut attributes = BTreeMap::new();
+        for (name, field_type, len) in &fields {
+            let raw = record
+                .get(offset..offset + len)
+                .ok_or_else(|| invalid("Truncated dbf record".to_string()))?;
+            attributes.insert(name.clone(), parse_field(*field_type, raw));
+            offset += len;
+        }
+        records.push(Some(attributes));
+    }
+
+    let names = fields.into_iter().map(|(name, _, _)| name).collect();
+    Ok((names, records))
+}
+
+fn parse_field(field_type: u8, raw: &[u8]) -> FieldValue {
+    let text = String::from_utf8_lossy(raw);
+    let text = text.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+
+    match field_type {
+        b'N' | b'F' => text.parse().map_or(FieldValue::Null, FieldValue::Number),
+        b'D' => {
+            NaiveDate::parse_from_str(text, "%Y%m%d").map_or(FieldValue::Null, FieldValue::Date)
+        }
+        b'L' => match text {
+            "T" | "t" | "Y" | "y" => FieldValue::Logical(true),
+            "F" | "f" | "N" | "n" => FieldValue::Logical(false),
+            _ => FieldValue::Null,
+        },
+        _ => FieldValue::Text(text.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_polylines_with_parts() {
+        let archive = include_bytes!("mock/fixtures/files/KerbLine.zip");
+        let shapefiles = Shapefile::from_zip(archive).unwrap();
+        assert_eq!(shapefiles.len(), 1);
+
+        let kerbs = &shapefiles[0];
+        assert_eq!(kerbs.name, "KerbLine");
+        assert_eq!(kerbs.fields, vec!["KERB_ID", "ROAD_NAME"]);
+        assert!(kerbs
+            .projection
+            .as_deref()
+            .unwrap()
+            .starts_with("PROJCS[\"SVY21\""));
+
+        let second = &kerbs.features[1];
+        assert_eq!(second.attributes["KERB_ID"], FieldValue::Number(102.0));
+        assert_eq!(second.attributes["ROAD_NAME"].as_str(), Some("JALAN BESAR"));
+        match &second.geometry {
+            Geometry::PolyLine(parts) => {
+                assert_eq!(parts.len(), 2);
+                assert_eq!(
+                    parts[1][1],
+                    Point {
+                        x: 30015.0,
+                        y: 30020.0
+                    }
+                );
+            }
+            other => panic!("Expected PolyLine, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_shapes() {
+        let archive = include_bytes!("mock/fixtures/files/KerbLine.zip");
+        let mut zip = ZipArchive::new(Cursor::new(&archive[..])).unwrap();
+        let mut shp = Vec::new();
+        zip.by_name("KerbLine_Jun2024/KerbLine.shp")
+            .unwrap()
+            .read_to_end(&mut shp)
+            .unwrap();
+
+        shp.truncate(shp.len() - 4);
+        assert!(Shapefile::parse("KerbLine", &shp, None).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_dbf_records() {
+        // Header of a dbf file with 1 record of length 0 and no field descriptors
+        let mut dbf = vec![0u8; 32];
+        dbf[4] = 1;
+        dbf[8] = 32;
+        assert!(parse_dbf(&dbf).is_err());
+    }
+}
@@ -0,0 +1,528 @@
+//! Offline bus journey planner on top of a [`BusNetwork`]
+//!
+//! Itineraries walk to a stop near the origin, ride up to `max_transfers + 1` buses, changing at
+//! stops served by both routes, and walk from a stop near the destination. They are ranked by a
+//! cost in minutes, which adds the time spent walking and riding, the expected wait for each bus
+//! and a penalty for each transfer. The expected wait is half the headway of the service in the
+//! [`Period`] of the journey, as published by `BusRequests::get_bus_services`.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::bus_network::BusNetwork;
+//! use lta::journey::{JourneyPlanner, Place, PlanOptions};
+//! use lta::models::utils::Coordinates;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let routes = Bus::get_all_bus_routes(&client).await?;
+//!     let stops = Bus::get_all_bus_stops(&client).await?;
+//!     let services = Bus::get_all_bus_services(&client).await?;
+//!     let network = BusNetwork::new(&routes, &stops);
+//!     let planner = JourneyPlanner::new(&network, &services);
+//!
+//!     let from = Place::Coordinates(Coordinates::new(1.2966, 103.8525));
+//!     let itineraries = planner.plan(&from, &Place::Stop(14229), &PlanOptions::new());
+//!     for itinerary in itineraries {
+//!         println!("{:.0}min, {} transfers", itinerary.cost, itinerary.transfers());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::bus_network::{BusNetwork, Ride, RouteId};
+use crate::coordinates::haversine_distance;
+use crate::models::bus::bus_services::{BusFreq, BusService};
+use crate::models::chrono::NaiveTime;
+use crate::models::utils::Coordinates;
+use std::collections::HashMap;
+
+/// Average speed of a bus, in km/h
+const BUS_SPEED: f64 = 20.0;
+
+/// Average walking speed, in m/min
+const WALK_SPEED: f64 = 80.0;
+
+/// Cost of each transfer, on top of the wait for the next bus, in minutes
+const TRANSFER_PENALTY: f64 = 5.0;
+
+/// Headway of services without a published frequency, in minutes
+const UNKNOWN_HEADWAY: f64 = 20.0;
+
+/// Origin or destination of a journey
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    Stop(u32),
+
+    /// Walks to or from any stop within [`PlanOptions::max_walk`]
+    Coordinates(Coordinates),
+}
+
+impl From<u32> for Place {
+    fn from(bus_stop_code: u32) -> Self {
+        Place::Stop(bus_stop_code)
+    }
+}
+
+impl From<Coordinates> for Place {
+    fn from(coordinates: Coordinates) -> Self {
+        Place::Coordinates(coordinates)
+    }
+}
+
+/// Time of day of a bus frequency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Period {
+    /// 06:30 to 08:30
+    AmPeak,
+
+    /// 08:31 to 16:59
+    #[default]
+    AmOffpeak,
+
+    /// 17:00 to 19:00
+    PmPeak,
+
+    /// After 19:00, until the first bus
+    PmOffpeak,
+}
+
+impl Period {
+    /// Returns the period of a departure at `time`
+    pub fn at(time: NaiveTime) -> Self {
+        let hm = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        if time < hm(6, 30) {
+            Period::PmOffpeak
+        } else if time <= hm(8, 30) {
+            Period::AmPeak
+        } else if time < hm(17, 0) {
+            Period::AmOffpeak
+        } else if time <= hm(19, 0) {
+            Period::PmPeak
+        } else {
+            Period::PmOffpeak
+        }
+    }
+
+    fn freq(self, service: &BusService) -> &BusFreq {
+        match self {
+            Period::AmPeak => &service.am_peak_freq,
+            Period::AmOffpeak => &service.am_offpeak_freq,
+            Period::PmPeak => &service.pm_peak_freq,
+            Period::PmOffpeak => &service.pm_offpeak_freq,
+        }
+    }
+}
+
+/// Options of [`JourneyPlanner::plan`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlanOptions {
+    /// Defaults to 2
+    pub max_transfers: usize,
+
+    /// Longest walk to or from a stop, in metres, defaults to 400m
+    pub max_walk: f64,
+
+    /// Defaults to [`Period::AmOffpeak`]
+    pub period: Period,
+
+    /// Number of itineraries returned, defaults to 5
+    pub limit: usize,
+}
+
+impl PlanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_transfers(mut self, max_transfers: usize) -> Self {
+        self.max_transfers = max_transfers;
+        self
+    }
+
+    pub fn max_walk(mut self, max_walk: f64) -> Self {
+        self.max_walk = max_walk;
+        self
+    }
+
+    pub fn period(mut self, period: Period) -> Self {
+        self.period = period;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+impl Default for PlanOptions {
+    fn default() -> Self {
+        PlanOptions {
+            max_transfers: 2,
+            max_walk: 400.0,
+            period: Period::default(),
+            limit: 5,
+        }
+    }
+}
+
+/// Part of an [`Itinerary`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Leg {
+    Walk {
+        from: Coordinates,
+        to: Coordinates,
+
+        /// In metres, as the crow flies
+        distance: f64,
+    },
+
+    Bus {
+        ride: Ride,
+        from: u32,
+        to: u32,
+
+        /// Half the headway of the service, in minutes
+        expected_wait: f64,
+    },
+}
+
+/// Journey from an origin to a destination
+#[derive(Debug, Clone, PartialEq)]
+pub struct Itinerary {
+    pub legs: Vec<Leg>,
+
+    /// Walking, riding and expected waiting time plus transfer penalties, in minutes
+    pub cost: f64,
+}
+
+impl Itinerary {
+    fn rides(&self) -> impl Iterator<Item = (&Ride, f64)> {
+        self.legs.iter().filter_map(|leg| match leg {
+            Leg::Bus {
+                ride,
+                expected_wait,
+                ..
+            } => Some((ride, *expected_wait)),
+            Leg::Walk { .. } => None,
+        })
+    }
+
+    /// Returns the services taken, in order
+    pub fn routes(&self) -> Vec<&RouteId> {
+        self.rides().map(|(ride, _)| &ride.route).collect()
+    }
+
+    pub fn transfers(&self) -> usize {
+        self.rides().count().saturating_sub(1)
+    }
+
+    /// In km
+    pub fn bus_distance(&self) -> f64 {
+        self.rides().map(|(ride, _)| ride.distance).sum()
+    }
+
+    /// In metres
+    pub fn walk_distance(&self) -> f64 {
+        self.legs
+            .iter()
+            .map(|leg| match leg {
+                Leg::Walk { distance, .. } => *distance,
+                Leg::Bus { .. } => 0.0,
+            })
+            .sum()
+    }
+
+    /// In minutes
+    pub fn expected_wait(&self) -> f64 {
+        self.rides().map(|(_, wait)| wait).sum()
+    }
+
+    fn then(&self, leg: Leg, cost: f64) -> Itinerary {
+        let mut legs = self.legs.clone();
+        legs.push(leg);
+        Itinerary {
+            legs,
+            cost: self.cost + cost,
+        }
+    }
+}
+
+fn walk(from: &Coordinates, to: &Coordinates) -> (Leg, f64) {
+    let distance = haversine_distance(from, to);
+    let leg = Leg::Walk {
+        from: from.clone(),
+        to: to.clone(),
+        distance,
+    };
+    (leg, distance / WALK_SPEED)
+}
+
+/// Plans journeys on a [`BusNetwork`], waiting for buses as often as `BusService` says they run
+#[derive(Debug, Clone)]
+pub struct JourneyPlanner<'a> {
+    network: &'a BusNetwork,
+    services: HashMap<RouteId, &'a BusService>,
+}
+
+impl<'a> JourneyPlanner<'a> {
+    /// Routes without a [`BusService`] wait for a 20min headway
+    pub fn new(network: &'a BusNetwork, services: &'a [BusService]) -> Self {
+        let services = services
+            .iter()
+            .map(|s| (RouteId::new(s.service_no.clone(), s.no_direction), s))
+            .collect();
+        JourneyPlanner { network, services }
+    }
+
+    /// Returns the expected wait for `route` in `period`, in minutes. Frequencies given as a range
+    /// use its middle
+    pub fn expected_wait(&self, route: &RouteId, period: Period) -> f64 {
+        let freq = self.services.get(route).map(|s| period.freq(s));
+        let bounds: Vec<f64> = freq
+            .into_iter()
+            .flat_map(|f| [f.min, f.max])
+            .flatten()
+            .filter(|&m| m > 0)
+            .map(f64::from)
+            .collect();
+
+        let headway = match bounds.len() {
+            0 => UNKNOWN_HEADWAY,
+            n => bounds.iter().sum::<f64>() / n as f64,
+        };
+        headway / 2.0
+    }
+
+    /// Returns the stops that can be walked to from `place`, with the walk
+    fn stops_near(&self, place: &Place, max_walk: f64) -> Vec<(u32, Option<(Leg, f64)>)> {
+        match place {
+            Place::Stop(code) => vec![(*code, None)],
+            Place::Coordinates(at) => {
+                let mut near: Vec<(u32, Option<(Leg, f64)>)> = self
+                    .network
+                    .stops()
+                    .filter_map(|stop| {
+                        let coords = Coordinates::new(stop.lat, stop.long);
+                        let (leg, cost) = walk(at, &coords);
+                        match &leg {
+                            Leg::Walk { distance, .. } if *distance <= max_walk => {
+                                Some((stop.bus_stop_code, Some((leg, cost))))
+                            }
+                            _ => None,
+                        }
+                    })
+                    .collect();
+                near.sort_by_key(|(code, _)| *code);
+                near
+            }
+        }
+    }
+
+    /// Returns the walk from stop `code` to `place`, reversing [`stops_near`](Self::stops_near)
+    fn egress(&self, code: u32, place: &Place) -> Option<(Leg, f64)> {
+        let (to, stop) = match place {
+            Place::Stop(_) => return None,
+            Place::Coordinates(to) => (to, self.network.stop(code)?),
+        };
+        Some(walk(&Coordinates::new(stop.lat, stop.long), to))
+    }
+
+    /// Returns the best itineraries from `from` to `to`, cheapest first. Only the cheapest
+    /// itinerary of each sequence of services is kept. Transfers are made at the same stop, and a
+    /// walk of up to [`PlanOptions::max_walk`] is returned when no bus is needed
+    pub fn plan(&self, from: &Place, to: &Place, opts: &PlanOptions) -> Vec<Itinerary> {
+        let destinations: HashMap<u32, Option<(Leg, f64)>> = self
+            .stops_near(to, opts.max_walk)
+            .into_iter()
+            .map(|(code, _)| (code, self.egress(code, to)))
+            .collect();
+        let mut itineraries = Vec::new();
+
+        if let (Place::Coordinates(a), Place::Coordinates(b)) = (from, to) {
+            let (leg, cost) = walk(a, b);
+            if haversine_distance(a, b) <= opts.max_walk {
+                itineraries.push(Itinerary {
+                    legs: vec![leg],
+                    cost,
+                });
+            }
+        }
+
+        // Cheapest itinerary to each stop as of the latest round, and over every round
+        let mut frontier: Vec<(u32, Itinerary)> = self
+            .stops_near(from, opts.max_walk)
+            .into_iter()
+            .map(|(code, access)| {
+                let (legs, cost) = match access {
+                    Some((leg, cost)) => (vec![leg], cost),
+                    None => (vec![], 0.0),
+                };
+                (code, Itinerary { legs, cost })
+            })
+            .collect();
+        let mut best: HashMap<u32, f64> = HashMap::new();
+
+        for round in 0..=opts.max_transfers {
+            let penalty = if round == 0 { 0.0 } else { TRANSFER_PENALTY };
+            let mut next: HashMap<u32, Itinerary> = HashMap::new();
+
+            for (code, itinerary) in &frontier {
+                let last_route = itinerary.routes().last().cloned();
+                for route in self.network.routes_at(*code) {
+                    if Some(route) == last_route {
+                        continue;
+                    }
+                    let stops = self.network.route(route).unwrap_or_default();
+                    let board = match stops.iter().position(|s| s.bus_stop_code == *code) {
+                        Some(board) => board,
+                        None => continue,
+                    };
+                    let wait = self.expected_wait(route, opts.period);
+
+                    for (alight, stop) in stops.iter().enumerate().skip(board + 1) {
+                        let distance = (stop.dist - stops[board].dist).max(0.0);
+                        let leg = Leg::Bus {
+                            ride: Ride {
+                                route: route.clone(),
+                                board,
+                                alight,
+                                distance,
+                            },
+                            from: *code,
+                            to: stop.bus_stop_code,
+                            expected_wait: wait,
+                        };
+                        let cost = penalty + wait + distance / BUS_SPEED * 60.0;
+                        let arrived = itinerary.then(leg, cost);
+
+                        if let Some(egress) = destinations.get(&stop.bus_stop_code) {
+                            itineraries.push(match egress {
+                                Some((leg, cost)) => arrived.then(leg.clone(), *cost),
+                                None => arrived.clone(),
+                            });
+                        }
+
+                        let dominated = best
+                            .get(&stop.bus_stop_code)
+                            .is_some_and(|&c| c <= arrived.cost);
+                        if !dominated {
+                            best.insert(stop.bus_stop_code, arrived.cost);
+                            next.insert(stop.bus_stop_code, arrived);
+                        }
+                    }
+                }
+            }
+
+            frontier = next.into_iter().collect();
+            frontier.sort_by_key(|(code, _)| *code);
+        }
+
+        itineraries.sort_by(|a, b| {
+            a.cost
+                .total_cmp(&b.cost)
+                .then(a.transfers().cmp(&b.transfers()))
+                .then(a.bus_distance().total_cmp(&b.bus_distance()))
+        });
+        let mut seen: Vec<Vec<RouteId>> = Vec::new();
+        itineraries.retain(|itinerary| {
+            let routes: Vec<RouteId> = itinerary.routes().into_iter().cloned().collect();
+            let new = !seen.contains(&routes);
+            seen.push(routes);
+            new
+        });
+        itineraries.truncate(opts.limit);
+        itineraries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::bus::prelude::{BusRoute, BusStop};
+    use crate::models::bus_enums::{BusCategory, Operator};
+
+    fn route(service_no: &str, stops: &[(u32, f64)]) -> Vec<BusRoute> {
+        stops
+            .iter()
+            .enumerate()
+            .map(|(i, (code, dist))| BusRoute {
+                service_no: service_no.to_string(),
+                operator: Operator::SBST,
+                direction: 1,
+                stop_seq: i as u32 + 1,
+                bus_stop_code: *code,
+                dist: *dist,
+                wd_first: None,
+                wd_last: None,
+                sat_first: None,
+                sat_last: None,
+                sun_first: None,
+                sun_last: None,
+            })
+            .collect()
+    }
+
+    fn service(service_no: &str, headway: u32) -> BusService {
+        BusService {
+            service_no: service_no.to_string(),
+            operator: Operator::SBST,
+            no_direction: 1,
+            category: BusCategory::Trunk,
+            origin_code: None,
+            dest_code: None,
+            am_peak_freq: BusFreq::new(headway, headway),
+            am_offpeak_freq: BusFreq::new(headway, headway),
+            pm_peak_freq: BusFreq::new(headway, headway),
+            pm_offpeak_freq: BusFreq::no_timing(),
+            loop_desc: None,
+        }
+    }
+
+    fn stop(bus_stop_code: u32, lat: f64, long: f64) -> BusStop {
+        BusStop {
+            bus_stop_code,
+            road_name: String::new(),
+            desc: String::new(),
+            lat,
+            long,
+        }
+    }
+
+    #[test]
+    fn plans_with_transfers() {
+        let mut routes = route("10", &[(1, 0.0), (2, 1.0), (3, 2.0)]);
+        routes.extend(route("20", &[(3, 0.0), (4, 1.0), (5, 2.0)]));
+        routes.extend(route("30", &[(1, 0.0), (5, 4.0)]));
+        let stops = vec![stop(1, 1.3000, 103.8000), stop(5, 1.3300, 103.8300)];
+        let services = vec![service("10", 6), service("20", 6), service("30", 60)];
+        let network = BusNetwork::new(&routes, &stops);
+        let planner = JourneyPlanner::new(&network, &services);
+
+        // Near stop 1, not within walking distance of anything else
+        let from = Place::Coordinates(Coordinates::new(1.3010, 103.8000));
+        let itineraries = planner.plan(&from, &Place::Stop(5), &PlanOptions::new());
+        let routes: Vec<Vec<&str>> = itineraries
+            .iter()
+            .map(|i| i.routes().iter().map(|r| r.service_no.as_str()).collect())
+            .collect();
+        assert_eq!(routes, vec![vec!["10", "20"], vec!["30"]]);
+
+        let best = &itineraries[0];
+        assert_eq!(best.transfers(), 1);
+        assert!((best.walk_distance() - 111.0).abs() < 1.0);
+        assert!((best.bus_distance() - 4.0).abs() < 1e-9);
+        assert!((best.expected_wait() - 6.0).abs() < 1e-9);
+
+        let direct = planner.plan(&from, &Place::Stop(5), &PlanOptions::new().max_transfers(0));
+        assert_eq!(direct.len(), 1);
+
+        // Off-peak frequency of 30 is not published
+        let wait = planner.expected_wait(&RouteId::new("30", 1), Period::PmOffpeak);
+        assert!((wait - UNKNOWN_HEADWAY / 2.0).abs() < 1e-9);
+        let late = NaiveTime::from_hms_opt(23, 0, 0).unwrap();
+        assert_eq!(Period::at(late), Period::PmOffpeak);
+    }
+}
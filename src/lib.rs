@@ -30,14 +30,14 @@
 //! ### Cargo.toml setup
 //! ```toml
 //! [dependencies]
-//! # extra features available: blocking
+//! # extra features available: arrow, blocking, cli, geojson
 //! lta = { version = "0.5.0-beta" }
 //! ```
 //!
 //! ### API key setup
 //! You can get your API key from [here](https://www.mytransport.sg/content/mytransport/home/dataMall/request-for-api.html)
 //!
-//! ```rust
+//! ```rust,no_run
 //! use lta::{LTAResult, LTAClient, Client, Traffic, TrafficRequests};
 //!
 //! #[tokio::main]
@@ -58,6 +58,11 @@ macro_rules! api_url {
     };
 }
 
+/// Default base URL that every API path is appended to
+pub const DEFAULT_BASE_URL: &str = api_url!("");
+
+pub use crate::r#async::bus::ArrivalQuery;
+pub use crate::r#async::paginate::PageOptions;
 pub use crate::r#async::prelude::*;
 pub use crate::r#async::LTAClient;
 pub use lta_models as models;
@@ -69,36 +74,227 @@ pub mod prelude {
 
 use crate::models::crowd::passenger_vol::VolType;
 pub use reqwest;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::fmt;
+use std::time::Duration;
 
 /// Internal Async module
 pub mod r#async;
 
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
 /// Internal Blocking module
 #[cfg(feature = "blocking")]
 pub mod blocking;
 
+pub mod bus_network;
+pub mod cache;
+pub mod cassette;
+pub mod coordinates;
+pub mod disk_cache;
+pub mod export;
+pub mod facilities;
+#[cfg(feature = "geojson")]
+pub mod geojson;
+pub mod geospatial;
+pub mod journey;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod od_matrix;
+pub mod passenger_vol;
+pub mod rate_limit;
+pub mod retry;
+pub mod spatial;
+pub mod timetable;
+pub mod traffic_lights;
+
+use crate::cache::ResponseCache;
+use crate::cassette::Cassette;
+use crate::rate_limit::RateLimiter;
+use crate::retry::{RetryPolicy, NO_RETRY};
+
+/// Maximum number of records returned by Datamall for a single request.
+/// APIs that accept `skip` have to be called with increasing `$skip` to get the remaining records
+pub const PAGE_SIZE: u32 = 500;
+
 /// Type alias for `Result<T, LTAError>`
 pub type LTAResult<T> = Result<T, LTAError>;
 
 /// LTAError type, all request using lta-rs returns `Result<T, LTAError>`
 #[derive(Debug)]
 pub enum LTAError {
-    /// Internal error within the client backend, open a PR if this happens
-    BackendError(reqwest::Error),
+    /// Request could not be sent or the response could not be read, eg connection errors or
+    /// timeouts
+    Transport {
+        url: String,
+        source: reqwest::Error,
+    },
+    /// Response body could not be decoded into the expected type, open an issue if this happens
+    Decode {
+        url: String,
+        source: serde_json::Error,
+    },
     /// API key is most likely empty
     InvalidAPIKey,
-    RateLimitReached,
+    /// HTTP TOO_MANY_REQUESTS
+    RateLimitReached(HttpErrorDetails),
     UnknownEnumVariant,
     /// Make sure that your API key is correct and valid
-    Unauthorized,
+    Unauthorized(HttpErrorDetails),
     /// HTTP NOTFOUND
-    NotFound,
+    NotFound(HttpErrorDetails),
     /// Undocumented status code, open an issue if this happens
-    UnhandledStatusCode,
+    UnhandledStatusCode(HttpErrorDetails),
     /// Custom
     Custom(String),
 }
 
+/// Details of a response with a non-success status code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpErrorDetails {
+    pub status: StatusCode,
+    pub url: String,
+    /// Start of the response body, at most [`MAX_BODY_SNIPPET_LEN`] bytes
+    pub body: String,
+    /// Value of the `Retry-After` header, if it is in seconds
+    pub retry_after: Option<Duration>,
+}
+
+/// Max length of the response body kept in [`HttpErrorDetails`]
+pub const MAX_BODY_SNIPPET_LEN: usize = 512;
+
+impl HttpErrorDetails {
+    pub fn new<U: Into<String>, B: Into<String>>(status: StatusCode, url: U, body: B) -> Self {
+        let mut body = body.into();
+
+        if body.len() > MAX_BODY_SNIPPET_LEN {
+            let mut end = MAX_BODY_SNIPPET_LEN;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+
+        HttpErrorDetails {
+            status,
+            url: url.into(),
+            body,
+            retry_after: None,
+        }
+    }
+
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+}
+
+impl LTAError {
+    pub(crate) fn transport(url: &str, source: reqwest::Error) -> Self {
+        let url = source
+            .url()
+            .map_or_else(|| url.to_string(), |u| u.to_string());
+        LTAError::Transport { url, source }
+    }
+
+    pub(crate) fn decode(url: &str, source: serde_json::Error) -> Self {
+        let url = url.to_string();
+        LTAError::Decode { url, source }
+    }
+
+    /// Maps a non-success status code to its `LTAError`
+    pub(crate) fn from_status(details: HttpErrorDetails) -> Self {
+        match details.status {
+            StatusCode::UNAUTHORIZED => LTAError::Unauthorized(details),
+            StatusCode::NOT_FOUND => LTAError::NotFound(details),
+            StatusCode::TOO_MANY_REQUESTS => LTAError::RateLimitReached(details),
+            _ => LTAError::UnhandledStatusCode(details),
+        }
+    }
+
+    /// HTTP status code of the response, if any
+    pub fn status(&self) -> Option<StatusCode> {
+        self.http_details()
+            .map(|d| d.status)
+            .or_else(|| self.reqwest_error().and_then(reqwest::Error::status))
+    }
+
+    /// URL of the request that failed, if any
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            LTAError::Transport { url, .. } | LTAError::Decode { url, .. } => Some(url.as_str()),
+            _ => self.http_details().map(|d| d.url.as_str()),
+        }
+    }
+
+    /// Value of the `Retry-After` header of the response, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.http_details().and_then(|d| d.retry_after)
+    }
+
+    /// Details of the response for HTTP errors
+    pub fn http_details(&self) -> Option<&HttpErrorDetails> {
+        match self {
+            LTAError::RateLimitReached(d)
+            | LTAError::Unauthorized(d)
+            | LTAError::NotFound(d)
+            | LTAError::UnhandledStatusCode(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    fn reqwest_error(&self) -> Option<&reqwest::Error> {
+        match self {
+            LTAError::Transport { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LTAError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LTAError::Transport { url, source } => {
+                write!(f, "Failed to send request to {}: {}", url, source)
+            }
+            LTAError::Decode { url, source } => {
+                write!(f, "Failed to decode response from {}: {}", url, source)
+            }
+            LTAError::InvalidAPIKey => write!(f, "API key is empty"),
+            LTAError::RateLimitReached(d) => write!(f, "Rate limit reached: {}", d),
+            LTAError::UnknownEnumVariant => write!(f, "Unknown enum variant"),
+            LTAError::Unauthorized(d) => write!(f, "Unauthorized, check your API key: {}", d),
+            LTAError::NotFound(d) => write!(f, "Not found: {}", d),
+            LTAError::UnhandledStatusCode(d) => write!(f, "Unhandled status code: {}", d),
+            LTAError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Display for HttpErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from {}", self.status, self.url)?;
+
+        if !self.body.is_empty() {
+            write!(f, " ({})", self.body)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for LTAError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LTAError::Transport { source, .. } => Some(source),
+            LTAError::Decode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
 /// A `Client` to make requests with
 /// The `Client` holds a connection pool internally, so it is advised that you create one and reuse it
 pub trait Client: Sized {
@@ -117,6 +313,37 @@ pub trait Client: Sized {
 
     /// Returns `Self::RB`
     fn req_builder(&self, url: &str) -> Self::RB;
+
+    /// Returns `Self::RB` for a link returned by an API, eg a passenger volume archive. Defaults
+    /// to [`req_builder`](Client::req_builder)
+    fn link_req_builder(&self, url: &str) -> Self::RB {
+        self.req_builder(url)
+    }
+
+    /// Base URL that every API path is appended to, defaults to [`DEFAULT_BASE_URL`]
+    fn base_url(&self) -> &str {
+        DEFAULT_BASE_URL
+    }
+
+    /// Policy used to retry failed requests, defaults to [`RetryPolicy::none`]
+    fn retry_policy(&self) -> &RetryPolicy {
+        &NO_RETRY
+    }
+
+    /// Limiter that delays requests before they are sent, defaults to `None`
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        None
+    }
+
+    /// Cache that decoded responses are read from and stored in, defaults to `None`
+    fn cache(&self) -> Option<&ResponseCache> {
+        None
+    }
+
+    /// Cassette that requests are recorded to or replayed from, defaults to `None`
+    fn cassette(&self) -> Option<&Cassette> {
+        None
+    }
 }
 
 /// Bus type that implements APIs. Can be either blocking or async
@@ -147,17 +374,59 @@ pub struct Geo;
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Facility;
 
-/// util to map enum to url
-pub(crate) fn vol_type_to_url(vol_type: VolType) -> LTAResult<&'static str> {
-    use crate::models::crowd::passenger_vol;
-
-    let url = match vol_type {
-        VolType::BusStops => passenger_vol::URL_BY_BUS_STOPS,
-        VolType::OdBusStop => passenger_vol::URL_BY_OD_BUS_STOPS,
-        VolType::Train => passenger_vol::URL_BY_TRAIN,
-        VolType::OdTrain => passenger_vol::URL_BY_OD_TRAIN,
+/// util to map enum to API path
+pub(crate) fn vol_type_to_path(vol_type: VolType) -> LTAResult<&'static str> {
+    let path = match vol_type {
+        VolType::BusStops => "/PV/Bus",
+        VolType::OdBusStop => "/PV/ODBus",
+        VolType::Train => "/PV/Train",
+        VolType::OdTrain => "/PV/ODTrain",
         _ => return Err(LTAError::UnknownEnumVariant),
     };
 
-    Ok(url)
+    Ok(path)
+}
+
+/// util to read the `Retry-After` header, only the delay in seconds format is supported
+pub(crate) fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Response with its body read, either from the network or a [`Cassette`]
+#[derive(Debug, Clone)]
+pub(crate) struct RawResponse {
+    pub status: StatusCode,
+    pub url: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl RawResponse {
+    /// Maps a non-success status code to its `LTAError`
+    pub fn error_for_status(self) -> LTAResult<Self> {
+        if self.status.is_success() {
+            return Ok(self);
+        }
+
+        let body = String::from_utf8_lossy(&self.body);
+        let details = HttpErrorDetails::new(self.status, self.url.as_str(), body)
+            .with_retry_after(parse_retry_after(&self.headers));
+        Err(LTAError::from_status(details))
+    }
+
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> LTAResult<T> {
+        serde_json::from_slice(&self.body).map_err(|e| LTAError::decode(&self.url, e))
+    }
+}
+
+/// util to join the client's base URL and an API path
+pub(crate) fn join_url<C: Client>(client: &C, path: &str) -> String {
+    format!("{}{}", client.base_url().trim_end_matches('/'), path)
 }
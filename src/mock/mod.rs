@@ -0,0 +1,489 @@
+//! Offline mock of the Datamall API, for tests that should not need an API key or network
+//!
+//! [`MockServer`] listens on a random local port and serves recorded fixtures for every API the
+//! crate calls. Paginated APIs honour `$skip` like Datamall does, returning up to
+//! [`PAGE_SIZE`](crate::PAGE_SIZE) records per request. Responses carry an `ETag` and
+//! conditional requests are answered with `304 Not Modified`.
+//!
+//! Links returned by the passenger volume, geospatial and facilities maintenance APIs point at
+//! files served by the same server under `/files`.
+//!
+//! Errors can be injected per API path with [`MockServer::fail_next`].
+//!
+//! ## Example
+//! ```rust
+//! use lta::mock::{Fault, MockServer};
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAError, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let server = MockServer::start().expect("Unable to start mock server");
+//!     let client = LTAClient::with_api_key("API_KEY")?.with_base_url(server.base_url());
+//!
+//!     let bus_stops = Bus::get_bus_stops(&client, None).await?;
+//!     assert!(!bus_stops.is_empty());
+//!
+//!     server.fail_next("/BusStops", Fault::Unauthorized);
+//!     let err = Bus::get_bus_stops(&client, None).await.unwrap_err();
+//!     assert!(matches!(err, LTAError::Unauthorized(_)));
+//!     Ok(())
+//! }
+//! ```
+use crate::PAGE_SIZE;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+
+/// Recorded responses of every API the crate calls, keyed by path
+const FIXTURES: &[(&str, &str)] = &[
+    ("/BusArrivalv2", include_str!("fixtures/BusArrivalv2.json")),
+    ("/BusServices", include_str!("fixtures/BusServices.json")),
+    ("/BusRoutes", include_str!("fixtures/BusRoutes.json")),
+    ("/BusStops", include_str!("fixtures/BusStops.json")),
+    ("/PV/Bus", include_str!("fixtures/PV_Bus.json")),
+    ("/PV/ODBus", include_str!("fixtures/PV_ODBus.json")),
+    ("/PV/Train", include_str!("fixtures/PV_Train.json")),
+    ("/PV/ODTrain", include_str!("fixtures/PV_ODTrain.json")),
+    (
+        "/Taxi-Availability",
+        include_str!("fixtures/Taxi-Availability.json"),
+    ),
+    ("/TaxiStands", include_str!("fixtures/TaxiStands.json")),
+    (
+        "/TrainServiceAlerts",
+        include_str!("fixtures/TrainServiceAlerts.json"),
+    ),
+    (
+        "/CarParkAvailabilityv2",
+        include_str!("fixtures/CarParkAvailabilityv2.json"),
+    ),
+    ("/ERPRates", include_str!("fixtures/ERPRates.json")),
+    (
+        "/EstTravelTimes",
+        include_str!("fixtures/EstTravelTimes.json"),
+    ),
+    (
+        "/FaultyTrafficLights",
+        include_str!("fixtures/FaultyTrafficLights.json"),
+    ),
+    ("/RoadOpenings", include_str!("fixtures/RoadOpenings.json")),
+    ("/RoadWorks", include_str!("fixtures/RoadWorks.json")),
+    (
+        "/TrafficSpeedBandsv2",
+        include_str!("fixtures/TrafficSpeedBandsv2.json"),
+    ),
+    (
+        "/Traffic-Imagesv2",
+        include_str!("fixtures/Traffic-Imagesv2.json"),
+    ),
+    (
+        "/TrafficIncidents",
+        include_str!("fixtures/TrafficIncidents.json"),
+    ),
+    ("/VMS", include_str!("fixtures/VMS.json")),
+    (
+        "/BicycleParkingv2",
+        include_str!("fixtures/BicycleParkingv2.json"),
+    ),
+    (
+        "/GeospatialWholeIsland",
+        include_str!("fixtures/GeospatialWholeIsland.json"),
+    ),
+    (
+        "/FacilitiesMaintenance",
+        include_str!("fixtures/FacilitiesMaintenance.json"),
+    ),
+];
+
+/// Files linked to by the fixtures, served under `/files`
+const FILES: &[(&str, &[u8])] = &[
+    (
+        "/files/PV_Bus.zip",
+        include_bytes!("fixtures/files/PV_Bus.zip"),
+    ),
+    (
+        "/files/PV_ODBus.zip",
+        include_bytes!("fixtures/files/PV_ODBus.zip"),
+    ),
+    (
+        "/files/PV_Train.zip",
+        include_bytes!("fixtures/files/PV_Train.zip"),
+    ),
+    (
+        "/files/PV_ODTrain.zip",
+        include_bytes!("fixtures/files/PV_ODTrain.zip"),
+    ),
+    (
+        "/files/ArrowMarking.zip",
+        include_bytes!("fixtures/files/ArrowMarking.zip"),
+    ),
+    (
+        "/files/KerbLine.zip",
+        include_bytes!("fixtures/files/KerbLine.zip"),
+    ),
+    (
+        "/files/FacilitiesMaintenance.json",
+        include_bytes!("fixtures/files/FacilitiesMaintenance.json"),
+    ),
+];
+
+/// Placeholder in fixtures that is replaced by the base URL of the server
+const BASE_URL: &str = "{base_url}";
+
+/// Error returned by the next request to an API path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// `401 Unauthorized`, as returned for an invalid `AccountKey`
+    Unauthorized,
+
+    /// `429 Too Many Requests`, with a `Retry-After` header in seconds if set
+    RateLimited { retry_after: Option<u64> },
+
+    /// `500 Internal Server Error`
+    ServerError,
+
+    /// `200 OK` with a body that is not valid JSON
+    MalformedJson,
+}
+
+/// Request received by a [`MockServer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub path: String,
+
+    /// Percent decoded query pairs, in order
+    pub query: Vec<(String, String)>,
+
+    /// Headers with lowercase names, in order
+    pub headers: Vec<(String, String)>,
+}
+
+impl MockRequest {
+    pub fn query(&self, name: &str) -> Option<&str> {
+        find(&self.query, name)
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find(&self.headers, &name.to_ascii_lowercase())
+    }
+}
+
+fn find<'a>(pairs: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+#[derive(Debug, Default)]
+struct State {
+    base_url: String,
+    fixtures: HashMap<String, Value>,
+    faults: HashMap<String, VecDeque<Fault>>,
+    requests: Vec<MockRequest>,
+    api_key: Option<String>,
+}
+
+/// Local HTTP server that mimics Datamall. The server stops when it is dropped
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Starts a server on a random local port, serving the recorded fixtures
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let fixtures = FIXTURES
+            .iter()
+            .map(|(path, json)| {
+                let fixture = serde_json::from_str(json).expect("Invalid fixture");
+                (path.to_string(), fixture)
+            })
+            .collect();
+        let state = Arc::new(Mutex::new(State {
+            base_url: format!("http://{}", addr),
+            fixtures,
+            ..State::default()
+        }));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let (thread_state, thread_shutdown) = (state.clone(), shutdown.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if thread_shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                if let Ok(stream) = stream {
+                    let state = thread_state.clone();
+                    thread::spawn(move || handle_connection(stream, &state));
+                }
+            }
+        });
+
+        Ok(MockServer {
+            addr,
+            state,
+            shutdown,
+        })
+    }
+
+    /// Returns a server that lives until the process exits, shared by every caller
+    pub fn shared() -> &'static MockServer {
+        static SERVER: OnceLock<MockServer> = OnceLock::new();
+        SERVER.get_or_init(|| MockServer::start().expect("Unable to start mock server"))
+    }
+
+    /// Base URL to pass to `LTAClient::with_base_url`
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Replaces the response of an API path, eg `/BusStops`. Paginated APIs are responses with a
+    /// `value` array. `{base_url}` in string values is replaced by the base URL of the server
+    pub fn set_fixture<S: Into<String>>(&self, path: S, fixture: Value) {
+        self.state().fixtures.insert(path.into(), fixture);
+    }
+
+    /// Makes the next request to an API path fail. Faults queue up, so calling this `n` times
+    /// fails the next `n` requests
+    pub fn fail_next<S: Into<String>>(&self, path: S, fault: Fault) {
+        self.state()
+            .faults
+            .entry(path.into())
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// Answers requests without this `AccountKey` with `401 Unauthorized`
+    pub fn require_api_key<S: Into<String>>(&self, api_key: S) {
+        self.state().api_key = Some(api_key.into());
+    }
+
+    /// Returns the requests received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wakes up the accept loop so that it sees the flag
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new<S: Into<String>>(status: u16, body: S) -> Self {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: body.into().into_bytes(),
+        }
+    }
+
+    fn file(path: &str, body: &[u8]) -> Self {
+        let content_type = if path.ends_with(".json") {
+            "application/json"
+        } else {
+            "application/zip"
+        };
+
+        Response {
+            status: 200,
+            content_type,
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn header(mut self, name: &'static str, value: String) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, state: &Mutex<State>) {
+    let req = match read_request(&stream) {
+        Ok(Some(req)) => req,
+        _ => return,
+    };
+
+    let res = respond(&req, state);
+    lock(state).requests.push(req);
+    let _ = write_response(&mut stream, &res);
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Option<MockRequest>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let target = match line.split_whitespace().nth(1) {
+        Some(target) => target.to_string(),
+        None => return Ok(None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+
+    Ok(Some(MockRequest {
+        path: percent_decode(path),
+        query,
+        headers,
+    }))
+}
+
+fn respond(req: &MockRequest, state: &Mutex<State>) -> Response {
+    let mut state = lock(state);
+
+    if let Some(api_key) = &state.api_key {
+        if req.header("AccountKey") != Some(api_key.as_str()) {
+            return Response::new(401, "Unauthorized");
+        }
+    }
+
+    let fault = state
+        .faults
+        .get_mut(&req.path)
+        .and_then(VecDeque::pop_front);
+    match fault {
+        Some(Fault::Unauthorized) => return Response::new(401, "Unauthorized"),
+        Some(Fault::RateLimited { retry_after }) => {
+            let res = Response::new(429, "Too Many Requests");
+            return match retry_after {
+                Some(secs) => res.header("Retry-After", secs.to_string()),
+                None => res,
+            };
+        }
+        Some(Fault::ServerError) => return Response::new(500, "Internal Server Error"),
+        Some(Fault::MalformedJson) => return Response::new(200, "{\"value\": [{"),
+        None => {}
+    }
+
+    if let Some((_, file)) = FILES.iter().find(|(path, _)| *path == req.path) {
+        return Response::file(&req.path, file);
+    }
+
+    let fixture = match state.fixtures.get(&req.path) {
+        Some(fixture) => fixture,
+        None => return Response::new(404, format!("No fixture for {}", req.path)),
+    };
+
+    let skip = req.query("$skip").and_then(|s| s.parse::<usize>().ok());
+    let body = match (skip, fixture.get("value").and_then(Value::as_array)) {
+        (Some(skip), Some(records)) => {
+            let page: Vec<&Value> = records.iter().skip(skip).take(PAGE_SIZE as usize).collect();
+            let mut body = fixture.clone();
+            body["value"] = serde_json::json!(page);
+            body.to_string()
+        }
+        _ => fixture.to_string(),
+    };
+    let body = body.replace(BASE_URL, &state.base_url);
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+
+    if req.header("If-None-Match") == Some(etag.as_str()) {
+        return Response::new(304, "").header("ETag", etag);
+    }
+
+    Response::new(200, body).header("ETag", etag)
+}
+
+fn write_response(stream: &mut TcpStream, res: &Response) -> io::Result<()> {
+    let reason = reqwest::StatusCode::from_u16(res.status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        res.status,
+        reason,
+        res.content_type,
+        res.body.len()
+    );
+    for (name, value) in &res.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&res.body)?;
+    stream.flush()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 3;
+                        continue;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            b'+' => out.push(b' '),
+            b => out.push(b),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
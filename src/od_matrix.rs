@@ -0,0 +1,228 @@
+//! Origin destination matrices built from the `VolType::OdBusStop` and `VolType::OdTrain`
+//! passenger volumes
+//!
+//! An [`OdMatrix`] only stores the pairs of bus stops or stations that have trips between them.
+//! Records are summed over every hour and day type that the [`OdFilter`] matches.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::models::crowd::passenger_vol::VolType;
+//! use lta::od_matrix::{OdFilter, OdMatrix};
+//! use lta::passenger_vol::DayType;
+//! use lta::{Client, Crowd, CrowdRequests, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let records = Crowd::get_passenger_vol_records(&client, VolType::OdTrain, None).await?;
+//!
+//!     let morning_peak = OdFilter::new().hours(7..=9).day_type(DayType::Weekday);
+//!     let matrix = OdMatrix::from_records(records, &morning_peak)?;
+//!     for flow in matrix.top_flows(10) {
+//!         println!("{} -> {}: {}", flow.origin, flow.destination, flow.trips);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::passenger_vol::{DayType, OdVolume, PassengerVolRecord};
+use crate::{LTAError, LTAResult};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+/// Hours and day type of the records added to an [`OdMatrix`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OdFilter {
+    /// Hours of the day that trips started in, defaults to every hour
+    pub hours: RangeInclusive<u8>,
+
+    /// Day type of the trips, defaults to `None` for both weekdays and weekends
+    pub day_type: Option<DayType>,
+}
+
+impl OdFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn hours(mut self, hours: RangeInclusive<u8>) -> Self {
+        self.hours = hours;
+        self
+    }
+
+    pub fn day_type(mut self, day_type: DayType) -> Self {
+        self.day_type = Some(day_type);
+        self
+    }
+
+    /// Returns `true` if `record` is within the hours and day type of the filter
+    pub fn matches(&self, record: &OdVolume) -> bool {
+        let day_type = self.day_type.unwrap_or(record.day_type);
+        self.hours.contains(&record.hour) && day_type == record.day_type
+    }
+}
+
+impl Default for OdFilter {
+    fn default() -> Self {
+        OdFilter {
+            hours: 0..=23,
+            day_type: None,
+        }
+    }
+}
+
+/// Trips from an origin to a destination
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Flow<'a> {
+    pub origin: &'a str,
+    pub destination: &'a str,
+    pub trips: u64,
+}
+
+/// Sparse matrix of trips between bus stops or train stations
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OdMatrix {
+    trips: HashMap<String, HashMap<String, u64>>,
+}
+
+impl OdMatrix {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds a matrix from the records of an origin destination dataset, eg as returned by
+    /// `CrowdRequests::get_passenger_vol_records`, keeping the ones that `filter` matches.
+    ///
+    /// Returns the first error of `records`, and an error for records that are not
+    /// [`PassengerVolRecord::Od`]
+    pub fn from_records<I>(records: I, filter: &OdFilter) -> LTAResult<Self>
+    where
+        I: IntoIterator<Item = LTAResult<PassengerVolRecord>>,
+    {
+        let mut matrix = OdMatrix::new();
+        for record in records {
+            match record? {
+                PassengerVolRecord::Od(od) if filter.matches(&od) => matrix.add(&od),
+                PassengerVolRecord::Od(_) => {}
+                PassengerVolRecord::Node(_) => {
+                    return Err(LTAError::Custom(
+                        "Expected origin destination passenger volumes".to_string(),
+                    ))
+                }
+            }
+        }
+
+        Ok(matrix)
+    }
+
+    /// Adds the trips of `record`, regardless of its hour and day type
+    pub fn add(&mut self, record: &OdVolume) {
+        *self
+            .trips
+            .entry(record.origin_pt_code.clone())
+            .or_default()
+            .entry(record.destination_pt_code.clone())
+            .or_default() += u64::from(record.total_trips);
+    }
+
+    /// Returns the trips from `origin` to `destination`, `0` if there are none
+    pub fn trips(&self, origin: &str, destination: &str) -> u64 {
+        self.trips
+            .get(origin)
+            .and_then(|dests| dests.get(destination))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of origin destination pairs with trips
+    pub fn len(&self) -> usize {
+        self.trips.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trips.is_empty()
+    }
+
+    /// Returns every pair with trips, in no particular order
+    pub fn flows(&self) -> impl Iterator<Item = Flow<'_>> {
+        self.trips.iter().flat_map(|(origin, dests)| {
+            dests.iter().map(move |(destination, trips)| Flow {
+                origin,
+                destination,
+                trips: *trips,
+            })
+        })
+    }
+
+    /// Returns the `n` pairs with the most trips, most first. Ties are ordered by origin and
+    /// destination
+    pub fn top_flows(&self, n: usize) -> Vec<Flow<'_>> {
+        let mut flows: Vec<Flow<'_>> = self.flows().collect();
+        flows.sort_unstable_by_key(|f| (Reverse(f.trips), f.origin, f.destination));
+        flows.truncate(n);
+        flows
+    }
+
+    /// Returns the trips starting from each origin
+    pub fn origin_totals(&self) -> HashMap<&str, u64> {
+        self.trips
+            .iter()
+            .map(|(origin, dests)| (origin.as_str(), dests.values().sum()))
+            .collect()
+    }
+
+    /// Returns the trips of every pair
+    pub fn total_trips(&self) -> u64 {
+        self.trips.values().flat_map(HashMap::values).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::passenger_vol::{PtType, YearMonth};
+
+    fn od(day_type: DayType, hour: u8, origin: &str, destination: &str, trips: u32) -> OdVolume {
+        OdVolume {
+            year_month: YearMonth {
+                year: 2020,
+                month: 12,
+            },
+            day_type,
+            hour,
+            pt_type: PtType::Bus,
+            origin_pt_code: origin.to_string(),
+            destination_pt_code: destination.to_string(),
+            total_trips: trips,
+        }
+    }
+
+    #[test]
+    fn filters_and_sums_trips() {
+        let records = vec![
+            od(DayType::Weekday, 7, "A", "B", 10),
+            od(DayType::Weekday, 8, "A", "B", 5),
+            od(DayType::Weekday, 8, "A", "C", 15),
+            od(DayType::Weekday, 8, "B", "C", 15),
+            od(DayType::Weekday, 12, "C", "A", 100),
+            od(DayType::WeekendsHoliday, 8, "A", "B", 50),
+        ];
+        let records = records.into_iter().map(|r| Ok(PassengerVolRecord::Od(r)));
+        let filter = OdFilter::new().hours(7..=9).day_type(DayType::Weekday);
+        let matrix = OdMatrix::from_records(records, &filter).unwrap();
+
+        assert_eq!(matrix.len(), 3);
+        assert_eq!(matrix.trips("A", "B"), 15);
+        assert_eq!(matrix.trips("C", "A"), 0);
+        assert_eq!(matrix.total_trips(), 45);
+        assert_eq!(matrix.origin_totals()["A"], 30);
+
+        let top: Vec<_> = matrix
+            .top_flows(2)
+            .into_iter()
+            .map(|f| (f.origin, f.destination, f.trips))
+            .collect();
+        assert_eq!(top, vec![("A", "B", 15), ("A", "C", 15)]);
+    }
+}
@@ -0,0 +1,365 @@
+//! Typed records of the passenger volume datasets
+//!
+//! `CrowdRequests::get_passenger_vol_by` only returns links to ZIP archives, each holding a
+//! single CSV file. [`PassengerVolRecords`] reads that CSV straight out of a downloaded archive,
+//! decoding one record at a time, so the uncompressed file is never held in memory.
+//!
+//! Tap in and tap out volumes of `VolType::BusStops` and `VolType::Train` are decoded into
+//! [`NodeVolume`], trips of `VolType::OdBusStop` and `VolType::OdTrain` into [`OdVolume`].
+use crate::{LTAError, LTAResult};
+use flate2::read::DeflateDecoder;
+use flate2::Crc;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::io::{self, Cursor, Read};
+use std::str::FromStr;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Column that only the origin destination datasets have
+const ORIGIN_PT_CODE: &str = "ORIGIN_PT_CODE";
+
+/// Month that a record was collected in, written as `2020-12` in the datasets
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct YearMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl fmt::Display for YearMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl FromStr for YearMonth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid year month {:?}", s);
+        let (year, month) = s.trim().split_once('-').ok_or_else(invalid)?;
+        let year = year.parse().map_err(|_| invalid())?;
+        let month = month.parse().map_err(|_| invalid())?;
+
+        match month {
+            1..=12 => Ok(YearMonth { year, month }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for YearMonth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Serialize for YearMonth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum DayType {
+    #[serde(rename = "WEEKDAY")]
+    Weekday,
+
+    /// Weekends and public holidays
+    #[serde(rename = "WEEKENDS/HOLIDAY")]
+    WeekendsHoliday,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum PtType {
+    #[serde(rename = "BUS")]
+    Bus,
+
+    #[serde(rename = "TRAIN")]
+    Train,
+}
+
+/// Number of taps at a bus stop or train station within an hour
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct NodeVolume {
+    #[serde(rename = "YEAR_MONTH")]
+    pub year_month: YearMonth,
+
+    #[serde(rename = "DAY_TYPE")]
+    pub day_type: DayType,
+
+    /// Hour of the day, from `0` to `23`
+    #[serde(rename = "TIME_PER_HOUR")]
+    pub hour: u8,
+
+    #[serde(rename = "PT_TYPE")]
+    pub pt_type: PtType,
+
+    /// Bus stop code, or station codes joined with `-` for interchanges, eg `NS1-EW24-JE5`
+    #[serde(rename = "PT_CODE")]
+    pub pt_code: String,
+
+    #[serde(rename = "TOTAL_TAP_IN_VOLUME")]
+    pub tap_in: u32,
+
+    #[serde(rename = "TOTAL_TAP_OUT_VOLUME")]
+    pub tap_out: u32,
+}
+
+/// Number of trips between two bus stops or train stations that started within an hour
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct OdVolume {
+    #[serde(rename = "YEAR_MONTH")]
+    pub year_month: YearMonth,
+
+    #[serde(rename = "DAY_TYPE")]
+    pub day_type: DayType,
+
+    /// Hour of the day that trips started in, from `0` to `23`
+    #[serde(rename = "TIME_PER_HOUR")]
+    pub hour: u8,
+
+    #[serde(rename = "PT_TYPE")]
+    pub pt_type: PtType,
+
+    #[serde(rename = "ORIGIN_PT_CODE")]
+    pub origin_pt_code: String,
+
+    #[serde(rename = "DESTINATION_PT_CODE")]
+    pub destination_pt_code: String,
+
+    #[serde(rename = "TOTAL_TRIPS")]
+    pub total_trips: u32,
+}
+
+/// Record of any passenger volume dataset
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PassengerVolRecord {
+    /// Record of the bus stop and train station datasets
+    Node(NodeVolume),
+
+    /// Record of the origin destination datasets
+    Od(OdVolume),
+}
+
+/// Iterator over the records of a passenger volume archive, decoding them as they are read
+pub struct PassengerVolRecords {
+    rows: csv::StringRecordsIntoIter<Box<dyn Read + Send>>,
+    headers: csv::StringRecord,
+    od: bool,
+}
+
+impl PassengerVolRecords {
+    /// Reads the CSV file in a ZIP archive as downloaded from a passenger volume link
+    pub fn from_zip(archive: Vec<u8>) -> LTAResult<Self> {
+        let invalid = |e: zip::result::ZipError| {
+            LTAError::Custom(format!("Invalid passenger volume archive: {}", e))
+        };
+        let mut zip = ZipArchive::new(Cursor::new(archive)).map_err(invalid)?;
+
+        let mut entry = None;
+        for i in 0..zip.len() {
+            let file = zip.by_index(i).map_err(invalid)?;
+            if file.name().to_ascii_lowercase().ends_with(".csv") {
+                entry = Some((
+                    file.data_start(),
+                    file.compressed_size(),
+                    file.compression(),
+                    file.size(),
+                    file.crc32(),
+                ));
+                break;
+            }
+        }
+
+        let (start, size, compression, uncompressed_size, crc32) = entry.ok_or_else(|| {
+            LTAError::Custom("No CSV file in passenger volume archive".to_string())
+        })?;
+
+        // The archive is already in memory, so the entry is read from its offset instead of
+        // through `ZipFile`, which borrows the archive. Its CRC-32 and size are checked as
+        // `ZipFile` would
+        let mut cursor = zip.into_inner();
+        cursor.set_position(start);
+        let checked = |reader| CheckedEntry {
+            inner: reader,
+            crc: Crc::new(),
+            read: 0,
+            size: uncompressed_size,
+            crc32,
+        };
+        let reader: Box<dyn Read + Send> = match compression {
+            CompressionMethod::Stored => Box::new(checked(Box::new(cursor.take(size)))),
+            CompressionMethod::Deflated => {
+                Box::new(checked(Box::new(DeflateDecoder::new(cursor.take(size)))))
+            }
+            other => {
+                return Err(LTAError::Custom(format!(
+                    "Unsupported compression in passenger volume archive: {}",
+                    other
+                )))
+            }
+        };
+
+        Self::from_csv(reader)
+    }
+
+    /// Reads a passenger volume CSV file, with its header row
+    pub fn from_csv<R: Read + Send + 'static>(csv: R) -> LTAResult<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(Box::new(csv) as Box<dyn Read + Send>);
+        let headers = reader.headers().map_err(invalid_csv)?.clone();
+        let od = headers.iter().any(|h| h == ORIGIN_PT_CODE);
+
+        Ok(PassengerVolRecords {
+            rows: reader.into_records(),
+            headers,
+            od,
+        })
+    }
+
+    /// Returns `true` if the records are [`PassengerVolRecord::Od`]
+    pub fn is_origin_destination(&self) -> bool {
+        self.od
+    }
+}
+
+impl Iterator for PassengerVolRecords {
+    type Item = LTAResult<PassengerVolRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.rows.next()? {
+            Ok(row) => row,
+            Err(e) => return Some(Err(invalid_csv(e))),
+        };
+
+        let record = if self.od {
+            row.deserialize(Some(&self.headers))
+                .map(PassengerVolRecord::Od)
+        } else {
+            row.deserialize(Some(&self.headers))
+                .map(PassengerVolRecord::Node)
+        };
+
+        Some(record.map_err(invalid_csv))
+    }
+}
+
+impl fmt::Debug for PassengerVolRecords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassengerVolRecords")
+            .field("headers", &self.headers)
+            .field("od", &self.od)
+            .finish()
+    }
+}
+
+/// Entry of a ZIP archive that fails at its end if its CRC-32 or size is not the one in the
+/// archive, eg when the download was truncated
+struct CheckedEntry {
+    inner: Box<dyn Read + Send>,
+    crc: Crc,
+    read: u64,
+    size: u64,
+    crc32: u32,
+}
+
+impl Read for CheckedEntry {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        self.read += n as u64;
+
+        let corrupted = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "CRC-32 or size mismatch in archive",
+            )
+        };
+        if self.read > self.size {
+            return Err(corrupted());
+        }
+        if n == 0 && !buf.is_empty() && (self.read != self.size || self.crc.sum() != self.crc32) {
+            return Err(corrupted());
+        }
+        Ok(n)
+    }
+}
+
+fn invalid_csv(e: csv::Error) -> LTAError {
+    LTAError::Custom(format!("Invalid passenger volume CSV: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_node_and_od_records() {
+        let node = "YEAR_MONTH,DAY_TYPE,TIME_PER_HOUR,PT_TYPE,PT_CODE,TOTAL_TAP_IN_VOLUME,\
+                    TOTAL_TAP_OUT_VOLUME\r\n2020-12,WEEKENDS/HOLIDAY,17,BUS,01012,18,6\r\n";
+        let records: Vec<_> = PassengerVolRecords::from_csv(Cursor::new(node))
+            .unwrap()
+            .collect::<LTAResult<_>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![PassengerVolRecord::Node(NodeVolume {
+                year_month: YearMonth {
+                    year: 2020,
+                    month: 12
+                },
+                day_type: DayType::WeekendsHoliday,
+                hour: 17,
+                pt_type: PtType::Bus,
+                pt_code: "01012".to_string(),
+                tap_in: 18,
+                tap_out: 6,
+            })]
+        );
+
+        let od = "YEAR_MONTH,DAY_TYPE,TIME_PER_HOUR,PT_TYPE,ORIGIN_PT_CODE,DESTINATION_PT_CODE,\
+                  TOTAL_TRIPS\n2020-12,WEEKDAY,8,TRAIN,NS1-EW24-JE5,CC1,1093\n2020-13,WEEKDAY,8,\
+                  TRAIN,NS1,CC1,1\n";
+        let mut records = PassengerVolRecords::from_csv(Cursor::new(od)).unwrap();
+        assert!(records.is_origin_destination());
+        match records.next() {
+            Some(Ok(PassengerVolRecord::Od(od))) => {
+                assert_eq!(od.origin_pt_code, "NS1-EW24-JE5");
+                assert_eq!(od.total_trips, 1093);
+            }
+            other => panic!("Expected OD record, got {:?}", other),
+        }
+        assert!(matches!(records.next(), Some(Err(LTAError::Custom(_)))));
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn rejects_corrupted_archives() {
+        use std::io::Write;
+        use zip::write::{FileOptions, ZipWriter};
+
+        let csv = "YEAR_MONTH,DAY_TYPE,TIME_PER_HOUR,PT_TYPE,PT_CODE,TOTAL_TAP_IN_VOLUME,\
+                   TOTAL_TAP_OUT_VOLUME\n"
+            .to_string()
+            + &"2020-12,WEEKDAY,17,BUS,01012,18,6\n".repeat(100);
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        writer
+            .start_file("transport_node_bus.csv", options)
+            .unwrap();
+        writer.write_all(csv.as_bytes()).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        let records = PassengerVolRecords::from_zip(archive.clone()).unwrap();
+        assert_eq!(records.count(), 100);
+
+        // Same length, different content
+        let offset = archive.windows(4).position(|w| w == b"0101").unwrap();
+        let mut corrupted = archive;
+        corrupted[offset..offset + 4].copy_from_slice(b"0202");
+        let results: Vec<_> = PassengerVolRecords::from_zip(corrupted).unwrap().collect();
+        assert!(matches!(results.last(), Some(Err(LTAError::Custom(_)))));
+    }
+}
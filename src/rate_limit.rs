@@ -0,0 +1,254 @@
+//! Client side rate limiting to stay within Datamall quotas
+//!
+//! ## Example
+//! ```rust
+//! use lta::rate_limit::RateLimiter;
+//! use lta::{Client, LTAClient, LTAResult};
+//!
+//! fn rate_limited_client() -> LTAResult<LTAClient> {
+//!     let limiter = RateLimiter::new(5).daily_budget(10_000);
+//!     let client = LTAClient::with_api_key("API_KEY")?.with_rate_limiter(limiter);
+//!
+//!     // Clones of the client share the same limiter
+//!     let _clone = client.clone();
+//!     Ok(client)
+//! }
+//! ```
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Length of the window the daily budget applies to
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Token bucket that delays requests to stay under a requests per second rate, and optionally a
+/// daily budget.
+///
+/// Cloning a `RateLimiter` returns a handle to the same bucket, so every clone of a client
+/// shares the same limits.
+///
+/// Once the daily budget is used up, requests are delayed until the 24 hour window, which starts
+/// at the first request, is over. Each delayed request counts against the window it is scheduled
+/// in, so requests keep being pushed to later windows until one has room for them.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    daily_budget: Option<u32>,
+    state: Arc<Mutex<State>>,
+}
+
+/// Snapshot of the counters of a [`RateLimiter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimiterStats {
+    /// Number of requests sent through the limiter
+    pub total_requests: u64,
+
+    /// Number of requests sent in the current daily window
+    pub requests_today: u32,
+
+    /// Number of requests left in the current daily window, if there is a daily budget
+    pub remaining_today: Option<u32>,
+
+    /// Number of requests that had to wait
+    pub delayed_requests: u64,
+
+    /// Sum of the time requests had to wait
+    pub total_delay: Duration,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    last_refill: Instant,
+    day_start: Instant,
+    requests_today: u32,
+    queued: u64,
+    total_requests: u64,
+    delayed_requests: u64,
+    total_delay: Duration,
+}
+
+impl RateLimiter {
+    /// Allows `per_second` requests every second, with bursts of up to `per_second` requests
+    pub fn new(per_second: u32) -> Self {
+        let per_second = f64::from(per_second.max(1));
+        let now = Instant::now();
+
+        RateLimiter {
+            per_second,
+            burst: per_second,
+            daily_budget: None,
+            state: Arc::new(Mutex::new(State {
+                tokens: per_second,
+                last_refill: now,
+                day_start: now,
+                requests_today: 0,
+                queued: 0,
+                total_requests: 0,
+                delayed_requests: 0,
+                total_delay: Duration::from_secs(0),
+            })),
+        }
+    }
+
+    /// Max number of requests that can be sent at once before requests are delayed
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = f64::from(burst.max(1));
+        self.state().tokens = self.burst;
+        self
+    }
+
+    /// Max number of requests sent in a 24 hour window
+    pub fn daily_budget(mut self, daily_budget: u32) -> Self {
+        self.daily_budget = Some(daily_budget.max(1));
+        self
+    }
+
+    /// Returns the counters of the limiter
+    pub fn stats(&self) -> RateLimiterStats {
+        let mut state = self.state();
+        self.roll_window(&mut state, Instant::now());
+
+        RateLimiterStats {
+            total_requests: state.total_requests,
+            requests_today: state.requests_today,
+            remaining_today: self
+                .daily_budget
+                .map(|budget| budget.saturating_sub(state.requests_today)),
+            delayed_requests: state.delayed_requests,
+            total_delay: state.total_delay,
+        }
+    }
+
+    /// Reserves a slot for one request and returns how long the caller has to wait before
+    /// sending it
+    pub fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut state = self.state();
+
+        let elapsed = now.saturating_duration_since(state.last_refill);
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * self.per_second).min(self.burst);
+        state.last_refill = now;
+
+        self.roll_window(&mut state, now);
+
+        let mut delay = Duration::from_secs(0);
+
+        match self.daily_budget {
+            Some(budget) if state.requests_today >= budget => {
+                let windows_ahead = 1 + state.queued / u64::from(budget);
+                let scheduled = state.day_start + DAY * windows_ahead as u32;
+                delay = scheduled.saturating_duration_since(now);
+                state.queued += 1;
+            }
+            _ => state.requests_today += 1,
+        }
+
+        state.tokens -= 1.0;
+        if state.tokens < 0.0 {
+            delay = delay.max(Duration::from_secs_f64(-state.tokens / self.per_second));
+        }
+
+        state.total_requests += 1;
+        if delay > Duration::from_secs(0) {
+            state.delayed_requests += 1;
+            state.total_delay += delay;
+        }
+
+        delay
+    }
+
+    /// Moves the daily window forward once it is over. Requests queued for the windows that
+    /// have started since are counted against them, in the order they were reserved.
+    fn roll_window(&self, state: &mut State, now: Instant) {
+        let elapsed = now.saturating_duration_since(state.day_start);
+        if elapsed < DAY {
+            return;
+        }
+
+        if state.queued == 0 {
+            state.day_start = now;
+            state.requests_today = 0;
+            return;
+        }
+
+        let budget = u64::from(self.daily_budget.unwrap_or(u32::MAX));
+        let windows = elapsed.as_secs() / DAY.as_secs();
+        let past = state.queued.min((windows - 1) * budget);
+        let current = (state.queued - past).min(budget);
+
+        state.day_start += DAY * windows as u32;
+        state.requests_today = current as u32;
+        state.queued -= past + current;
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rate_limit::{RateLimiter, DAY};
+    use std::time::Duration;
+
+    #[test]
+    fn delays_after_burst() {
+        let limiter = RateLimiter::new(10).burst(2);
+
+        assert_eq!(limiter.reserve(), Duration::from_secs(0));
+        assert_eq!(limiter.reserve(), Duration::from_secs(0));
+
+        let delay = limiter.reserve();
+        assert!(delay > Duration::from_millis(90) && delay <= Duration::from_millis(100));
+
+        let stats = limiter.stats();
+        assert_eq!(stats.total_requests, 3);
+        assert_eq!(stats.delayed_requests, 1);
+        assert_eq!(stats.remaining_today, None);
+    }
+
+    #[test]
+    fn delays_until_next_day_when_budget_is_used() {
+        let limiter = RateLimiter::new(100).daily_budget(2);
+        limiter.reserve();
+        limiter.reserve();
+        assert_eq!(limiter.stats().remaining_today, Some(0));
+
+        let delay = limiter.reserve();
+        assert!(delay > DAY - Duration::from_secs(1) && delay <= DAY);
+        assert_eq!(limiter.stats().requests_today, 2);
+        assert_eq!(limiter.stats().remaining_today, Some(0));
+    }
+
+    #[test]
+    fn delays_every_request_over_budget() {
+        let limiter = RateLimiter::new(100).daily_budget(2);
+        assert_eq!(limiter.reserve(), Duration::from_secs(0));
+        assert_eq!(limiter.reserve(), Duration::from_secs(0));
+
+        // The next window has room for 2 requests, the one after for the rest
+        let delays = (0..4).map(|_| limiter.reserve()).collect::<Vec<_>>();
+        for delay in &delays[..2] {
+            assert!(*delay > DAY - Duration::from_secs(1) && *delay <= DAY);
+        }
+        for delay in &delays[2..] {
+            assert!(*delay > DAY * 2 - Duration::from_secs(1) && *delay <= DAY * 2);
+        }
+
+        let stats = limiter.stats();
+        assert_eq!(stats.total_requests, 6);
+        assert_eq!(stats.delayed_requests, 4);
+    }
+
+    #[test]
+    fn clones_share_state() {
+        let limiter = RateLimiter::new(1);
+        let clone = limiter.clone();
+        limiter.reserve();
+        clone.reserve();
+
+        assert_eq!(limiter.stats().total_requests, 2);
+        assert_eq!(clone.stats().delayed_requests, 1);
+    }
+}
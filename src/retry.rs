@@ -0,0 +1,203 @@
+//! Retrying of failed requests with exponential backoff and jitter
+//!
+//! ## Example
+//! ```rust
+//! use lta::retry::RetryPolicy;
+//! use lta::{Client, LTAClient, LTAResult};
+//! use std::time::Duration;
+//!
+//! fn client_with_retries() -> LTAResult<LTAClient> {
+//!     let policy = RetryPolicy::new(5)
+//!         .base_delay(Duration::from_millis(200))
+//!         .max_delay(Duration::from_secs(10));
+//!
+//!     Ok(LTAClient::with_api_key("API_KEY")?.with_retry_policy(policy))
+//! }
+//! ```
+use crate::LTAError;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Policy that does not retry, used by clients that do not have a policy set
+pub(crate) static NO_RETRY: RetryPolicy = RetryPolicy {
+    max_attempts: 1,
+    base_delay: Duration::from_millis(500),
+    max_delay: Duration::from_secs(30),
+    jitter: 0.5,
+    respect_retry_after: true,
+    retry_on: is_transient,
+};
+
+/// Decides if and when a failed request is sent again.
+///
+/// The delay before attempt `n + 1` is `base_delay * 2^(n - 1)`, capped at `max_delay`, with up to
+/// `jitter` of it randomly taken off. If the response carries a `Retry-After` header and
+/// `respect_retry_after` is set, the delay is at least that long.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Max number of times a request is sent, including the first attempt
+    pub max_attempts: u32,
+
+    /// Delay before the first retry
+    pub base_delay: Duration,
+
+    /// Upper bound of the exponential backoff
+    pub max_delay: Duration,
+
+    /// Fraction of the delay, between `0.0` and `1.0`, that is randomised
+    pub jitter: f64,
+
+    /// Wait for at least the duration in the `Retry-After` header, if present
+    pub respect_retry_after: bool,
+
+    /// Returns `true` if the request that failed with the error should be retried
+    pub retry_on: fn(&LTAError) -> bool,
+}
+
+impl RetryPolicy {
+    /// Retries transient errors (see [`is_transient`]) until `max_attempts` requests are sent
+    pub fn new(max_attempts: u32) -> Self {
+        RetryPolicy {
+            max_attempts: max_attempts.max(1),
+            ..NO_RETRY.clone()
+        }
+    }
+
+    /// Policy that never retries
+    pub fn none() -> Self {
+        NO_RETRY.clone()
+    }
+
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn respect_retry_after(mut self, respect_retry_after: bool) -> Self {
+        self.respect_retry_after = respect_retry_after;
+        self
+    }
+
+    pub fn retry_on(mut self, retry_on: fn(&LTAError) -> bool) -> Self {
+        self.retry_on = retry_on;
+        self
+    }
+
+    /// Returns the delay before sending the request again, or `None` if the request failed for
+    /// the last time. `attempt` starts at 1
+    pub fn next_delay(&self, attempt: u32, err: &LTAError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !(self.retry_on)(err) {
+            return None;
+        }
+
+        let exp = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self.base_delay.checked_mul(exp).unwrap_or(self.max_delay);
+        let backoff = backoff.min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let backoff = backoff.mul_f64(1.0 - jitter * fastrand::f64());
+
+        let delay = match err.retry_after() {
+            Some(retry_after) if self.respect_retry_after => backoff.max(retry_after),
+            _ => backoff,
+        };
+
+        Some(delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3)
+    }
+}
+
+/// Default `retry_on` of [`RetryPolicy`]. Retries transport errors, `429 Too Many Requests` and
+/// `5xx` responses
+pub fn is_transient(err: &LTAError) -> bool {
+    match err {
+        LTAError::Transport { .. } | LTAError::RateLimitReached(_) => true,
+        LTAError::UnhandledStatusCode(d) => {
+            d.status.is_server_error() || d.status == StatusCode::REQUEST_TIMEOUT
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::retry::RetryPolicy;
+    use crate::{HttpErrorDetails, LTAError};
+    use reqwest::StatusCode;
+    use std::time::Duration;
+
+    fn http_error(status: StatusCode) -> LTAError {
+        LTAError::from_status(HttpErrorDetails::new(status, "http://localhost", ""))
+    }
+
+    #[test]
+    fn backoff_without_jitter() {
+        let policy = RetryPolicy::new(4)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(300))
+            .jitter(0.0);
+        let err = http_error(StatusCode::SERVICE_UNAVAILABLE);
+
+        assert_eq!(policy.next_delay(1, &err), Some(Duration::from_millis(100)));
+        assert_eq!(policy.next_delay(2, &err), Some(Duration::from_millis(200)));
+        assert_eq!(policy.next_delay(3, &err), Some(Duration::from_millis(300)));
+        assert_eq!(policy.next_delay(4, &err), None);
+    }
+
+    #[test]
+    fn backoff_with_jitter() {
+        let policy = RetryPolicy::new(2).base_delay(Duration::from_millis(100));
+        let err = http_error(StatusCode::TOO_MANY_REQUESTS);
+        let delay = policy.next_delay(1, &err).unwrap();
+
+        assert!(delay > Duration::from_millis(50) && delay <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn respects_retry_after() {
+        let policy = RetryPolicy::new(2).jitter(0.0);
+        let details = HttpErrorDetails::new(StatusCode::TOO_MANY_REQUESTS, "http://localhost", "")
+            .with_retry_after(Some(Duration::from_secs(7)));
+        let err = LTAError::from_status(details);
+
+        assert_eq!(policy.next_delay(1, &err), Some(Duration::from_secs(7)));
+
+        let policy = policy.respect_retry_after(false);
+        assert_eq!(policy.next_delay(1, &err), Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        let policy = RetryPolicy::new(3);
+
+        assert!(policy
+            .next_delay(1, &http_error(StatusCode::NOT_FOUND))
+            .is_none());
+        assert!(policy
+            .next_delay(1, &http_error(StatusCode::UNAUTHORIZED))
+            .is_none());
+        assert!(policy.next_delay(1, &LTAError::InvalidAPIKey).is_none());
+        assert!(RetryPolicy::none()
+            .next_delay(1, &http_error(StatusCode::BAD_GATEWAY))
+            .is_none());
+
+        let policy = policy.retry_on(|err| matches!(err, LTAError::NotFound(_)));
+        assert!(policy
+            .next_delay(1, &http_error(StatusCode::NOT_FOUND))
+            .is_some());
+    }
+}
@@ -0,0 +1,308 @@
+//! Nearest neighbour and radius queries over anything with a location
+//!
+//! [`SpatialIndex`] buckets items into a grid of square cells of latitude and longitude, so that a
+//! query only measures the items of the cells around it. Distances are great circle distances in
+//! metres, see [`haversine_distance`].
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::models::utils::Coordinates;
+//! use lta::spatial::SpatialIndex;
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult, Taxi, TaxiRequests};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let stops = SpatialIndex::new(Bus::get_all_bus_stops(&client).await?);
+//!     let stands = SpatialIndex::new(Taxi::get_all_taxi_stands(&client).await?);
+//!
+//!     let here = Coordinates::new(1.2966, 103.8525);
+//!     for n in stops.within(&here, 300.0) {
+//!         println!("{} {:.0}m", n.item.desc, n.distance);
+//!     }
+//!     if let Some(n) = stands.nearest(&here, 1).first() {
+//!         println!("{} {:.0}m", n.item.name, n.distance);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::coordinates::{haversine_distance, EARTH_RADIUS};
+use crate::models::bus::prelude::BusStop;
+use crate::models::taxi::prelude::TaxiStand;
+use crate::models::traffic::prelude::{BikeParking, CarPark};
+use crate::models::utils::Coordinates;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+
+/// Side of a cell of [`SpatialIndex::new`], in metres
+const DEFAULT_CELL_SIZE: f64 = 250.0;
+
+/// Anything that can be placed in a [`SpatialIndex`]
+pub trait Located {
+    /// `None` for items without a known location, which are not indexed
+    fn coordinates(&self) -> Option<Coordinates>;
+}
+
+impl Located for Coordinates {
+    fn coordinates(&self) -> Option<Coordinates> {
+        Some(self.clone())
+    }
+}
+
+impl Located for BusStop {
+    fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates::new(self.lat, self.long))
+    }
+}
+
+impl Located for TaxiStand {
+    fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates::new(self.lat, self.long))
+    }
+}
+
+impl Located for BikeParking {
+    fn coordinates(&self) -> Option<Coordinates> {
+        Some(Coordinates::new(self.lat, self.long))
+    }
+}
+
+impl Located for CarPark {
+    fn coordinates(&self) -> Option<Coordinates> {
+        self.coords.clone()
+    }
+}
+
+/// Result of a query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbour<'a, T> {
+    pub item: &'a T,
+
+    /// From the queried point, in metres
+    pub distance: f64,
+}
+
+/// Grid of items by location
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<T> {
+    items: Vec<(Coordinates, T)>,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+
+    /// Lowest and highest occupied cells, `None` if no item is indexed
+    extent: Option<((i64, i64), (i64, i64))>,
+
+    /// In degrees
+    cell_size: f64,
+}
+
+fn metres_per_degree() -> f64 {
+    EARTH_RADIUS.to_radians()
+}
+
+/// Offsets of the cells `ring` cells away from a cell, the perimeter of a square of
+/// `2 * ring + 1` cells a side
+fn ring_offsets(ring: i64) -> impl Iterator<Item = (i64, i64)> {
+    let centre = std::iter::once((0, 0)).filter(move |_| ring == 0);
+    let perimeter =
+        (-ring..ring).flat_map(move |d| [(-ring, d), (d, ring), (ring, -d), (-d, -ring)]);
+    centre.chain(perimeter)
+}
+
+impl<T: Located> SpatialIndex<T> {
+    /// Indexes `items` in cells of 250m, which suits queries of a few hundred metres
+    pub fn new(items: Vec<T>) -> Self {
+        Self::with_cell_size(items, DEFAULT_CELL_SIZE)
+    }
+
+    /// Indexes `items` in cells of `cell_size` metres. Items without coordinates are dropped
+    pub fn with_cell_size(items: Vec<T>, cell_size: f64) -> Self {
+        let mut index = SpatialIndex {
+            items: Vec::with_capacity(items.len()),
+            cells: HashMap::new(),
+            extent: None,
+            cell_size: cell_size / metres_per_degree(),
+        };
+
+        for item in items {
+            if let Some(at) = item.coordinates() {
+                let cell = index.cell(&at);
+                index.extent = Some(match index.extent {
+                    Some((low, high)) => (
+                        (low.0.min(cell.0), low.1.min(cell.1)),
+                        (high.0.max(cell.0), high.1.max(cell.1)),
+                    ),
+                    None => (cell, cell),
+                });
+                index.cells.entry(cell).or_default().push(index.items.len());
+                index.items.push((at, item));
+            }
+        }
+        index
+    }
+}
+
+impl<T> SpatialIndex<T> {
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the indexed items, in the order they were given
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().map(|(_, item)| item)
+    }
+
+    fn cell(&self, at: &Coordinates) -> (i64, i64) {
+        (
+            (at.lat / self.cell_size).floor() as i64,
+            (at.long / self.cell_size).floor() as i64,
+        )
+    }
+
+    fn measure(&self, at: &Coordinates, i: usize) -> Neighbour<'_, T> {
+        let (coords, item) = &self.items[i];
+        Neighbour {
+            item,
+            distance: haversine_distance(at, coords),
+        }
+    }
+
+    /// Returns the items within `radius` metres of `at`, nearest first
+    pub fn within(&self, at: &Coordinates, radius: f64) -> Vec<Neighbour<'_, T>> {
+        let lat_span = radius / metres_per_degree();
+        // Meridians converge away from the equator, so a metre is more degrees of longitude
+        let long_span = lat_span / at.lat.to_radians().cos().max(1e-6);
+        let (lat_from, long_from) =
+            self.cell(&Coordinates::new(at.lat - lat_span, at.long - long_span));
+        let (lat_to, long_to) =
+            self.cell(&Coordinates::new(at.lat + lat_span, at.long + long_span));
+
+        let mut found: Vec<Neighbour<'_, T>> = (lat_from..=lat_to)
+            .flat_map(|y| (long_from..=long_to).map(move |x| (y, x)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .map(|&i| self.measure(at, i))
+            .filter(|n| n.distance <= radius)
+            .collect();
+        found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        found
+    }
+
+    /// Returns the `k` items nearest to `at`, nearest first. Searches rings of cells around `at`
+    /// until the items found are nearer than any cell not searched yet. Far from the indexed
+    /// items, where most cells searched would be empty, every item is measured instead
+    pub fn nearest(&self, at: &Coordinates, k: usize) -> Vec<Neighbour<'_, T>> {
+        let mut found: Vec<Neighbour<'_, T>> = Vec::new();
+        let ((low_y, low_x), (high_y, high_x)) = match self.extent {
+            Some(extent) if k > 0 => extent,
+            _ => return found,
+        };
+
+        // Rings past the farthest corner of the occupied cells are empty
+        let (y0, x0) = self.cell(at);
+        let last_ring = [low_y - y0, high_y - y0, low_x - x0, high_x - x0]
+            .iter()
+            .map(|d| d.abs())
+            .max()
+            .unwrap_or(0);
+
+        let mut visited = 0;
+        for ring in 0..=last_ring {
+            if visited > self.cells.len() {
+                return self.nearest_by_scan(at, k);
+            }
+
+            for (dy, dx) in ring_offsets(ring) {
+                visited += 1;
+                if let Some(items) = self.cells.get(&(y0 + dy, x0 + dx)) {
+                    found.extend(items.iter().map(|&i| self.measure(at, i)));
+                }
+            }
+            found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            found.truncate(k);
+
+            // Cells past this ring are at least `ring` cells away, measured at the latitude of
+            // the farthest of them since cells narrow away from the equator
+            let reach = (ring as f64) * self.cell_size;
+            let narrowest = (at.lat.abs() + reach).min(90.0).to_radians().cos();
+            if found.len() == k && found[k - 1].distance <= reach * metres_per_degree() * narrowest
+            {
+                break;
+            }
+        }
+
+        found
+    }
+
+    /// Measures every item, for [`nearest`](SpatialIndex::nearest) queries far from the items
+    fn nearest_by_scan(&self, at: &Coordinates, k: usize) -> Vec<Neighbour<'_, T>> {
+        let mut found: Vec<Neighbour<'_, T>> =
+            (0..self.items.len()).map(|i| self.measure(at, i)).collect();
+        found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        found.truncate(k);
+        found
+    }
+}
+
+impl<T: Located> FromIterator<T> for SpatialIndex<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::traffic::carpark_avail::LotType;
+    use crate::models::traffic::prelude::Agency;
+
+    #[test]
+    fn finds_neighbours() {
+        let origin = Coordinates::new(1.3, 103.8);
+        // Every ~111m north of the origin, then one far away
+        let mut points: Vec<Coordinates> = (1..=10)
+            .map(|i| Coordinates::new(1.3 + 0.001 * i as f64, 103.8))
+            .collect();
+        points.push(Coordinates::new(1.45, 104.0));
+        let index: SpatialIndex<Coordinates> = points.into_iter().collect();
+
+        let within = index.within(&origin, 300.0);
+        assert_eq!(within.len(), 2);
+        assert!(within[0].distance < within[1].distance);
+
+        let nearest = index.nearest(&origin, 3);
+        let lats: Vec<f64> = nearest.iter().map(|n| n.item.lat).collect();
+        assert_eq!(lats, vec![1.301, 1.302, 1.303]);
+        // Only reachable by searching every ring
+        let farthest = index.nearest(&Coordinates::new(1.45, 104.1), 1);
+        assert_eq!(farthest[0].item.long, 104.0);
+        assert_eq!(index.nearest(&origin, 20).len(), 11);
+        assert!(SpatialIndex::<Coordinates>::new(Vec::new())
+            .nearest(&origin, 1)
+            .is_empty());
+
+        // Far outside the indexed area, with latitude and longitude swapped and at (0, 0)
+        let swapped = index.nearest(&Coordinates::new(103.8, 1.3), 1);
+        assert_eq!(swapped[0].item.lat, 1.45);
+        let null_island = index.nearest(&Coordinates::new(0.0, 0.0), 2);
+        let lats: Vec<f64> = null_island.iter().map(|n| n.item.lat).collect();
+        // Past a quarter of the globe east, points further north are nearer
+        assert_eq!(lats, vec![1.31, 1.309]);
+
+        let carpark = |coords| CarPark {
+            carpark_id: "1".to_string(),
+            area: "Marina".to_string(),
+            dev: "Suntec City".to_string(),
+            coords,
+            avail_lots: 10,
+            lot_type: LotType::C,
+            agency: Agency::LTA,
+        };
+        let carparks = SpatialIndex::new(vec![carpark(Some(origin.clone())), carpark(None)]);
+        assert_eq!(carparks.len(), 1);
+    }
+}
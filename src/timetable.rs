@@ -0,0 +1,378 @@
+//! First and last bus times of bus routes, by day of the week and public holiday
+//!
+//! `BusRequests::get_bus_routes` returns the scheduled first and last bus of every service at
+//! every stop, for weekdays, Saturdays and Sundays. The API encodes them as `HHMM`, `-` when the
+//! service does not call on that day. Last buses after midnight are either given as is, eg `0030`,
+//! or past 24 hours, eg `2430`, and belong to the service day before. Public holidays run the
+//! Sunday schedule.
+//!
+//! ## Example
+//! ```rust,no_run
+//! use lta::models::chrono::NaiveDate;
+//! use lta::timetable::{PublicHolidays, Timetable};
+//! use lta::{Bus, BusRequests, Client, LTAClient, LTAResult};
+//!
+//! #[tokio::main]
+//! async fn main() -> LTAResult<()> {
+//!     let api_key = std::env::var("API_KEY").expect("API_KEY not found!");
+//!     let client = LTAClient::with_api_key(api_key)?;
+//!     let routes = Bus::get_all_bus_routes(&client).await?;
+//!     let timetable = Timetable::new(&routes, PublicHolidays::singapore());
+//!
+//!     let date = NaiveDate::from_ymd_opt(2025, 12, 25).unwrap();
+//!     for bus in timetable.last_buses(83139, date) {
+//!         println!("{} {}", bus.service_no, bus.departs);
+//!     }
+//!     Ok(())
+//! }
+//! ```
+use crate::models::bus::prelude::BusRoute;
+use crate::models::bus_enums::Operator;
+use crate::models::chrono::{
+    Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday,
+};
+use crate::models::utils::de::from_str;
+use crate::{LTAError, LTAResult};
+use serde::{Deserialize, Deserializer};
+use std::collections::{HashMap, HashSet};
+
+/// Last buses without a first bus to compare with are past midnight if they are before this hour
+const SERVICE_DAY_START: u32 = 4;
+
+/// Parses a first or last bus time, eg `0530`. Returns `None` for `-`, the encoding of a service
+/// that does not call on that day. Times past 24 hours, eg `2430`, wrap around to the same time
+/// after midnight
+pub fn parse_bus_time(time: &str) -> LTAResult<Option<NaiveTime>> {
+    let time = time.trim();
+    if time == "-" || time.is_empty() {
+        return Ok(None);
+    }
+
+    let invalid = || LTAError::Custom(format!("Invalid bus time: {}", time));
+    let digits = time.replace(':', "");
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let hour: u32 = digits[..2].parse().map_err(|_| invalid())?;
+    let min: u32 = digits[2..].parse().map_err(|_| invalid())?;
+    let hour = if (24..48).contains(&hour) {
+        hour - 24
+    } else {
+        hour
+    };
+    NaiveTime::from_hms_opt(hour, min, 0)
+        .map(Some)
+        .ok_or_else(invalid)
+}
+
+fn de_bus_time<'de, D>(deserializer: D) -> Result<Option<NaiveTime>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let time = Option::<String>::deserialize(deserializer)?;
+    match time {
+        Some(time) => parse_bus_time(&time).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+/// Row of `/BusRoutes`, decoded with [`parse_bus_time`]. The decoding of `lta_models` reads one
+/// digit each of the hour and minute, eg `0530` as 00:03
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawBusRoute {
+    service_no: String,
+    operator: Operator,
+    direction: u32,
+    stop_sequence: u32,
+    #[serde(deserialize_with = "from_str")]
+    bus_stop_code: u32,
+    distance: Option<f64>,
+    #[serde(rename = "WD_FirstBus", deserialize_with = "de_bus_time", default)]
+    wd_first: Option<NaiveTime>,
+    #[serde(rename = "WD_LastBus", deserialize_with = "de_bus_time", default)]
+    wd_last: Option<NaiveTime>,
+    #[serde(rename = "SAT_FirstBus", deserialize_with = "de_bus_time", default)]
+    sat_first: Option<NaiveTime>,
+    #[serde(rename = "SAT_LastBus", deserialize_with = "de_bus_time", default)]
+    sat_last: Option<NaiveTime>,
+    #[serde(rename = "SUN_FirstBus", deserialize_with = "de_bus_time", default)]
+    sun_first: Option<NaiveTime>,
+    #[serde(rename = "SUN_LastBus", deserialize_with = "de_bus_time", default)]
+    sun_last: Option<NaiveTime>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawBusRouteResp {
+    value: Vec<RawBusRoute>,
+}
+
+impl From<RawBusRouteResp> for Vec<BusRoute> {
+    fn from(data: RawBusRouteResp) -> Self {
+        data.value
+            .into_iter()
+            .map(|r| BusRoute {
+                service_no: r.service_no,
+                operator: r.operator,
+                direction: r.direction,
+                stop_seq: r.stop_sequence,
+                bus_stop_code: r.bus_stop_code,
+                dist: r.distance.unwrap_or_default(),
+                wd_first: r.wd_first,
+                wd_last: r.wd_last,
+                sat_first: r.sat_first,
+                sat_last: r.sat_last,
+                sun_first: r.sun_first,
+                sun_last: r.sun_last,
+            })
+            .collect()
+    }
+}
+
+/// Schedule that a service runs on a date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Schedule {
+    Weekday,
+    Saturday,
+
+    /// Sundays and public holidays
+    Sunday,
+}
+
+impl Schedule {
+    pub fn of(date: NaiveDate, holidays: &PublicHolidays) -> Self {
+        if holidays.contains(date) {
+            return Schedule::Sunday;
+        }
+
+        match date.weekday() {
+            Weekday::Sat => Schedule::Saturday,
+            Weekday::Sun => Schedule::Sunday,
+            _ => Schedule::Weekday,
+        }
+    }
+
+    /// Returns the first and last bus of `route` on this day
+    fn times(self, route: &BusRoute) -> (Option<NaiveTime>, Option<NaiveTime>) {
+        match self {
+            Schedule::Weekday => (route.wd_first, route.wd_last),
+            Schedule::Saturday => (route.sat_first, route.sat_last),
+            Schedule::Sunday => (route.sun_first, route.sun_last),
+        }
+    }
+}
+
+/// Dates that run the Sunday schedule, including days in lieu
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PublicHolidays {
+    dates: HashSet<NaiveDate>,
+}
+
+impl PublicHolidays {
+    pub fn new<I: IntoIterator<Item = NaiveDate>>(dates: I) -> Self {
+        PublicHolidays {
+            dates: dates.into_iter().collect(),
+        }
+    }
+
+    /// Singapore public holidays gazetted for 2024 to 2026. Later years have to be added with
+    /// [`insert`](Self::insert), as most holidays follow the lunar or Islamic calendars
+    pub fn singapore() -> Self {
+        const DATES: [(i32, u32, u32); 35] = [
+            (2024, 1, 1),
+            (2024, 2, 10),
+            (2024, 2, 11),
+            (2024, 2, 12),
+            (2024, 3, 29),
+            (2024, 4, 10),
+            (2024, 5, 1),
+            (2024, 5, 22),
+            (2024, 6, 17),
+            (2024, 8, 9),
+            (2024, 10, 31),
+            (2024, 12, 25),
+            (2025, 1, 1),
+            (2025, 1, 29),
+            (2025, 1, 30),
+            (2025, 3, 31),
+            (2025, 4, 18),
+            (2025, 5, 1),
+            (2025, 5, 3),
+            (2025, 5, 12),
+            (2025, 6, 7),
+            (2025, 8, 9),
+            (2025, 10, 20),
+            (2025, 12, 25),
+            (2026, 1, 1),
+            (2026, 2, 17),
+            (2026, 2, 18),
+            (2026, 3, 21),
+            (2026, 4, 3),
+            (2026, 5, 1),
+            (2026, 5, 27),
+            (2026, 6, 1),
+            (2026, 8, 10),
+            (2026, 11, 9),
+            (2026, 12, 25),
+        ];
+
+        Self::new(
+            DATES
+                .iter()
+                .filter_map(|&(y, m, d)| NaiveDate::from_ymd_opt(y, m, d)),
+        )
+    }
+
+    pub fn insert(&mut self, date: NaiveDate) {
+        self.dates.insert(date);
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.dates.contains(&date)
+    }
+}
+
+/// Last bus of a service at a stop
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastBus {
+    pub service_no: String,
+    pub direction: u32,
+
+    /// Local time, on the day after the service day for last buses after midnight
+    pub departs: NaiveDateTime,
+}
+
+/// First and last buses of every route, by stop
+#[derive(Debug, Clone, Default)]
+pub struct Timetable {
+    routes_at: HashMap<u32, Vec<BusRoute>>,
+    holidays: PublicHolidays,
+}
+
+impl Timetable {
+    pub fn new(routes: &[BusRoute], holidays: PublicHolidays) -> Self {
+        let mut routes_at: HashMap<u32, Vec<BusRoute>> = HashMap::new();
+        for route in routes {
+            routes_at
+                .entry(route.bus_stop_code)
+                .or_default()
+                .push(route.clone());
+        }
+
+        Timetable {
+            routes_at,
+            holidays,
+        }
+    }
+
+    pub fn schedule(&self, date: NaiveDate) -> Schedule {
+        Schedule::of(date, &self.holidays)
+    }
+
+    /// Returns the last bus of each service and direction calling at `bus_stop_code` on the
+    /// service day of `date`, ordered by service. Services that do not run that day are left out,
+    /// and loop services calling twice return their later call
+    pub fn last_buses(&self, bus_stop_code: u32, date: NaiveDate) -> Vec<LastBus> {
+        let schedule = self.schedule(date);
+        let mut last: HashMap<(&str, u32), NaiveDateTime> = HashMap::new();
+
+        for route in self.routes_at.get(&bus_stop_code).into_iter().flatten() {
+            let (first, last_time) = match schedule.times(route) {
+                (first, Some(last_time)) => (first, last_time),
+                (_, None) => continue,
+            };
+            let after_midnight = match first {
+                Some(first) => last_time < first,
+                None => last_time.hour() < SERVICE_DAY_START,
+            };
+            let day = if after_midnight {
+                date + Duration::days(1)
+            } else {
+                date
+            };
+
+            let departs = day.and_time(last_time);
+            let key = (route.service_no.as_str(), route.direction);
+            let entry = last.entry(key).or_insert(departs);
+            *entry = (*entry).max(departs);
+        }
+
+        let mut buses: Vec<LastBus> = last
+            .into_iter()
+            .map(|((service_no, direction), departs)| LastBus {
+                service_no: service_no.to_string(),
+                direction,
+                departs,
+            })
+            .collect();
+        buses.sort_by(|a, b| {
+            a.service_no
+                .cmp(&b.service_no)
+                .then(a.direction.cmp(&b.direction))
+        });
+        buses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bus_times() {
+        let time = |h, m| Some(NaiveTime::from_hms_opt(h, m, 0).unwrap());
+        assert_eq!(parse_bus_time("0530").unwrap(), time(5, 30));
+        assert_eq!(parse_bus_time("2359").unwrap(), time(23, 59));
+        assert_eq!(parse_bus_time("2430").unwrap(), time(0, 30));
+        assert_eq!(parse_bus_time("-").unwrap(), None);
+        assert!(parse_bus_time("530").is_err());
+        assert!(parse_bus_time("2570").is_err());
+
+        let json = r#"{"value": [{"ServiceNo": "10", "Operator": "SBST", "Direction": 1,
+            "StopSequence": 1, "BusStopCode": "75009", "Distance": 0, "WD_FirstBus": "0530",
+            "WD_LastBus": "2430", "SAT_FirstBus": "0530", "SAT_LastBus": "2300",
+            "SUN_FirstBus": "-", "SUN_LastBus": "-"}]}"#;
+        let resp: RawBusRouteResp = serde_json::from_str(json).unwrap();
+        let routes: Vec<BusRoute> = resp.into();
+        assert_eq!(routes[0].wd_first, time(5, 30));
+        assert_eq!(routes[0].wd_last, time(0, 30));
+        assert_eq!(routes[0].sun_last, None);
+    }
+
+    #[test]
+    fn finds_last_buses() {
+        let time = |h, m| Some(NaiveTime::from_hms_opt(h, m, 0).unwrap());
+        let route = |service_no: &str, wd_last, sun_last| BusRoute {
+            service_no: service_no.to_string(),
+            operator: Operator::SBST,
+            direction: 1,
+            stop_seq: 1,
+            bus_stop_code: 83139,
+            dist: 0.0,
+            wd_first: time(5, 30),
+            wd_last,
+            sat_first: time(5, 30),
+            sat_last: wd_last,
+            sun_first: time(6, 0),
+            sun_last,
+        };
+        let routes = vec![
+            route("15", time(0, 30), time(23, 0)),
+            route("155", time(23, 15), None),
+        ];
+        let christmas = NaiveDate::from_ymd_opt(2025, 12, 25).unwrap();
+        let timetable = Timetable::new(&routes, PublicHolidays::singapore());
+        assert_eq!(timetable.schedule(christmas), Schedule::Sunday);
+
+        let holiday = timetable.last_buses(83139, christmas);
+        assert_eq!(holiday.len(), 1);
+        assert_eq!(holiday[0].departs, christmas.and_hms_opt(23, 0, 0).unwrap());
+
+        let eve = christmas.pred_opt().unwrap();
+        let weekday = timetable.last_buses(83139, eve);
+        let services: Vec<&str> = weekday.iter().map(|b| b.service_no.as_str()).collect();
+        assert_eq!(services, vec!["15", "155"]);
+        assert_eq!(weekday[0].departs, christmas.and_hms_opt(0, 30, 0).unwrap());
+    }
+}
@@ -0,0 +1,118 @@
+//! Decoding of `/FaultyTrafficLights` as Datamall returns it
+//!
+//! Datamall gives the alarm type as its number, eg `4` for a blackout, and a blank `EndDate`
+//! while the fault is ongoing. The decoding of `lta_models` only reads alarm types by name and
+//! fails on blank dates, so responses are decoded here into the same models instead.
+use crate::models::chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use crate::models::traffic::faulty_traffic_lights::{FaultyTrafficLight, TechnicalAlarmType};
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Returns the alarm type of its number or name, `Unknown` if it is neither
+pub fn parse_alarm_type(value: &Value) -> TechnicalAlarmType {
+    let number = match value {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    };
+
+    match (number, value.as_str()) {
+        (Some(4), _) | (_, Some("Blackout")) => TechnicalAlarmType::Blackout,
+        (Some(13), _) | (_, Some("FlashingYellow")) => TechnicalAlarmType::FlashingYellow,
+        _ => TechnicalAlarmType::Unknown,
+    }
+}
+
+fn de_alarm_type<'de, D>(deserializer: D) -> Result<TechnicalAlarmType, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Value::deserialize(deserializer).map(|value| parse_alarm_type(&value))
+}
+
+/// Blank dates are `None`
+fn de_date_time<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+    if s.trim().is_empty() {
+        return Ok(None);
+    }
+
+    NaiveDateTime::parse_from_str(s.trim(), DATE_FORMAT)
+        .map(|date| Some(Utc.from_utc_datetime(&date)))
+        .map_err(serde::de::Error::custom)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawFaultyTrafficLight {
+    #[serde(rename = "AlarmID")]
+    alarm_id: String,
+    #[serde(rename = "NodeID")]
+    node_id: String,
+    #[serde(rename = "Type", deserialize_with = "de_alarm_type")]
+    alarm_type: TechnicalAlarmType,
+    #[serde(deserialize_with = "de_date_time", default)]
+    start_date: Option<DateTime<Utc>>,
+    #[serde(deserialize_with = "de_date_time", default)]
+    end_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct RawFaultyTrafficLightResp {
+    value: Vec<RawFaultyTrafficLight>,
+}
+
+impl From<RawFaultyTrafficLightResp> for Vec<FaultyTrafficLight> {
+    fn from(data: RawFaultyTrafficLightResp) -> Self {
+        data.value
+            .into_iter()
+            .map(|r| FaultyTrafficLight {
+                alarm_id: r.alarm_id,
+                node_id: r.node_id,
+                technical_alarm_type: r.alarm_type,
+                start_date: r.start_date,
+                end_date: r.end_date,
+                message: r.message,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_alarm_types_by_number() {
+        let json = r#"{"value": [
+            {"AlarmID": "A", "NodeID": "1", "Type": 4, "StartDate": "2014-04-12 01:58:00",
+             "EndDate": "", "Message": "Blackout"},
+            {"AlarmID": "B", "NodeID": "2", "Type": "13", "StartDate": "2014-04-12 01:58:00",
+             "EndDate": "2014-04-12 03:58:00", "Message": "Flashing Yellow"},
+            {"AlarmID": "C", "NodeID": "3", "Type": 99, "StartDate": "", "Message": ""}
+        ]}"#;
+        let resp: RawFaultyTrafficLightResp = serde_json::from_str(json).unwrap();
+        let lights: Vec<FaultyTrafficLight> = resp.into();
+
+        assert_eq!(lights[0].technical_alarm_type, TechnicalAlarmType::Blackout);
+        assert_eq!(lights[0].end_date, None);
+        assert_eq!(
+            lights[0].start_date,
+            Utc.with_ymd_and_hms(2014, 4, 12, 1, 58, 0).single()
+        );
+        assert_eq!(
+            lights[1].technical_alarm_type,
+            TechnicalAlarmType::FlashingYellow
+        );
+        assert!(lights[1].end_date.is_some());
+        assert_eq!(lights[2].technical_alarm_type, TechnicalAlarmType::Unknown);
+        assert_eq!(lights[2].start_date, None);
+    }
+}